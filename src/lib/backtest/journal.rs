@@ -0,0 +1,452 @@
+use crate::lib::backtest::simulator::{Fill, Liquidation};
+use crate::lib::op::operation::trade::TradeOperator;
+use crate::lib::op::ticker_store::Ticker;
+use serde::Serialize;
+use std::fmt::Display;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub enum Side {
+    Long,
+    Short,
+}
+
+impl Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Side::Long => write!(f, "Long"),
+            Side::Short => write!(f, "Short"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub enum EntryKind {
+    Entry,
+    Add,
+    Exit,
+    Liquidation,
+}
+
+///One fill as seen by a position, a fill that flips a position is split into an exit and an entry
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct JournalEntry {
+    pub kind: EntryKind,
+    pub candle: usize,
+    ///None for liquidations
+    pub order_id: Option<usize>,
+    pub price: f32,
+    pub amount: f32,
+    pub fee: f32,
+}
+
+///The lifecycle of a position from the fill that opens it to the one that brings it back to zero.
+/// Amounts are unsigned, profits are in the quote currency.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct JournalPosition {
+    pub id: usize,
+    pub market_index: usize,
+    pub side: Side,
+    pub opened_candle: usize,
+    pub closed_candle: Option<usize>,
+    ///average price of the entry and adds
+    pub entry_price: f32,
+    ///average price of the exits, 0 until the first one
+    pub exit_price: f32,
+    ///what is still open
+    pub amount: f32,
+    pub max_amount: f32,
+    pub exited_amount: f32,
+    ///before fees, a liquidation loses the margin
+    pub realized_pnl: f32,
+    pub fees: f32,
+    ///maximum adverse excursion, the worst unrealized pnl seen while open, 0 or less
+    pub mae: f32,
+    ///maximum favourable excursion, the best unrealized pnl seen while open, 0 or more
+    pub mfe: f32,
+    pub liquidated: bool,
+    pub entries: Vec<JournalEntry>,
+}
+
+impl JournalPosition {
+    fn open(id: usize, fill: &Fill, side: Side, amount: f32, fee: f32) -> JournalPosition {
+        JournalPosition {
+            id,
+            market_index: fill.market_index,
+            side,
+            opened_candle: fill.candle,
+            closed_candle: None,
+            entry_price: fill.price,
+            exit_price: 0.0,
+            amount,
+            max_amount: amount,
+            exited_amount: 0.0,
+            realized_pnl: 0.0,
+            fees: fee,
+            mae: 0.0,
+            mfe: 0.0,
+            liquidated: false,
+            entries: vec![JournalEntry {
+                kind: EntryKind::Entry,
+                candle: fill.candle,
+                order_id: Some(fill.order_id),
+                price: fill.price,
+                amount,
+                fee,
+            }],
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.closed_candle.is_none()
+    }
+
+    pub fn net_pnl(&self) -> f32 {
+        self.realized_pnl - self.fees
+    }
+
+    ///candles between the opening and closing fill, None while open
+    pub fn holding_candles(&self) -> Option<usize> {
+        self.closed_candle
+            .map(|closed_candle| closed_candle - self.opened_candle)
+    }
+
+    fn direction(&self) -> f32 {
+        match self.side {
+            Side::Long => 1.0,
+            Side::Short => -1.0,
+        }
+    }
+
+    fn unrealized_pnl(&self, price: f32) -> f32 {
+        self.amount * (price - self.entry_price) * self.direction()
+    }
+
+    fn add(&mut self, fill: &Fill, amount: f32, fee: f32) {
+        self.entry_price =
+            (self.entry_price * self.amount + fill.price * amount) / (self.amount + amount);
+        self.amount += amount;
+        self.max_amount = self.max_amount.max(self.amount);
+        self.fees += fee;
+        self.entries.push(JournalEntry {
+            kind: EntryKind::Add,
+            candle: fill.candle,
+            order_id: Some(fill.order_id),
+            price: fill.price,
+            amount,
+            fee,
+        });
+    }
+
+    fn exit(&mut self, candle: usize, order_id: Option<usize>, price: f32, amount: f32, fee: f32) {
+        self.exit_price =
+            (self.exit_price * self.exited_amount + price * amount) / (self.exited_amount + amount);
+        self.exited_amount += amount;
+        self.realized_pnl += amount * (price - self.entry_price) * self.direction();
+        self.fees += fee;
+        self.amount -= amount;
+        if self.amount <= 0.0 {
+            self.amount = 0.0;
+            self.closed_candle = Some(candle);
+        }
+        self.entries.push(JournalEntry {
+            kind: EntryKind::Exit,
+            candle,
+            order_id,
+            price,
+            amount,
+            fee,
+        });
+    }
+}
+
+///Groups simulator fills into positions, at most one open position per market
+#[derive(Clone, Debug, Default)]
+pub struct Journal {
+    ///indexed by market index
+    open: Vec<Option<JournalPosition>>,
+    closed: Vec<JournalPosition>,
+    next_id: usize,
+}
+
+impl Journal {
+    pub fn new() -> Journal {
+        Journal::default()
+    }
+
+    pub fn closed_positions(&self) -> &[JournalPosition] {
+        &self.closed
+    }
+
+    pub fn open_positions(&self) -> impl Iterator<Item = &JournalPosition> {
+        self.open.iter().flatten()
+    }
+
+    ///closed positions in the order they closed followed by the open ones
+    pub fn positions(&self) -> Vec<&JournalPosition> {
+        self.closed.iter().chain(self.open_positions()).collect()
+    }
+
+    fn take_open(&mut self, market_index: usize) -> Option<JournalPosition> {
+        if self.open.len() <= market_index {
+            self.open.resize(market_index + 1, None);
+        }
+        self.open[market_index].take()
+    }
+
+    fn store(&mut self, position: JournalPosition) {
+        if position.is_open() {
+            let market_index = position.market_index;
+            self.open[market_index] = Some(position);
+        } else {
+            self.closed.push(position);
+        }
+    }
+
+    pub fn record_fills(&mut self, fills: &[Fill]) {
+        for fill in fills {
+            self.record_fill(fill);
+        }
+    }
+
+    pub fn record_fill(&mut self, fill: &Fill) {
+        if fill.amount <= 0.0 || fill.operator == TradeOperator::Nothing {
+            return;
+        }
+        let side = match fill.operator {
+            TradeOperator::Buy => Side::Long,
+            _ => Side::Short,
+        };
+        let mut remaining = fill.amount;
+        let fee_per_amount = fill.fee / fill.amount;
+        match self.take_open(fill.market_index) {
+            Some(mut position) if position.side == side => {
+                position.add(fill, remaining, fill.fee);
+                remaining = 0.0;
+                self.store(position);
+            }
+            Some(mut position) => {
+                let exited = remaining.min(position.amount);
+                position.exit(
+                    fill.candle,
+                    Some(fill.order_id),
+                    fill.price,
+                    exited,
+                    exited * fee_per_amount,
+                );
+                remaining -= exited;
+                self.store(position);
+            }
+            None => {}
+        }
+        //whatever is left opens a position, possibly flipping the one just closed
+        if remaining > 0.0 {
+            let position = JournalPosition::open(
+                self.next_id,
+                fill,
+                side,
+                remaining,
+                remaining * fee_per_amount,
+            );
+            self.next_id += 1;
+            self.store(position);
+        }
+    }
+
+    ///closes the position at the liquidation price, the lost margin is added to the pnl
+    /// realized by earlier exits instead of the pnl at the liquidation price
+    pub fn record_liquidation(&mut self, liquidation: &Liquidation) {
+        if let Some(mut position) = self.take_open(liquidation.market_index) {
+            let amount = position.amount;
+            let realized_pnl = position.realized_pnl;
+            position.exit(liquidation.candle, None, liquidation.price, amount, 0.0);
+            position.realized_pnl = realized_pnl - liquidation.margin;
+            position.liquidated = true;
+            if let Some(entry) = position.entries.last_mut() {
+                entry.kind = EntryKind::Liquidation;
+            }
+            self.store(position);
+        }
+    }
+
+    ///updates the excursions of the market's open position with the candle's extremes
+    pub fn record_candle(&mut self, market_index: usize, ticker: &Ticker) {
+        if let Some(Some(position)) = self.open.get_mut(market_index) {
+            for price in [ticker.low, ticker.high] {
+                if price.is_nan() {
+                    continue;
+                }
+                let pnl = position.unrealized_pnl(price);
+                position.mae = position.mae.min(pnl);
+                position.mfe = position.mfe.max(pnl);
+            }
+        }
+    }
+
+    ///one row per position without the individual entries
+    pub fn write_csv(&self, writer: impl std::io::Write) -> Result<(), csv::Error> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record([
+            "id",
+            "market_index",
+            "side",
+            "opened_candle",
+            "closed_candle",
+            "holding_candles",
+            "entry_price",
+            "exit_price",
+            "max_amount",
+            "realized_pnl",
+            "fees",
+            "net_pnl",
+            "mae",
+            "mfe",
+            "liquidated",
+        ])?;
+        let optional =
+            |value: Option<usize>| value.map(|value| value.to_string()).unwrap_or_default();
+        for position in self.positions() {
+            writer.write_record(&[
+                position.id.to_string(),
+                position.market_index.to_string(),
+                position.side.to_string(),
+                position.opened_candle.to_string(),
+                optional(position.closed_candle),
+                optional(position.holding_candles()),
+                position.entry_price.to_string(),
+                position.exit_price.to_string(),
+                position.max_amount.to_string(),
+                position.realized_pnl.to_string(),
+                position.fees.to_string(),
+                position.net_pnl().to_string(),
+                position.mae.to_string(),
+                position.mfe.to_string(),
+                position.liquidated.to_string(),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    ///every position with its entries
+    pub fn write_json(&self, writer: impl std::io::Write) -> serde_json::Result<()> {
+        serde_json::to_writer_pretty(writer, &self.positions())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::op::operation::trade::OrderType;
+
+    fn fill(candle: usize, operator: TradeOperator, price: f32, amount: f32, fee: f32) -> Fill {
+        Fill {
+            order_id: candle,
+            candle,
+            market_index: 1,
+            operator,
+            order_type: OrderType::Market,
+            price,
+            amount,
+            fee,
+        }
+    }
+
+    fn range(low: f32, high: f32) -> Ticker {
+        Ticker {
+            low,
+            high,
+            ..Ticker::default()
+        }
+    }
+
+    #[test]
+    fn test_position_lifecycle() {
+        let mut journal = Journal::new();
+        journal.record_fill(&fill(0, TradeOperator::Buy, 100.0, 1.0, 1.0));
+        journal.record_candle(1, &range(95.0, 105.0));
+        journal.record_fill(&fill(1, TradeOperator::Buy, 110.0, 1.0, 1.0));
+        journal.record_candle(1, &range(90.0, 120.0));
+        journal.record_fill(&fill(2, TradeOperator::Sell, 120.0, 1.0, 1.0));
+        //closing more than is held flips to a short with the rest of the fill
+        journal.record_fill(&fill(4, TradeOperator::Sell, 130.0, 2.0, 2.0));
+
+        let closed = &journal.closed_positions()[0];
+        assert_eq!(closed.side, Side::Long);
+        assert_eq!(closed.entry_price, 105.0);
+        assert_eq!(closed.exit_price, 125.0);
+        assert_eq!(closed.max_amount, 2.0);
+        assert_eq!(closed.realized_pnl, 40.0);
+        assert_eq!(closed.fees, 4.0);
+        assert_eq!(closed.net_pnl(), 36.0);
+        assert_eq!(closed.mae, -30.0);
+        assert_eq!(closed.mfe, 30.0);
+        assert_eq!(closed.holding_candles(), Some(4));
+        let kinds: Vec<EntryKind> = closed.entries.iter().map(|entry| entry.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                EntryKind::Entry,
+                EntryKind::Add,
+                EntryKind::Exit,
+                EntryKind::Exit
+            ]
+        );
+
+        let open: Vec<&JournalPosition> = journal.open_positions().collect();
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].side, Side::Short);
+        assert_eq!(open[0].amount, 1.0);
+        assert_eq!(open[0].fees, 1.0);
+        assert_eq!(open[0].holding_candles(), None);
+    }
+
+    #[test]
+    fn test_liquidation_and_export() {
+        let mut journal = Journal::new();
+        journal.record_fill(&fill(0, TradeOperator::Sell, 100.0, 1.0, 0.0));
+        journal.record_liquidation(&Liquidation {
+            candle: 3,
+            market_index: 1,
+            price: 119.0,
+            amount: -1.0,
+            margin: 20.0,
+        });
+        let position = &journal.closed_positions()[0];
+        assert!(position.liquidated);
+        assert_eq!(position.realized_pnl, -20.0);
+        assert_eq!(position.entries[1].kind, EntryKind::Liquidation);
+
+        let mut csv = Vec::new();
+        journal.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("id,market_index,side"));
+        assert_eq!(lines[1], "0,1,Short,0,3,3,100,119,1,-20,0,-20,0,0,true");
+
+        let mut json = Vec::new();
+        journal.write_json(&mut json).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(value[0]["side"], "Short");
+        assert_eq!(value[0]["entries"][1]["kind"], "Liquidation");
+    }
+
+    #[test]
+    fn test_liquidation_after_partial_exit() {
+        let mut journal = Journal::new();
+        journal.record_fill(&fill(0, TradeOperator::Buy, 100.0, 2.0, 0.0));
+        journal.record_fill(&fill(1, TradeOperator::Sell, 110.0, 1.0, 0.0));
+        journal.record_liquidation(&Liquidation {
+            candle: 2,
+            market_index: 1,
+            price: 81.0,
+            amount: 1.0,
+            margin: 20.0,
+        });
+        let position = &journal.closed_positions()[0];
+        assert!(position.liquidated);
+        //10 from the partial exit, minus the margin lost on the rest
+        assert_eq!(position.realized_pnl, -10.0);
+        assert_eq!(position.entries[2].kind, EntryKind::Liquidation);
+    }
+}
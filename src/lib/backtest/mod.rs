@@ -0,0 +1,5 @@
+pub mod journal;
+pub mod replay;
+pub mod risk;
+pub mod runner;
+pub mod simulator;
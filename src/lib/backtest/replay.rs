@@ -0,0 +1,427 @@
+use crate::lib::backtest::runner::{
+    candle_to_ticker, Backtest, BacktestConfig, BacktestReport, StepCandle,
+};
+use crate::lib::op::operation::OperationList;
+use crate::lib::op::ticker_store::{Ticker, TickerStore};
+use barter_data::model::Candle;
+use std::iter::Peekable;
+use std::ops::Range;
+
+type CandleStream = Peekable<Box<dyn Iterator<Item = (i64, Ticker)>>>;
+
+///The candles of every market that opened at timestamp_ms, and the markets whose last candle
+/// this was while other markets keep trading
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayStep {
+    pub timestamp_ms: i64,
+    pub candles: Vec<StepCandle>,
+    pub delisted: Vec<usize>,
+}
+
+///Merges the candle streams of several markets by open time. Market indexes are the order
+/// the markets were added in, every stream has to be sorted by time.
+#[derive(Default)]
+pub struct Replay {
+    symbols: Vec<String>,
+    streams: Vec<CandleStream>,
+    listed: Vec<bool>,
+    delisted: Vec<bool>,
+}
+
+impl Replay {
+    pub fn new() -> Replay {
+        Replay::default()
+    }
+
+    pub fn symbols(&self) -> &[String] {
+        &self.symbols
+    }
+
+    fn add_stream(
+        &mut self,
+        symbol: String,
+        stream: Box<dyn Iterator<Item = (i64, Ticker)>>,
+    ) -> usize {
+        self.symbols.push(symbol);
+        self.streams.push(stream.peekable());
+        self.listed.push(false);
+        self.delisted.push(false);
+        self.symbols.len() - 1
+    }
+
+    ///returns the market index
+    pub fn add_candles<Candles>(&mut self, symbol: impl Into<String>, candles: Candles) -> usize
+    where
+        Candles: IntoIterator<Item = Candle>,
+        Candles::IntoIter: 'static,
+    {
+        let stream = candles.into_iter().map(|candle| {
+            (
+                candle.start_timestamp.timestamp_millis(),
+                candle_to_ticker(&candle),
+            )
+        });
+        self.add_stream(symbol.into(), Box::new(stream))
+    }
+
+    ///replays the ingested tickers, tickers synthesized for gaps are left out
+    pub fn add_ticker_store(
+        &mut self,
+        symbol: impl Into<String>,
+        ticker_store: &TickerStore,
+    ) -> usize {
+        let tickers: Vec<(i64, Ticker)> = ticker_store
+            .tickers()
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| ticker_store.is_valid(*index))
+            .map(|(index, ticker)| {
+                let timestamp =
+                    ticker_store.start_timestamp() + ticker_store.ticker_size() * index as u64;
+                (timestamp as i64, *ticker)
+            })
+            .collect();
+        self.add_stream(symbol.into(), Box::new(tickers.into_iter()))
+    }
+
+    ///Replays every step through a backtest, force-closing the positions of delisted markets.
+    /// The config's ticker size has to match the candles.
+    pub fn run(self, program: &OperationList, config: BacktestConfig) -> BacktestReport {
+        let mut backtest = Backtest::new(config, self.symbols.clone());
+        for step in self {
+            backtest.step(program, &step.candles);
+            for market_index in step.delisted {
+                backtest.delist(market_index);
+            }
+        }
+        backtest.finish()
+    }
+}
+
+///A replay kept in memory so windows of it can be backtested again and again
+#[derive(Clone, Debug, Default)]
+pub struct ReplayHistory {
+    pub symbols: Vec<String>,
+    pub steps: Vec<ReplayStep>,
+}
+
+impl ReplayHistory {
+    pub fn collect(replay: Replay) -> ReplayHistory {
+        ReplayHistory {
+            symbols: replay.symbols.clone(),
+            steps: replay.collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    ///indexes of the steps from start up to but excluding end, in ms
+    pub fn step_range(&self, timestamps_ms: Range<i64>) -> Range<usize> {
+        let index = |timestamp_ms: i64| {
+            self.steps
+                .partition_point(|step| step.timestamp_ms < timestamp_ms)
+        };
+        let start = index(timestamps_ms.start);
+        start..index(timestamps_ms.end).max(start)
+    }
+
+    ///Only the given markets, renumbered in the given order. Steps left without a candle
+    /// or delisting are dropped.
+    pub fn select_markets(&self, markets: &[usize]) -> ReplayHistory {
+        let new_index = |market_index: usize| {
+            markets
+                .iter()
+                .position(|selected| *selected == market_index)
+        };
+        let steps = self
+            .steps
+            .iter()
+            .filter_map(|step| {
+                let candles: Vec<StepCandle> = step
+                    .candles
+                    .iter()
+                    .filter_map(|candle| {
+                        new_index(candle.market_index).map(|market_index| StepCandle {
+                            market_index,
+                            ..*candle
+                        })
+                    })
+                    .collect();
+                let delisted: Vec<usize> = step
+                    .delisted
+                    .iter()
+                    .filter_map(|market_index| new_index(*market_index))
+                    .collect();
+                (!candles.is_empty() || !delisted.is_empty()).then_some(ReplayStep {
+                    timestamp_ms: step.timestamp_ms,
+                    candles,
+                    delisted,
+                })
+            })
+            .collect();
+        ReplayHistory {
+            symbols: markets
+                .iter()
+                .filter_map(|market_index| self.symbols.get(*market_index).cloned())
+                .collect(),
+            steps,
+        }
+    }
+
+    ///Backtests the steps in window after warming up on as many as warmup steps before it.
+    /// Markets delisted before the window are delisted during the warm-up.
+    pub fn run(
+        &self,
+        program: &OperationList,
+        config: &BacktestConfig,
+        window: Range<usize>,
+        warmup: usize,
+    ) -> BacktestReport {
+        let end = window.end.min(self.steps.len());
+        let start = window.start.min(end);
+        let mut backtest = Backtest::new(config.clone(), self.symbols.clone());
+        for step in &self.steps[start.saturating_sub(warmup)..start] {
+            backtest.warm_up(&step.candles);
+            for market_index in &step.delisted {
+                backtest.delist(*market_index);
+            }
+        }
+        for step in &self.steps[start..end] {
+            backtest.step(program, &step.candles);
+            for market_index in &step.delisted {
+                backtest.delist(*market_index);
+            }
+        }
+        backtest.finish()
+    }
+}
+
+impl Iterator for Replay {
+    type Item = ReplayStep;
+
+    fn next(&mut self) -> Option<ReplayStep> {
+        let timestamp_ms = self
+            .streams
+            .iter_mut()
+            .filter_map(|stream| stream.peek().map(|(timestamp_ms, _)| *timestamp_ms))
+            .min()?;
+
+        let mut candles = Vec::new();
+        for (market_index, stream) in self.streams.iter_mut().enumerate() {
+            if let Some((_, ticker)) = stream.next_if(|(timestamp, _)| *timestamp == timestamp_ms) {
+                self.listed[market_index] = true;
+                candles.push(StepCandle {
+                    market_index,
+                    timestamp_ms,
+                    ticker,
+                });
+            }
+        }
+
+        //a stream running out is only a delisting while other markets go on
+        let mut delisted = Vec::new();
+        let trading = self
+            .streams
+            .iter_mut()
+            .any(|stream| stream.peek().is_some());
+        for (market_index, stream) in self.streams.iter_mut().enumerate() {
+            if trading
+                && self.listed[market_index]
+                && !self.delisted[market_index]
+                && stream.peek().is_none()
+            {
+                self.delisted[market_index] = true;
+                delisted.push(market_index);
+            }
+        }
+
+        Some(ReplayStep {
+            timestamp_ms,
+            candles,
+            delisted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::backtest::simulator::{CancelReason, SimulatorConfig};
+    use crate::lib::op::environment::Env;
+    use crate::lib::op::operand::Operand;
+    use crate::lib::op::operation::trade::*;
+    use crate::lib::op::operation::Operation;
+    use crate::lib::op::terminal_type::TerminalType;
+    use chrono::{TimeZone, Utc};
+
+    fn candles(first_minute: i64, closes: &[f64]) -> Vec<Candle> {
+        closes
+            .iter()
+            .enumerate()
+            .map(|(index, close)| {
+                let start = (first_minute + index as i64) * 60_000;
+                Candle {
+                    start_timestamp: Utc.timestamp_millis(start),
+                    end_timestamp: Utc.timestamp_millis(start + 59_999),
+                    open: *close,
+                    high: *close,
+                    low: *close,
+                    close: *close,
+                    volume: 1.0,
+                    trade_count: 1,
+                }
+            })
+            .collect()
+    }
+
+    fn buy(market_index: usize) -> OperationList {
+        vec![Operation::Trade((
+            TradeOperator::Buy,
+            Operand::Terminal(TerminalType::MarketIndex(market_index)),
+            Operand::Terminal(TerminalType::Number(0.0)),
+            Operand::Terminal(TerminalType::Number(1.0)),
+            OrderType::Market,
+            TimeInForce::GoodTilCancelled,
+            Operand::None,
+            TradeLeverage::X1,
+        ))]
+    }
+
+    fn config() -> BacktestConfig {
+        BacktestConfig {
+            simulator: SimulatorConfig {
+                taker_fee: 0.0,
+                maker_fee: 0.0,
+                ..SimulatorConfig::default()
+            },
+            initial_cash: 1000.0,
+            ..BacktestConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_markets_are_listed_as_they_start() {
+        let mut replay = Replay::new();
+        replay.add_candles("btcusdt", candles(0, &[1.0, 2.0, 3.0, 4.0]));
+        replay.add_candles("1inchusdt", candles(2, &[10.0, 20.0]));
+        let mut backtest = Backtest::new(config(), replay.symbols().to_vec());
+        let mut listed = Vec::new();
+        for step in replay {
+            assert!(step.delisted.is_empty());
+            backtest.step(&Vec::new(), &step.candles);
+            listed.push((step.timestamp_ms, backtest.env().get_market_index_list()));
+        }
+        assert_eq!(
+            listed,
+            vec![
+                (0, vec![0.0]),
+                (60_000, vec![0.0]),
+                (120_000, vec![0.0, 1.0]),
+                (180_000, vec![0.0, 1.0]),
+            ]
+        );
+        let env = backtest.env();
+        assert_eq!(env.get_market_listing_timestamp_ms(1), 120_000);
+        assert_eq!(env.get_market_data(1, 0, 240_000).close, vec![10.0, 20.0]);
+    }
+
+    #[test]
+    fn test_ticker_store_gaps_are_skipped() {
+        let ticker = |close: f32| Ticker {
+            open: close,
+            high: close,
+            low: close,
+            close,
+            ..Ticker::default()
+        };
+        let mut ticker_store = TickerStore::new(60_000, 60_000);
+        ticker_store.insert_ticker(60_000, ticker(1.0)).unwrap();
+        ticker_store.insert_ticker(240_000, ticker(4.0)).unwrap();
+        let mut replay = Replay::new();
+        replay.add_ticker_store("btcusdt", &ticker_store);
+        let steps: Vec<(i64, f32)> = replay
+            .map(|step| (step.timestamp_ms, step.candles[0].ticker.close))
+            .collect();
+        assert_eq!(steps, vec![(60_000, 1.0), (240_000, 4.0)]);
+    }
+
+    #[test]
+    fn test_delisting_force_closes() {
+        let mut replay = Replay::new();
+        replay.add_candles("btcusdt", candles(0, &[100.0, 110.0, 120.0]));
+        replay.add_candles("ethusdt", candles(0, &[1.0, 1.0, 1.0, 1.0, 1.0]));
+        let report = replay.run(&buy(0), config());
+
+        //bought at 110 and 120, the order after the last candle is cancelled
+        // and the position sold at the last close
+        let position = &report.journal.closed_positions()[0];
+        assert_eq!(position.max_amount, 2.0);
+        assert_eq!(position.exit_price, 120.0);
+        assert_eq!(position.realized_pnl, 10.0);
+        assert_eq!(report.fills.len(), 3);
+        assert_eq!(report.final_equity(), Some(1010.0));
+        assert_eq!(report.equity_curve.len(), 5);
+
+        //markets that end together are not delisted
+        let mut replay = Replay::new();
+        replay.add_candles("btcusdt", candles(0, &[1.0, 2.0]));
+        replay.add_candles("ethusdt", candles(1, &[1.0]));
+        let delisted: Vec<Vec<usize>> = replay.map(|step| step.delisted).collect();
+        assert_eq!(delisted, vec![Vec::<usize>::new(), vec![]]);
+    }
+
+    #[test]
+    fn test_history() {
+        assert!(ReplayHistory::default().is_empty());
+        let mut replay = Replay::new();
+        replay.add_candles("btcusdt", candles(0, &[100.0, 110.0, 120.0]));
+        replay.add_candles("ethusdt", candles(1, &[1.0, 1.0, 1.0]));
+        let history = ReplayHistory::collect(replay);
+        assert_eq!(history.symbols, vec!["btcusdt", "ethusdt"]);
+        assert_eq!(history.len(), 4);
+        assert_eq!(history.step_range(60_000..180_000), 1..3);
+        assert_eq!(history.step_range(240_000..300_000), 4..4);
+
+        //the buy of the window's first step fills on btc's last candle,
+        // whose delisting sells it again
+        let report = history.run(&buy(0), &config(), 1..3, 0);
+        assert_eq!(report.equity_curve.len(), 2);
+        let operators: Vec<TradeOperator> = report.fills.iter().map(|fill| fill.operator).collect();
+        assert_eq!(operators, vec![TradeOperator::Buy, TradeOperator::Sell]);
+        assert_eq!(history.select_markets(&[1]).len(), 3);
+    }
+
+    #[test]
+    fn test_force_close_cancels_orders() {
+        let mut backtest = Backtest::new(config(), vec!["btcusdt".to_string()]);
+        let step = |timestamp_ms| {
+            [StepCandle {
+                market_index: 0,
+                timestamp_ms,
+                ticker: Ticker {
+                    open: 10.0,
+                    high: 10.0,
+                    low: 10.0,
+                    close: 10.0,
+                    ..Ticker::default()
+                },
+            }]
+        };
+        backtest.step(&buy(0), &step(0));
+        backtest.step(&buy(0), &step(60_000));
+        let fill = backtest.delist(0).unwrap();
+        assert_eq!(fill.operator, TradeOperator::Sell);
+        assert_eq!(fill.amount, 1.0);
+        let simulator = backtest.simulator();
+        assert_eq!(simulator.position(0), 0.0);
+        assert!(simulator.open_orders().is_empty());
+        assert_eq!(simulator.cancelled()[0].1, CancelReason::Delisted);
+        assert!(backtest.env().get_market_index_list().is_empty());
+    }
+}
@@ -0,0 +1,427 @@
+use crate::lib::op::operation::trade::*;
+use std::fmt::Display;
+
+///Limits and sizing applied to the trades a program requests. Limits only ever shrink the part
+/// of a trade that grows a position, trades that reduce a position always pass.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RiskRule {
+    ///rejects zero, negative or NaN amounts, checked before any configured rule
+    ValidAmount,
+    ///largest position value as a fraction of equity
+    MaxPositionFraction(f32),
+    ///largest position value per market in the quote currency
+    MaxMarketExposure(f32),
+    ///Sizes opening trades to the given fraction of the Kelly criterion,
+    /// a non-positive edge rejects them
+    KellySizing {
+        win_rate: f32,
+        ///average win divided by average loss
+        payoff_ratio: f32,
+        fraction: f32,
+    },
+    ///sizes opening trades so the position's volatility is target times equity
+    VolatilityTarget(f32),
+    ///rejects opening trades once equity fell this fraction below the day's starting equity
+    DailyLossLimit(f32),
+    ///rejects trades that open a position in another market once this many are open
+    MaxOpenPositions(usize),
+}
+
+impl Display for RiskRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RiskRule::ValidAmount => write!(f, "ValidAmount"),
+            RiskRule::MaxPositionFraction(fraction) => {
+                write!(f, "MaxPositionFraction({})", fraction)
+            }
+            RiskRule::MaxMarketExposure(value) => write!(f, "MaxMarketExposure({})", value),
+            RiskRule::KellySizing {
+                win_rate,
+                payoff_ratio,
+                fraction,
+            } => write!(
+                f,
+                "KellySizing({}, {}, {})",
+                win_rate, payoff_ratio, fraction
+            ),
+            RiskRule::VolatilityTarget(target) => write!(f, "VolatilityTarget({})", target),
+            RiskRule::DailyLossLimit(fraction) => write!(f, "DailyLossLimit({})", fraction),
+            RiskRule::MaxOpenPositions(count) => write!(f, "MaxOpenPositions({})", count),
+        }
+    }
+}
+
+///The portfolio a trade list is checked against, vectors are indexed by market index
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RiskState {
+    pub equity: f32,
+    ///equity at the start of the current trading day
+    pub day_start_equity: f32,
+    ///signed amount held per market
+    pub positions: Vec<f32>,
+    ///latest price per market
+    pub prices: Vec<f32>,
+    ///standard deviation of recent per-candle returns per market
+    pub volatility: Vec<f32>,
+}
+
+impl RiskState {
+    fn position(&self, market_index: usize) -> f32 {
+        self.positions.get(market_index).copied().unwrap_or(0.0)
+    }
+
+    ///the price a trade is expected to fill at, the market price for market orders
+    fn reference_price(&self, trade: &Trade) -> f32 {
+        let market_price = self.prices.get(trade.index).copied().unwrap_or(f32::NAN);
+        let price = match trade.order_type {
+            OrderType::Market => market_price,
+            OrderType::StopMarket => trade.stop_price,
+            _ => trade.price,
+        };
+        if price.is_finite() && price > 0.0 {
+            price
+        } else {
+            market_price
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RiskAction {
+    Resized { from: f32, to: f32 },
+    Rejected,
+}
+
+///What a rule did to a trade, trade is its index in the submitted trade list
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RiskEvent {
+    pub trade: usize,
+    pub rule: RiskRule,
+    pub action: RiskAction,
+}
+
+impl Display for RiskEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.action {
+            RiskAction::Resized { from, to } => write!(
+                f,
+                "trade {} resized from {} to {} by {}",
+                self.trade, from, to, self.rule
+            ),
+            RiskAction::Rejected => write!(f, "trade {} rejected by {}", self.trade, self.rule),
+        }
+    }
+}
+
+///Post-processes trade lists with rules applied in order, so sizing rules should come before limits.
+/// Trades in one list are checked against the positions the earlier ones would leave.
+#[derive(Clone, Debug, Default)]
+pub struct RiskManager {
+    pub rules: Vec<RiskRule>,
+    events: Vec<RiskEvent>,
+}
+
+impl RiskManager {
+    pub fn new(rules: Vec<RiskRule>) -> RiskManager {
+        RiskManager {
+            rules,
+            events: Vec::new(),
+        }
+    }
+
+    ///every modification or rejection since the manager was created
+    pub fn events(&self) -> &[RiskEvent] {
+        &self.events
+    }
+
+    ///the accepted trades, possibly resized, rejected trades are left out
+    pub fn apply(&mut self, trade_list: &TradeList, state: &RiskState) -> TradeList {
+        let mut positions = state.positions.clone();
+        let mut accepted = Vec::new();
+        for (trade_index, trade) in trade_list.iter().enumerate() {
+            if trade.operator == TradeOperator::Nothing {
+                accepted.push(trade.clone());
+                continue;
+            }
+            let projected = RiskState {
+                positions: positions.clone(),
+                ..state.clone()
+            };
+            if let Some(trade) = self.check(trade_index, trade, &projected) {
+                let index = trade.index;
+                if positions.len() <= index {
+                    positions.resize(index + 1, 0.0);
+                }
+                positions[index] += signed_amount(&trade);
+                accepted.push(trade);
+            }
+        }
+        accepted
+    }
+
+    fn check(&mut self, trade_index: usize, trade: &Trade, state: &RiskState) -> Option<Trade> {
+        let mut trade = trade.clone();
+        let rules = std::iter::once(RiskRule::ValidAmount).chain(self.rules.iter().copied());
+        for rule in rules.collect::<Vec<RiskRule>>() {
+            let amount = apply_rule(rule, &trade, state);
+            if amount == trade.amount {
+                continue;
+            }
+            if amount > 0.0 {
+                self.events.push(RiskEvent {
+                    trade: trade_index,
+                    rule,
+                    action: RiskAction::Resized {
+                        from: trade.amount,
+                        to: amount,
+                    },
+                });
+                trade.amount = amount;
+            } else {
+                self.events.push(RiskEvent {
+                    trade: trade_index,
+                    rule,
+                    action: RiskAction::Rejected,
+                });
+                return None;
+            }
+        }
+        Some(trade)
+    }
+}
+
+fn signed_amount(trade: &Trade) -> f32 {
+    match trade.operator {
+        TradeOperator::Buy => trade.amount,
+        TradeOperator::Sell => -trade.amount,
+        TradeOperator::Nothing => 0.0,
+    }
+}
+
+///the part of a trade that closes an opposite position
+fn closing_amount(trade: &Trade, position: f32) -> f32 {
+    match trade.operator {
+        TradeOperator::Buy => (-position).max(0.0),
+        TradeOperator::Sell => position.max(0.0),
+        TradeOperator::Nothing => 0.0,
+    }
+}
+
+///the largest amount that leaves the position at most limit in size
+fn limit_amount(trade: &Trade, position: f32, limit: f32) -> f32 {
+    let closing = closing_amount(trade, position);
+    let open = if closing > 0.0 { 0.0 } else { position.abs() };
+    let allowed = closing + (limit - open).max(0.0);
+    trade.amount.min(allowed)
+}
+
+///Sizes the opening part of a trade so the position reaches size, keeping the closing part.
+/// A trade that only closes is left untouched, one adding to a position of size or more is rejected.
+fn sized_amount(trade: &Trade, position: f32, size: f32) -> f32 {
+    let closing = closing_amount(trade, position);
+    if trade.amount <= closing {
+        return trade.amount;
+    }
+    let open = if closing > 0.0 { 0.0 } else { position.abs() };
+    closing + (size - open).max(0.0)
+}
+
+///the amount a rule leaves a trade with, 0 or less rejects it
+fn apply_rule(rule: RiskRule, trade: &Trade, state: &RiskState) -> f32 {
+    let position = state.position(trade.index);
+    let price = state.reference_price(trade);
+    let opens = trade.amount > closing_amount(trade, position);
+    match rule {
+        RiskRule::ValidAmount if !trade.amount.is_finite() || trade.amount <= 0.0 => 0.0,
+        RiskRule::ValidAmount => trade.amount,
+        //without a price nothing can be valued, only reducing is allowed
+        _ if !price.is_finite() || price <= 0.0 => {
+            trade.amount.min(closing_amount(trade, position))
+        }
+        RiskRule::MaxPositionFraction(fraction) => {
+            limit_amount(trade, position, fraction * state.equity.max(0.0) / price)
+        }
+        RiskRule::MaxMarketExposure(value) => limit_amount(trade, position, value / price),
+        RiskRule::KellySizing {
+            win_rate,
+            payoff_ratio,
+            fraction,
+        } => {
+            let kelly = win_rate - (1.0 - win_rate) / payoff_ratio;
+            if !opens || kelly.is_nan() {
+                return trade.amount;
+            }
+            sized_amount(
+                trade,
+                position,
+                fraction * kelly * state.equity.max(0.0) / price,
+            )
+        }
+        RiskRule::VolatilityTarget(target) => {
+            let volatility = state.volatility.get(trade.index).copied().unwrap_or(0.0);
+            if !opens || !volatility.is_finite() || volatility <= 0.0 {
+                return trade.amount;
+            }
+            sized_amount(
+                trade,
+                position,
+                target * state.equity.max(0.0) / (price * volatility),
+            )
+        }
+        RiskRule::DailyLossLimit(fraction) => {
+            if state.equity < state.day_start_equity * (1.0 - fraction) {
+                trade.amount.min(closing_amount(trade, position))
+            } else {
+                trade.amount
+            }
+        }
+        RiskRule::MaxOpenPositions(count) => {
+            let open_positions = state
+                .positions
+                .iter()
+                .filter(|position| **position != 0.0)
+                .count();
+            if position == 0.0 && open_positions >= count {
+                0.0
+            } else {
+                trade.amount
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> RiskState {
+        RiskState {
+            equity: 1000.0,
+            day_start_equity: 1000.0,
+            positions: vec![0.0, 2.0, 0.0],
+            prices: vec![10.0, 100.0, 50.0],
+            volatility: vec![0.01, 0.02, 0.05],
+        }
+    }
+
+    fn buy(index: usize, amount: f32) -> Trade {
+        Trade::market(TradeOperator::Buy, index, 0.0, amount)
+    }
+
+    fn sell(index: usize, amount: f32) -> Trade {
+        Trade::market(TradeOperator::Sell, index, 0.0, amount)
+    }
+
+    #[test]
+    fn test_position_limits() {
+        let mut manager = RiskManager::new(vec![
+            RiskRule::MaxPositionFraction(0.5),
+            RiskRule::MaxMarketExposure(300.0),
+        ]);
+        let trades = manager.apply(
+            &vec![buy(1, 10.0), buy(1, 1.0), sell(1, 8.0), buy(2, -1.0)],
+            &state(),
+        );
+        //5 units is half the equity, with 2 held the fraction limit cuts it to 3
+        // and the exposure limit to 1
+        assert_eq!(trades[0].amount, 1.0);
+        //the first buy already used the whole exposure
+        assert_eq!(trades.len(), 2);
+        //selling closes 3 and may open a short of 3
+        assert_eq!(trades[1].amount, 6.0);
+        assert_eq!(
+            manager.events(),
+            &[
+                RiskEvent {
+                    trade: 0,
+                    rule: RiskRule::MaxPositionFraction(0.5),
+                    action: RiskAction::Resized {
+                        from: 10.0,
+                        to: 3.0
+                    },
+                },
+                RiskEvent {
+                    trade: 0,
+                    rule: RiskRule::MaxMarketExposure(300.0),
+                    action: RiskAction::Resized { from: 3.0, to: 1.0 },
+                },
+                RiskEvent {
+                    trade: 1,
+                    rule: RiskRule::MaxMarketExposure(300.0),
+                    action: RiskAction::Rejected,
+                },
+                RiskEvent {
+                    trade: 2,
+                    rule: RiskRule::MaxMarketExposure(300.0),
+                    action: RiskAction::Resized { from: 8.0, to: 6.0 },
+                },
+                RiskEvent {
+                    trade: 3,
+                    rule: RiskRule::ValidAmount,
+                    action: RiskAction::Rejected,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sizing() {
+        let kelly = RiskRule::KellySizing {
+            win_rate: 0.6,
+            payoff_ratio: 1.0,
+            fraction: 0.5,
+        };
+        let mut manager = RiskManager::new(vec![kelly]);
+        //kelly is 0.2, half of it is 10% of equity
+        let trades = manager.apply(&vec![buy(0, 1.0)], &state());
+        assert!((trades[0].amount - 10.0).abs() < 1e-5);
+        //the 2 held are worth 20% of equity, more than the kelly size
+        assert!(manager.apply(&vec![buy(1, 1.0)], &state()).is_empty());
+        //the second buy finds the position the first one leaves
+        assert_eq!(
+            manager
+                .apply(&vec![buy(0, 1.0), buy(0, 1.0)], &state())
+                .len(),
+            1
+        );
+        //closing trades keep their size
+        assert_eq!(manager.apply(&vec![sell(1, 2.0)], &state())[0].amount, 2.0);
+
+        let mut manager = RiskManager::new(vec![RiskRule::KellySizing {
+            win_rate: 0.4,
+            payoff_ratio: 1.0,
+            fraction: 1.0,
+        }]);
+        assert!(manager.apply(&vec![buy(0, 1.0)], &state()).is_empty());
+
+        let mut manager = RiskManager::new(vec![RiskRule::VolatilityTarget(0.01)]);
+        //10 of equity at risk per candle at 5% volatility is 200 worth of the market
+        let trades = manager.apply(&vec![buy(2, 1.0)], &state());
+        assert!((trades[0].amount - 4.0).abs() < 1e-5);
+        //5 units at 2% volatility hit the target, 2 of them are already held
+        let trades = manager.apply(&vec![buy(1, 1.0)], &state());
+        assert!((trades[0].amount - 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_daily_loss_limit_and_open_positions() {
+        let mut manager = RiskManager::new(vec![RiskRule::DailyLossLimit(0.05)]);
+        let losing = RiskState {
+            equity: 900.0,
+            ..state()
+        };
+        let trades = manager.apply(&vec![buy(0, 1.0), sell(1, 3.0)], &losing);
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].amount, 2.0);
+        assert_eq!(manager.events()[0].action, RiskAction::Rejected);
+
+        let mut manager = RiskManager::new(vec![RiskRule::MaxOpenPositions(2)]);
+        let trades = manager.apply(&vec![buy(0, 1.0), buy(2, 1.0), buy(1, 1.0)], &state());
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[1].index, 1);
+        assert_eq!(
+            manager.events()[0].to_string(),
+            "trade 1 rejected by MaxOpenPositions(2)"
+        );
+    }
+}
@@ -0,0 +1,782 @@
+use crate::lib::backtest::journal::Journal;
+use crate::lib::backtest::risk::{RiskEvent, RiskManager, RiskRule, RiskState};
+use crate::lib::backtest::simulator::{Fill, Liquidation, Simulator, SimulatorConfig};
+use crate::lib::op::environment::Env;
+use crate::lib::op::operation::market_data::{MarketData, OrderBook};
+use crate::lib::op::operation::number::{ListAlignment, NumericPolicy};
+use crate::lib::op::operation::trade::TradeList;
+use crate::lib::op::operation::OperationList;
+use crate::lib::op::order_book_store::OrderBookStore;
+use crate::lib::op::ticker_store::{Ticker, TickerStore, TickerStoreError};
+use barter::data::handler::{Continuation, Continuer, MarketGenerator};
+use barter::data::MarketEvent;
+use barter_data::model::{Candle, MarketData as BarterMarketData};
+
+const DAY_MS: i64 = 86_400_000;
+
+///Env backed by the candles replayed so far. Market indexes are the positions of the symbols,
+/// and only candles that have closed by the current timestamp are visible to a program.
+pub struct BacktestEnv {
+    symbols: Vec<String>,
+    ///None until the market's first candle arrives
+    ticker_stores: Vec<Option<TickerStore>>,
+    ///historical books per market, markets without one have an empty book
+    order_book_stores: Vec<Option<OrderBookStore>>,
+    delisted: Vec<bool>,
+    ticker_size_ms: u64,
+    current_timestamp_ms: i64,
+    ///latest close per market
+    prices: Vec<f32>,
+    ///value of the position held per market
+    market_values: Vec<f32>,
+    portfolio_value: f32,
+    pub list_alignment: ListAlignment,
+    pub numeric_policy: NumericPolicy,
+}
+
+impl BacktestEnv {
+    pub fn new(symbols: Vec<String>, ticker_size_ms: u64) -> BacktestEnv {
+        let market_count = symbols.len();
+        BacktestEnv {
+            symbols,
+            ticker_stores: (0..market_count).map(|_| None).collect(),
+            order_book_stores: (0..market_count).map(|_| None).collect(),
+            delisted: vec![false; market_count],
+            ticker_size_ms,
+            current_timestamp_ms: 0,
+            prices: vec![f32::NAN; market_count],
+            market_values: vec![0.0; market_count],
+            portfolio_value: 0.0,
+            list_alignment: ListAlignment::Shortest,
+            numeric_policy: NumericPolicy::Protected,
+        }
+    }
+
+    pub fn symbols(&self) -> &[String] {
+        &self.symbols
+    }
+
+    ///case insensitive
+    pub fn market_index(&self, symbol: &str) -> Option<usize> {
+        self.symbols
+            .iter()
+            .position(|market_symbol| market_symbol.eq_ignore_ascii_case(symbol))
+    }
+
+    pub fn ticker_store(&self, market_index: usize) -> Option<&TickerStore> {
+        self.ticker_stores.get(market_index)?.as_ref()
+    }
+
+    pub fn set_order_book_store(&mut self, market_index: usize, order_book_store: OrderBookStore) {
+        if let Some(slot) = self.order_book_stores.get_mut(market_index) {
+            *slot = Some(order_book_store);
+        }
+    }
+
+    ///whether the market had its first candle and hasn't been delisted
+    pub fn is_listed(&self, market_index: usize) -> bool {
+        self.ticker_store(market_index).is_some() && !self.delisted[market_index]
+    }
+
+    ///hides the market from `get_market_index_list`, its candles stay readable
+    pub fn delist(&mut self, market_index: usize) {
+        if let Some(delisted) = self.delisted.get_mut(market_index) {
+            *delisted = true;
+        }
+    }
+
+    pub fn price(&self, market_index: usize) -> f32 {
+        self.prices.get(market_index).copied().unwrap_or(f32::NAN)
+    }
+
+    pub fn set_current_timestamp_ms(&mut self, timestamp_ms: i64) {
+        self.current_timestamp_ms = timestamp_ms;
+    }
+
+    ///stores a candle that opened at timestamp_ms and makes its close the market price
+    pub fn record_candle(
+        &mut self,
+        market_index: usize,
+        timestamp_ms: i64,
+        ticker: Ticker,
+    ) -> Result<(), TickerStoreError> {
+        let timestamp = timestamp_ms.max(0) as u64;
+        let ticker_size_ms = self.ticker_size_ms;
+        let ticker_store = self.ticker_stores[market_index]
+            .get_or_insert_with(|| TickerStore::new(ticker_size_ms, timestamp));
+        ticker_store.insert_ticker(timestamp, ticker)?;
+        if !ticker.close.is_nan() {
+            self.prices[market_index] = ticker.close;
+        }
+        Ok(())
+    }
+
+    pub fn set_portfolio(&mut self, portfolio_value: f32, market_values: Vec<f32>) {
+        self.portfolio_value = portfolio_value;
+        self.market_values = market_values;
+    }
+
+    ///standard deviation of the close to close returns of the last window candles
+    pub fn volatility(&self, market_index: usize, window: usize) -> f32 {
+        let tickers = match self.ticker_store(market_index) {
+            Some(ticker_store) => ticker_store.tickers(),
+            None => return f32::NAN,
+        };
+        let start = tickers.len().saturating_sub(window + 1);
+        let returns: Vec<f32> = tickers[start..]
+            .windows(2)
+            .map(|pair| pair[1].close / pair[0].close - 1.0)
+            .filter(|value| value.is_finite())
+            .collect();
+        if returns.len() < 2 {
+            return f32::NAN;
+        }
+        let mean = returns.iter().sum::<f32>() / returns.len() as f32;
+        let variance = returns
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f32>()
+            / (returns.len() - 1) as f32;
+        variance.sqrt()
+    }
+
+    fn market_index_by_prefix(&self, prefix: &str) -> usize {
+        self.symbols
+            .iter()
+            .position(|symbol| symbol.to_lowercase().starts_with(prefix))
+            .unwrap_or(0)
+    }
+}
+
+impl Env for BacktestEnv {
+    fn get_market_index_list(&self) -> Vec<f32> {
+        (0..self.symbols.len())
+            .filter(|market_index| self.is_listed(*market_index))
+            .map(|market_index| market_index as f32)
+            .collect()
+    }
+
+    fn get_market_price(&self, index: usize) -> f32 {
+        let price = self.price(index);
+        if price.is_nan() {
+            0.0
+        } else {
+            price
+        }
+    }
+
+    fn get_market_portfolio_value(&self, index: usize) -> f32 {
+        self.market_values.get(index).copied().unwrap_or(0.0)
+    }
+
+    fn get_overall_portfolio_value(&self) -> f32 {
+        self.portfolio_value
+    }
+
+    fn get_usdt_market_index(&self) -> usize {
+        self.market_index_by_prefix("usdt")
+    }
+
+    fn get_btc_market_index(&self) -> usize {
+        self.market_index_by_prefix("btc")
+    }
+
+    fn get_eth_market_index(&self) -> usize {
+        self.market_index_by_prefix("eth")
+    }
+
+    fn get_list_alignment(&self) -> ListAlignment {
+        self.list_alignment
+    }
+
+    fn get_numeric_policy(&self) -> NumericPolicy {
+        self.numeric_policy
+    }
+
+    fn get_current_timestamp_ms(&self) -> i64 {
+        self.current_timestamp_ms
+    }
+
+    fn get_market_listing_timestamp_ms(&self, market_index: usize) -> i64 {
+        self.ticker_store(market_index)
+            .map(|ticker_store| ticker_store.start_timestamp() as i64)
+            .unwrap_or(0)
+    }
+
+    ///A negative duration looks back from timestamp_start.
+    /// Candles that haven't closed by the current timestamp are left out.
+    fn get_market_data(
+        &self,
+        market_index: usize,
+        timestamp_start: i64,
+        duration: i64,
+    ) -> MarketData {
+        let ticker_store = match self.ticker_store(market_index) {
+            Some(ticker_store) => ticker_store,
+            None => return MarketData::default(),
+        };
+        let start = ticker_store.start_timestamp() as i64;
+        let size = ticker_store.ticker_size().max(1) as i64;
+        let from = timestamp_start.min(timestamp_start.saturating_add(duration));
+        let to = timestamp_start.max(timestamp_start.saturating_add(duration));
+        //index of the first candle opening at or after timestamp
+        let index_at = |timestamp: i64| {
+            let offset = timestamp.saturating_sub(start).max(0);
+            (offset / size + (offset % size != 0) as i64) as usize
+        };
+        let closed = (self.current_timestamp_ms.saturating_sub(start).max(0) / size) as usize;
+        let tickers = ticker_store.tickers();
+        let end = index_at(to).min(closed).min(tickers.len());
+        let first = index_at(from).min(end);
+
+        let mut market_data = MarketData::default();
+        for ticker in &tickers[first..end] {
+            market_data.open.push(ticker.open);
+            market_data.high.push(ticker.high);
+            market_data.low.push(ticker.low);
+            market_data.close.push(ticker.close);
+            market_data.volume.push(ticker.volume);
+            market_data.trade_count.push(ticker.trade_count);
+        }
+        market_data
+    }
+
+    ///The latest snapshot at or before timestamp, capped at the current timestamp.
+    /// Empty when the market has no order book store or no snapshot yet.
+    fn get_order_book(&self, market_index: usize, timestamp: i64, depth: usize) -> OrderBook {
+        let timestamp = timestamp.min(self.current_timestamp_ms);
+        if timestamp < 0 {
+            return OrderBook::default();
+        }
+        self.order_book_stores
+            .get(market_index)
+            .and_then(Option::as_ref)
+            .and_then(|order_book_store| order_book_store.get_snapshot(timestamp as u64, depth))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BacktestConfig {
+    pub simulator: SimulatorConfig,
+    pub initial_cash: f32,
+    pub risk_rules: Vec<RiskRule>,
+    pub ticker_size_ms: u64,
+    ///candles the volatility handed to the risk rules is measured over
+    pub volatility_window: usize,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> BacktestConfig {
+        BacktestConfig {
+            simulator: SimulatorConfig::default(),
+            initial_cash: 10000.0,
+            risk_rules: Vec::new(),
+            ticker_size_ms: 60_000,
+            volatility_window: 20,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EquityPoint {
+    ///when the candles of the step closed
+    pub timestamp_ms: i64,
+    pub equity: f32,
+    pub cash: f32,
+}
+
+pub struct BacktestReport {
+    pub equity_curve: Vec<EquityPoint>,
+    pub fills: Vec<Fill>,
+    pub liquidations: Vec<Liquidation>,
+    pub journal: Journal,
+    pub risk_events: Vec<RiskEvent>,
+    ///candles the ticker stores refused, with the market index
+    pub skipped_candles: Vec<(usize, TickerStoreError)>,
+}
+
+impl BacktestReport {
+    pub fn final_equity(&self) -> Option<f32> {
+        self.equity_curve.last().map(|point| point.equity)
+    }
+
+    ///relative change from the first to the last point of the equity curve
+    pub fn total_return(&self) -> f32 {
+        match (self.equity_curve.first(), self.equity_curve.last()) {
+            (Some(first), Some(last)) if first.equity != 0.0 => last.equity / first.equity - 1.0,
+            _ => 0.0,
+        }
+    }
+
+    ///largest fall of the equity curve from a previous peak, as a fraction of the peak
+    pub fn max_drawdown(&self) -> f32 {
+        let mut peak = f32::MIN;
+        let mut max_drawdown: f32 = 0.0;
+        for point in &self.equity_curve {
+            peak = peak.max(point.equity);
+            if peak > 0.0 {
+                max_drawdown = max_drawdown.max(1.0 - point.equity / peak);
+            }
+        }
+        max_drawdown
+    }
+}
+
+///A candle of one market that opened at timestamp_ms
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StepCandle {
+    pub market_index: usize,
+    pub timestamp_ms: i64,
+    pub ticker: Ticker,
+}
+
+pub fn candle_to_ticker(candle: &Candle) -> Ticker {
+    Ticker {
+        open: candle.open as f32,
+        high: candle.high as f32,
+        low: candle.low as f32,
+        close: candle.close as f32,
+        volume: candle.volume as f32,
+        trade_count: candle.trade_count as f32,
+    }
+}
+
+///Replays candles through a program. Every step fills the orders resting from the previous step,
+/// moves the clock to the close of the candles, evaluates the program's final instruction and
+/// submits the trades the risk rules let through. The clock comes from the candles alone, so
+/// replaying the same candles always gives the same result.
+pub struct Backtest {
+    config: BacktestConfig,
+    env: BacktestEnv,
+    simulator: Simulator,
+    journal: Journal,
+    risk_manager: RiskManager,
+    equity_curve: Vec<EquityPoint>,
+    fills: Vec<Fill>,
+    skipped_candles: Vec<(usize, TickerStoreError)>,
+    day: i64,
+    day_start_equity: f32,
+}
+
+impl Backtest {
+    pub fn new(config: BacktestConfig, symbols: Vec<String>) -> Backtest {
+        Backtest {
+            env: BacktestEnv::new(symbols, config.ticker_size_ms),
+            simulator: Simulator::new(config.simulator, config.initial_cash),
+            journal: Journal::new(),
+            risk_manager: RiskManager::new(config.risk_rules.clone()),
+            equity_curve: Vec::new(),
+            fills: Vec::new(),
+            skipped_candles: Vec::new(),
+            day: i64::MIN,
+            day_start_equity: config.initial_cash,
+            config,
+        }
+    }
+
+    pub fn env(&self) -> &BacktestEnv {
+        &self.env
+    }
+
+    ///historical order books a program reads through OrderBook operations
+    pub fn set_order_book_store(&mut self, market_index: usize, order_book_store: OrderBookStore) {
+        self.env
+            .set_order_book_store(market_index, order_book_store);
+    }
+
+    pub fn simulator(&self) -> &Simulator {
+        &self.simulator
+    }
+
+    pub fn equity(&self) -> f32 {
+        self.simulator
+            .equity(|market_index| self.env.price(market_index))
+    }
+
+    ///Candles of markets the Backtest doesn't know are ignored, a candle the market's ticker store
+    /// refuses is skipped and recorded in the report
+    pub fn step(&mut self, program: &OperationList, candles: &[StepCandle]) -> TradeList {
+        let candles: Vec<StepCandle> = candles
+            .iter()
+            .filter(|candle| candle.market_index < self.env.symbols().len())
+            .copied()
+            .collect();
+        let clock = match candles.iter().map(|candle| candle.timestamp_ms).max() {
+            Some(timestamp_ms) => timestamp_ms + self.config.ticker_size_ms as i64,
+            None => return Vec::new(),
+        };
+
+        let liquidation_count = self.simulator.liquidations().len();
+        let fills = self.simulator.process_candle(|market_index| {
+            candles
+                .iter()
+                .find(|candle| candle.market_index == market_index)
+                .map(|candle| candle.ticker)
+        });
+        self.journal.record_fills(&fills);
+        for liquidation in &self.simulator.liquidations()[liquidation_count..] {
+            self.journal.record_liquidation(liquidation);
+        }
+        self.fills.extend(fills);
+
+        for candle in &candles {
+            self.journal
+                .record_candle(candle.market_index, &candle.ticker);
+            if let Err(error) =
+                self.env
+                    .record_candle(candle.market_index, candle.timestamp_ms, candle.ticker)
+            {
+                self.skipped_candles.push((candle.market_index, error));
+            }
+        }
+        self.env.set_current_timestamp_ms(clock);
+        self.update_portfolio();
+
+        let equity = self.equity();
+        if clock.div_euclid(DAY_MS) != self.day {
+            self.day = clock.div_euclid(DAY_MS);
+            self.day_start_equity = equity;
+        }
+        self.equity_curve.push(EquityPoint {
+            timestamp_ms: clock,
+            equity,
+            cash: self.simulator.cash(),
+        });
+
+        let mut trade_list = TradeList::new();
+        if let Some(output) = program.last() {
+            output.evaluate(program, &mut trade_list, &None, &self.env);
+        }
+        let trade_list = self
+            .risk_manager
+            .apply(&trade_list, &self.risk_state(equity));
+        self.simulator.submit(&trade_list);
+        trade_list
+    }
+
+    ///Records candles the program may look back on without filling orders, trading or
+    /// adding to the equity curve, so indicators have history when trading starts
+    pub fn warm_up(&mut self, candles: &[StepCandle]) {
+        let market_count = self.env.symbols().len();
+        let mut clock = None;
+        for candle in candles
+            .iter()
+            .filter(|candle| candle.market_index < market_count)
+        {
+            if let Err(error) =
+                self.env
+                    .record_candle(candle.market_index, candle.timestamp_ms, candle.ticker)
+            {
+                self.skipped_candles.push((candle.market_index, error));
+            }
+            clock = clock.max(Some(
+                candle.timestamp_ms + self.config.ticker_size_ms as i64,
+            ));
+        }
+        if let Some(clock) = clock {
+            self.env.set_current_timestamp_ms(clock);
+        }
+        self.update_portfolio();
+    }
+
+    ///Closes the market's position at its last price and cancels its orders,
+    /// programs no longer see it in the market index list
+    pub fn delist(&mut self, market_index: usize) -> Option<Fill> {
+        self.env.delist(market_index);
+        let fill = self
+            .simulator
+            .force_close(market_index, self.env.price(market_index));
+        if let Some(fill) = &fill {
+            self.journal.record_fill(fill);
+            self.fills.push(fill.clone());
+        }
+        self.update_portfolio();
+        fill
+    }
+
+    fn update_portfolio(&mut self) {
+        let market_values = (0..self.env.symbols().len())
+            .map(|market_index| {
+                let price = self.env.price(market_index);
+                let amount = self.simulator.position(market_index);
+                if amount == 0.0 || price.is_nan() {
+                    0.0
+                } else {
+                    amount * price
+                }
+            })
+            .collect();
+        let equity = self.equity();
+        self.env.set_portfolio(equity, market_values);
+    }
+
+    fn risk_state(&self, equity: f32) -> RiskState {
+        let market_count = self.env.symbols().len();
+        RiskState {
+            equity,
+            day_start_equity: self.day_start_equity,
+            positions: (0..market_count)
+                .map(|market_index| self.simulator.position(market_index))
+                .collect(),
+            prices: (0..market_count)
+                .map(|market_index| self.env.price(market_index))
+                .collect(),
+            volatility: (0..market_count)
+                .map(|market_index| {
+                    self.env
+                        .volatility(market_index, self.config.volatility_window)
+                })
+                .collect(),
+        }
+    }
+
+    ///steps a single candle event, trades and events of unknown symbols are ignored
+    pub fn on_market_event(&mut self, program: &OperationList, market_event: &MarketEvent) {
+        let market_index = match self.env.market_index(&market_event.symbol) {
+            Some(market_index) => market_index,
+            None => return,
+        };
+        if let BarterMarketData::Candle(candle) = &market_event.data {
+            self.step(
+                program,
+                &[StepCandle {
+                    market_index,
+                    timestamp_ms: candle.start_timestamp.timestamp_millis(),
+                    ticker: candle_to_ticker(candle),
+                }],
+            );
+        }
+    }
+
+    ///drives the generator until it stops
+    pub fn run<Generator>(
+        mut self,
+        program: &OperationList,
+        generator: &mut Generator,
+    ) -> BacktestReport
+    where
+        Generator: Continuer + MarketGenerator,
+    {
+        while let Continuation::Continue = generator.can_continue() {
+            if let Some(market_event) = generator.generate_market() {
+                self.on_market_event(program, &market_event);
+            }
+        }
+        self.finish()
+    }
+
+    pub fn finish(self) -> BacktestReport {
+        BacktestReport {
+            equity_curve: self.equity_curve,
+            fills: self.fills,
+            liquidations: self.simulator.liquidations().to_vec(),
+            journal: self.journal,
+            risk_events: self.risk_manager.events().to_vec(),
+            skipped_candles: self.skipped_candles,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::op::generator::ProgramGenerator;
+    use crate::lib::op::operand::Operand;
+    use crate::lib::op::operation::constant::ConstantOperator;
+    use crate::lib::op::operation::order_book::OrderBookOperator;
+    use crate::lib::op::operation::trade::*;
+    use crate::lib::op::operation::Operation;
+    use crate::lib::op::terminal_type::TerminalType;
+    use crate::lib::op::type_check::ValueType;
+    use barter::data::handler::historical::{HistoricalCandleHandler, HistoricalDataLego};
+    use chrono::{TimeZone, Utc};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn candles(closes: &[f64]) -> Vec<Candle> {
+        closes
+            .iter()
+            .enumerate()
+            .map(|(index, close)| Candle {
+                start_timestamp: Utc.timestamp_millis(index as i64 * 60_000),
+                end_timestamp: Utc.timestamp_millis(index as i64 * 60_000 + 59_999),
+                open: *close,
+                high: close + 1.0,
+                low: close - 1.0,
+                close: *close,
+                volume: 1.0,
+                trade_count: 1,
+            })
+            .collect()
+    }
+
+    fn buy_one() -> OperationList {
+        vec![Operation::Trade((
+            TradeOperator::Buy,
+            Operand::Terminal(TerminalType::MarketIndex(0)),
+            Operand::Terminal(TerminalType::Number(0.0)),
+            Operand::Terminal(TerminalType::Number(1.0)),
+            OrderType::Market,
+            TimeInForce::GoodTilCancelled,
+            Operand::None,
+            TradeLeverage::X1,
+        ))]
+    }
+
+    fn free_config() -> BacktestConfig {
+        BacktestConfig {
+            simulator: SimulatorConfig {
+                taker_fee: 0.0,
+                maker_fee: 0.0,
+                ..SimulatorConfig::default()
+            },
+            initial_cash: 1000.0,
+            ..BacktestConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_run_buys_every_candle() {
+        let mut handler = HistoricalCandleHandler::new(HistoricalDataLego {
+            exchange: "Binance",
+            symbol: "BTCUSDT".to_string(),
+            candles: candles(&[100.0, 110.0, 120.0]).into_iter(),
+        });
+        let backtest = Backtest::new(free_config(), vec!["btcusdt".to_string()]);
+        let report = backtest.run(&buy_one(), &mut handler);
+
+        //orders submitted after a candle fill at the next open, the last one is still resting
+        assert_eq!(report.fills.len(), 2);
+        assert_eq!(report.fills[0].price, 110.0);
+        assert_eq!(report.fills[1].price, 120.0);
+        let equity: Vec<f32> = report
+            .equity_curve
+            .iter()
+            .map(|point| point.equity)
+            .collect();
+        assert_eq!(equity, vec![1000.0, 1000.0, 1010.0]);
+        assert_eq!(report.equity_curve[0].timestamp_ms, 60_000);
+        assert_eq!(report.final_equity(), Some(1010.0));
+        assert!((report.total_return() - 0.01).abs() < 1e-6);
+        assert_eq!(report.journal.open_positions().count(), 1);
+        assert!(report.skipped_candles.is_empty());
+    }
+
+    #[test]
+    fn test_env_never_looks_ahead() {
+        let mut backtest = Backtest::new(free_config(), vec!["ethusdt".to_string()]);
+        let program = vec![Operation::Constant((
+            ConstantOperator::CurrentTimestampMs,
+            Operand::None,
+        ))];
+        for (index, candle) in candles(&[1.0, 2.0, 3.0, 4.0]).iter().enumerate() {
+            backtest.step(
+                &program,
+                &[StepCandle {
+                    market_index: 0,
+                    timestamp_ms: index as i64 * 60_000,
+                    ticker: candle_to_ticker(candle),
+                }],
+            );
+        }
+        let env = backtest.env();
+        assert_eq!(env.get_current_timestamp_ms(), 240_000);
+        assert_eq!(env.get_eth_market_index(), 0);
+        assert_eq!(
+            env.get_market_data(0, 240_000, -120_000).close,
+            vec![3.0, 4.0]
+        );
+        assert_eq!(env.get_market_data(0, 60_000, 60_000).close, vec![2.0]);
+        assert_eq!(env.get_market_data(0, 0, 1_000_000).close.len(), 4);
+
+        //a candle that hasn't closed yet is invisible
+        let mut env = BacktestEnv::new(vec!["ethusdt".to_string()], 60_000);
+        env.record_candle(0, 0, Ticker::default()).unwrap();
+        env.set_current_timestamp_ms(30_000);
+        assert!(env.get_market_data(0, 0, 60_000).close.is_empty());
+        assert_eq!(env.get_market_index_list(), vec![0.0]);
+        assert!(env.record_candle(0, 0, Ticker::default()).is_err());
+    }
+
+    #[test]
+    fn test_env_reads_order_book_snapshots() {
+        let book = |best_bid: f32| OrderBook {
+            bid_price: vec![best_bid, best_bid - 1.0],
+            bid_volume: vec![1.0, 2.0],
+            ask_price: vec![best_bid + 1.0, best_bid + 2.0],
+            ask_volume: vec![1.0, 2.0],
+        };
+        let mut order_book_store = OrderBookStore::new(5);
+        order_book_store.insert(0, book(10.0)).unwrap();
+        order_book_store.insert(120_000, book(20.0)).unwrap();
+        let mut backtest = Backtest::new(
+            free_config(),
+            vec!["btcusdt".to_string(), "ethusdt".to_string()],
+        );
+        backtest.set_order_book_store(0, order_book_store);
+        let program = vec![Operation::OrderBook((
+            OrderBookOperator::BidPrice,
+            Operand::Terminal(TerminalType::MarketIndex(0)),
+            Operand::Terminal(TerminalType::Timestamp(1_000_000)),
+            Operand::Terminal(TerminalType::Int(1)),
+        ))];
+        let bids = |backtest: &Backtest| {
+            program[0]
+                .evaluate(&program, &mut TradeList::new(), &None, backtest.env())
+                .to_list()
+        };
+
+        //asking for the future gets the book as of the current timestamp
+        let mut seen = Vec::new();
+        for (index, candle) in candles(&[1.0, 2.0]).iter().enumerate() {
+            backtest.warm_up(&[StepCandle {
+                market_index: 0,
+                timestamp_ms: index as i64 * 60_000,
+                ticker: candle_to_ticker(candle),
+            }]);
+            seen.push(bids(&backtest));
+        }
+        assert_eq!(seen, vec![vec![10.0], vec![20.0]]);
+        let env = backtest.env();
+        assert_eq!(env.get_order_book(0, 60_000, 5).bid_price, vec![10.0, 9.0]);
+        assert_eq!(env.get_order_book(1, 60_000, 5), OrderBook::default());
+        assert_eq!(env.get_order_book(0, -1, 5), OrderBook::default());
+
+        let mut env = BacktestEnv::new(vec!["btcusdt".to_string()], 60_000);
+        let mut order_book_store = OrderBookStore::new(5);
+        order_book_store.insert(120_000, book(20.0)).unwrap();
+        env.set_order_book_store(0, order_book_store);
+        env.set_current_timestamp_ms(60_000);
+        assert_eq!(env.get_order_book(0, 1_000_000, 5), OrderBook::default());
+    }
+
+    #[test]
+    fn test_generated_programs_read_market_data() {
+        let mut backtest = Backtest::new(free_config(), vec!["btcusdt".to_string()]);
+        let closes: Vec<f64> = (0..200).map(|index| 100.0 + index as f64).collect();
+        for (index, candle) in candles(&closes).iter().enumerate() {
+            backtest.warm_up(&[StepCandle {
+                market_index: 0,
+                timestamp_ms: index as i64 * 60_000,
+                ticker: candle_to_ticker(candle),
+            }]);
+        }
+        let env = backtest.env();
+        let generator = ProgramGenerator::default();
+        let mut rng = StdRng::seed_from_u64(11);
+        let (mut reads, mut non_empty) = (0, 0);
+        for _ in 0..300 {
+            let program = generator.generate(&mut rng, ValueType::NumberList);
+            for operation in &program {
+                if let Operation::MarketData(_) = operation {
+                    let value = operation.evaluate(&program, &mut TradeList::new(), &None, env);
+                    reads += 1;
+                    non_empty += !value.to_list().is_empty() as usize;
+                }
+            }
+        }
+        assert!(reads > 0);
+        assert!(non_empty * 2 > reads, "{} of {} reads", non_empty, reads);
+    }
+}
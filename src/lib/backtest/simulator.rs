@@ -0,0 +1,899 @@
+use crate::lib::op::operation::trade::*;
+use crate::lib::op::ticker_store::Ticker;
+use std::fmt::Display;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MarketType {
+    ///buys cost their full value, sells are limited to what is held and leverage is ignored
+    Spot,
+    ///linear perpetual futures with isolated margin, positions can be short
+    Perpetual,
+}
+
+///Fee rates are a fraction of the filled value
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SimulatorConfig {
+    ///paid by market orders and triggered stops
+    pub taker_fee: f32,
+    ///paid by limit, take-profit and stop-limit fills
+    pub maker_fee: f32,
+    pub market_type: MarketType,
+    ///fraction of a perpetual position's value its margin must stay above
+    pub maintenance_margin_rate: f32,
+    ///fraction of a perpetual position's value longs pay shorts every funding interval,
+    /// negative rates make shorts pay longs
+    pub funding_rate: f32,
+    ///candles between funding payments, 0 disables funding
+    pub funding_interval: usize,
+}
+
+impl Default for SimulatorConfig {
+    fn default() -> SimulatorConfig {
+        SimulatorConfig {
+            taker_fee: 0.001,
+            maker_fee: 0.001,
+            market_type: MarketType::Spot,
+            maintenance_margin_rate: 0.005,
+            funding_rate: 0.0001,
+            funding_interval: 8,
+        }
+    }
+}
+
+///Holdings of one market. Amount is negative for a short,
+/// margin is always 0 on spot where a position is paid in full.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Position {
+    pub amount: f32,
+    ///average price the current amount was opened at
+    pub entry_price: f32,
+    pub margin: f32,
+}
+
+impl Position {
+    pub fn is_long(&self) -> bool {
+        self.amount > 0.0
+    }
+
+    ///profit or loss if the position was closed at price, before fees
+    pub fn unrealized_pnl(&self, price: f32) -> f32 {
+        self.amount * (price - self.entry_price)
+    }
+
+    ///Price at which margin plus unrealized pnl falls to the maintenance margin,
+    /// None for a flat or unmargined position
+    pub fn liquidation_price(&self, maintenance_margin_rate: f32) -> Option<f32> {
+        if self.amount == 0.0 || self.margin <= 0.0 {
+            return None;
+        }
+        let size = self.amount.abs();
+        let price = if self.is_long() {
+            (size * self.entry_price - self.margin) / (size * (1.0 - maintenance_margin_rate))
+        } else {
+            (size * self.entry_price + self.margin) / (size * (1.0 + maintenance_margin_rate))
+        };
+        Some(price.max(0.0))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Liquidation {
+    pub candle: usize,
+    pub market_index: usize,
+    pub price: f32,
+    ///signed amount of the position that was closed
+    pub amount: f32,
+    ///the isolated margin that was lost
+    pub margin: f32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CancelReason {
+    ///an immediate-or-cancel or fill-or-kill order that didn't fill on its first candle
+    TimeInForce,
+    ///a good-for-candles order that ran out of candles
+    Expired,
+    ///the balance or position couldn't cover the whole order, the rest was cancelled
+    InsufficientBalance,
+    ///zero, negative or NaN amount
+    InvalidAmount,
+    Requested,
+    ///the market was delisted while the order rested
+    Delisted,
+}
+
+impl Display for CancelReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CancelReason::TimeInForce => write!(f, "time in force"),
+            CancelReason::Expired => write!(f, "expired"),
+            CancelReason::InsufficientBalance => write!(f, "insufficient balance"),
+            CancelReason::InvalidAmount => write!(f, "invalid amount"),
+            CancelReason::Requested => write!(f, "requested"),
+            CancelReason::Delisted => write!(f, "delisted"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Order {
+    pub id: usize,
+    ///amount is what is still unfilled
+    pub trade: Trade,
+    ///candle the order was submitted after
+    pub submitted_candle: usize,
+    ///candles the order has been live for
+    pub age: u32,
+    ///a stop-limit whose stop price has been reached and now rests as a limit order
+    pub triggered: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fill {
+    pub order_id: usize,
+    pub candle: usize,
+    pub market_index: usize,
+    pub operator: TradeOperator,
+    pub order_type: OrderType,
+    pub price: f32,
+    pub amount: f32,
+    ///paid in the quote currency
+    pub fee: f32,
+}
+
+///Where and how an order fills against a candle, None while it keeps resting.
+/// The candle's path between open, high and low is unknown, so stops are assumed
+/// to trigger before limits and gaps fill at the open when it is the better price.
+/// Sets triggered when a stop-limit's stop price is reached.
+pub fn fill_price(order: &mut Order, candle: &Ticker) -> Option<(f32, Liquidity)> {
+    let trade = &order.trade;
+    let buy = trade.operator == TradeOperator::Buy;
+    let limit = |price: f32| {
+        if buy && candle.low <= price {
+            Some((candle.open.min(price), Liquidity::Maker))
+        } else if !buy && candle.high >= price {
+            Some((candle.open.max(price), Liquidity::Maker))
+        } else {
+            None
+        }
+    };
+    let stop = |stop_price: f32| {
+        if buy && candle.high >= stop_price {
+            Some((candle.open.max(stop_price), Liquidity::Taker))
+        } else if !buy && candle.low <= stop_price {
+            Some((candle.open.min(stop_price), Liquidity::Taker))
+        } else {
+            None
+        }
+    };
+
+    match trade.order_type {
+        OrderType::Market => Some((candle.open, Liquidity::Taker)),
+        OrderType::Limit | OrderType::TakeProfit => limit(trade.price),
+        OrderType::StopMarket => stop(trade.stop_price),
+        OrderType::StopLimit if order.triggered => limit(trade.price),
+        OrderType::StopLimit => {
+            let (stop_fill, _) = stop(trade.stop_price)?;
+            order.triggered = true;
+            //on the candle it triggers it can only fill at the stop if that is within the limit
+            let within_limit = if buy {
+                stop_fill <= trade.price
+            } else {
+                stop_fill >= trade.price
+            };
+            within_limit.then_some((stop_fill, Liquidity::Maker))
+        }
+        OrderType::Oco => stop(trade.stop_price).or_else(|| limit(trade.price)),
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Liquidity {
+    Maker,
+    Taker,
+}
+
+///Spot or perpetual futures portfolio simulator. Trades are submitted as orders after a candle closes
+/// and rest until a later candle fills, expires or cancels them.
+/// Amounts are in units of the market's base asset, prices and cash in the quote currency.
+pub struct Simulator {
+    config: SimulatorConfig,
+    ///on perpetuals this is the balance not locked up as margin
+    cash: f32,
+    ///indexed by market index
+    positions: Vec<Position>,
+    orders: Vec<Order>,
+    fills: Vec<Fill>,
+    cancelled: Vec<(Order, CancelReason)>,
+    liquidations: Vec<Liquidation>,
+    ///positive when funding was paid, negative when it was received
+    funding_paid: f32,
+    ///number of candles processed so far
+    candle: usize,
+    next_order_id: usize,
+}
+
+impl Simulator {
+    pub fn new(config: SimulatorConfig, cash: f32) -> Simulator {
+        Simulator {
+            config,
+            cash,
+            positions: Vec::new(),
+            orders: Vec::new(),
+            fills: Vec::new(),
+            cancelled: Vec::new(),
+            liquidations: Vec::new(),
+            funding_paid: 0.0,
+            candle: 0,
+            next_order_id: 0,
+        }
+    }
+
+    pub fn cash(&self) -> f32 {
+        self.cash
+    }
+
+    ///signed amount held in the market
+    pub fn position(&self, market_index: usize) -> f32 {
+        self.position_state(market_index).amount
+    }
+
+    pub fn position_state(&self, market_index: usize) -> Position {
+        self.positions
+            .get(market_index)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn open_orders(&self) -> &[Order] {
+        &self.orders
+    }
+
+    pub fn fills(&self) -> &[Fill] {
+        &self.fills
+    }
+
+    pub fn cancelled(&self) -> &[(Order, CancelReason)] {
+        &self.cancelled
+    }
+
+    pub fn liquidations(&self) -> &[Liquidation] {
+        &self.liquidations
+    }
+
+    pub fn funding_paid(&self) -> f32 {
+        self.funding_paid
+    }
+
+    pub fn candle_count(&self) -> usize {
+        self.candle
+    }
+
+    ///cash plus every position valued at price
+    pub fn equity(&self, price: impl Fn(usize) -> f32) -> f32 {
+        self.cash
+            + self
+                .positions
+                .iter()
+                .enumerate()
+                .filter(|(_, position)| position.amount != 0.0)
+                .map(|(market_index, position)| match self.config.market_type {
+                    MarketType::Spot => position.amount * price(market_index),
+                    MarketType::Perpetual => {
+                        position.margin + position.unrealized_pnl(price(market_index))
+                    }
+                })
+                .sum::<f32>()
+    }
+
+    ///Queues every trade but `TradeOperator::Nothing` as an order and returns the new order ids,
+    /// trades with an invalid amount are cancelled right away
+    pub fn submit(&mut self, trade_list: &TradeList) -> Vec<usize> {
+        let mut ids = Vec::new();
+        for trade in trade_list {
+            if trade.operator == TradeOperator::Nothing {
+                continue;
+            }
+            let order = Order {
+                id: self.next_order_id,
+                trade: trade.clone(),
+                submitted_candle: self.candle,
+                age: 0,
+                triggered: false,
+            };
+            self.next_order_id += 1;
+            if !trade.amount.is_finite() || trade.amount <= 0.0 {
+                self.cancelled.push((order, CancelReason::InvalidAmount));
+                continue;
+            }
+            ids.push(order.id);
+            self.orders.push(order);
+        }
+        ids
+    }
+
+    pub fn cancel(&mut self, order_id: usize) -> bool {
+        match self.orders.iter().position(|order| order.id == order_id) {
+            Some(position) => {
+                let order = self.orders.remove(position);
+                self.cancelled.push((order, CancelReason::Requested));
+                true
+            }
+            None => false,
+        }
+    }
+
+    ///Cancels the market's resting orders and closes its position with a market order at price,
+    /// for a market that stops trading
+    pub fn force_close(&mut self, market_index: usize, price: f32) -> Option<Fill> {
+        let (delisted, orders) = std::mem::take(&mut self.orders)
+            .into_iter()
+            .partition(|order| order.trade.index == market_index);
+        self.orders = orders;
+        for order in delisted {
+            self.cancelled.push((order, CancelReason::Delisted));
+        }
+
+        let position = self.position(market_index);
+        if position == 0.0 || !price.is_finite() {
+            return None;
+        }
+        let operator = if position > 0.0 {
+            TradeOperator::Sell
+        } else {
+            TradeOperator::Buy
+        };
+        let mut order = Order {
+            id: self.next_order_id,
+            trade: Trade::market(operator, market_index, price, position.abs()),
+            submitted_candle: self.candle,
+            age: 0,
+            triggered: false,
+        };
+        self.next_order_id += 1;
+        let fill = self.fill(&mut order, price, Liquidity::Taker)?;
+        self.fills.push(fill.clone());
+        Some(fill)
+    }
+
+    ///Runs every resting order against the next candle of its market, in submission order.
+    /// Markets without a candle, or with a missing one, leave their orders untouched.
+    /// On perpetuals funding is then charged at the close and positions whose
+    /// liquidation price lies within the candle are liquidated.
+    pub fn process_candle(&mut self, candle: impl Fn(usize) -> Option<Ticker>) -> Vec<Fill> {
+        let mut fills = Vec::new();
+        for mut order in std::mem::take(&mut self.orders) {
+            let ticker = match candle(order.trade.index) {
+                Some(ticker) if !ticker.open.is_nan() => ticker,
+                _ => {
+                    self.orders.push(order);
+                    continue;
+                }
+            };
+            order.age += 1;
+            match fill_price(&mut order, &ticker) {
+                Some((price, liquidity)) => {
+                    if let Some(fill) = self.fill(&mut order, price, liquidity) {
+                        fills.push(fill);
+                    }
+                }
+                None => self.keep_or_expire(order),
+            }
+        }
+        if self.config.market_type == MarketType::Perpetual {
+            self.charge_funding(&candle);
+            self.liquidate(&candle);
+        }
+        self.candle += 1;
+        self.fills.extend(fills.iter().cloned());
+        fills
+    }
+
+    ///funding is paid out of and received into the isolated margin of each position
+    fn charge_funding(&mut self, candle: &impl Fn(usize) -> Option<Ticker>) {
+        let interval = self.config.funding_interval;
+        if interval == 0 || !(self.candle + 1).is_multiple_of(interval) {
+            return;
+        }
+        for (market_index, position) in self.positions.iter_mut().enumerate() {
+            if position.amount == 0.0 {
+                continue;
+            }
+            if let Some(ticker) = candle(market_index).filter(|ticker| !ticker.close.is_nan()) {
+                let payment = position.amount * ticker.close * self.config.funding_rate;
+                position.margin -= payment;
+                self.funding_paid += payment;
+            }
+        }
+    }
+
+    ///Closes positions whose liquidation price was reached by the candle's low for longs
+    /// or high for shorts, at the open when it gapped past. The isolated margin is lost
+    /// and never more than that.
+    fn liquidate(&mut self, candle: &impl Fn(usize) -> Option<Ticker>) {
+        let maintenance_margin_rate = self.config.maintenance_margin_rate;
+        for (market_index, position) in self.positions.iter_mut().enumerate() {
+            let liquidation_price = match position.liquidation_price(maintenance_margin_rate) {
+                Some(price) => price,
+                //funding ate all the margin
+                None if position.amount != 0.0 && position.margin <= 0.0 => f32::NAN,
+                None => continue,
+            };
+            let ticker = match candle(market_index) {
+                Some(ticker) if !ticker.open.is_nan() => ticker,
+                _ => continue,
+            };
+            let price = if liquidation_price.is_nan() {
+                ticker.close
+            } else if position.is_long() && ticker.low <= liquidation_price {
+                ticker.open.min(liquidation_price)
+            } else if !position.is_long() && ticker.high >= liquidation_price {
+                ticker.open.max(liquidation_price)
+            } else {
+                continue;
+            };
+            self.liquidations.push(Liquidation {
+                candle: self.candle,
+                market_index,
+                price,
+                amount: position.amount,
+                margin: position.margin.max(0.0),
+            });
+            *position = Position::default();
+        }
+    }
+
+    fn keep_or_expire(&mut self, order: Order) {
+        match order.trade.time_in_force {
+            TimeInForce::ImmediateOrCancel | TimeInForce::FillOrKill => {
+                self.cancelled.push((order, CancelReason::TimeInForce))
+            }
+            TimeInForce::GoodForCandles(candles) if order.age >= candles => {
+                self.cancelled.push((order, CancelReason::Expired))
+            }
+            _ => self.orders.push(order),
+        }
+    }
+
+    fn leverage(&self, trade: &Trade) -> f32 {
+        match self.config.market_type {
+            MarketType::Spot => 1.0,
+            MarketType::Perpetual => trade.leverage.factor(),
+        }
+    }
+
+    ///the largest part of order the balance allows at price
+    fn fillable_amount(&self, order: &Order, price: f32, fee_rate: f32) -> f32 {
+        let trade = &order.trade;
+        let position = self.position(trade.index);
+        let reduce_only = matches!(trade.order_type, OrderType::TakeProfit | OrderType::Oco);
+        //the part of the order that closes an opposite position needs no balance
+        let closing = match trade.operator {
+            TradeOperator::Buy => (-position).max(0.0),
+            TradeOperator::Sell => position.max(0.0),
+            TradeOperator::Nothing => return 0.0,
+        };
+        if reduce_only {
+            return trade.amount.min(closing);
+        }
+        let opening = match (self.config.market_type, trade.operator) {
+            (MarketType::Spot, TradeOperator::Sell) => 0.0,
+            _ => self.cash.max(0.0) / (price * (1.0 / self.leverage(trade) + fee_rate)),
+        };
+        trade.amount.min(closing + opening)
+    }
+
+    ///moves the position by amount, negative for sells, and settles cash and margin
+    fn settle(&mut self, index: usize, amount: f32, price: f32, leverage: f32) {
+        if self.positions.len() <= index {
+            self.positions.resize(index + 1, Position::default());
+        }
+        let market_type = self.config.market_type;
+        let position = &mut self.positions[index];
+        let mut opening = amount;
+        if position.amount != 0.0 && position.amount.signum() != amount.signum() {
+            let closed = amount.abs().min(position.amount.abs());
+            let fraction = closed / position.amount.abs();
+            self.cash += match market_type {
+                MarketType::Spot => closed * price,
+                MarketType::Perpetual => {
+                    position.margin * fraction
+                        + closed * (price - position.entry_price) * position.amount.signum()
+                }
+            };
+            position.margin -= position.margin * fraction;
+            if closed == position.amount.abs() {
+                *position = Position::default();
+            } else {
+                position.amount += closed * amount.signum();
+            }
+            opening -= closed * amount.signum();
+        }
+        if opening != 0.0 {
+            let value = opening.abs() * price;
+            let margin = match market_type {
+                MarketType::Spot => 0.0,
+                MarketType::Perpetual => value / leverage,
+            };
+            self.cash -= match market_type {
+                MarketType::Spot => value,
+                MarketType::Perpetual => margin,
+            };
+            let size = position.amount.abs();
+            position.entry_price = (position.entry_price * size + value) / (size + opening.abs());
+            position.amount += opening;
+            position.margin += margin;
+        }
+    }
+
+    fn fill(&mut self, order: &mut Order, price: f32, liquidity: Liquidity) -> Option<Fill> {
+        let fee_rate = match liquidity {
+            Liquidity::Maker => self.config.maker_fee,
+            Liquidity::Taker => self.config.taker_fee,
+        };
+        let amount = self.fillable_amount(order, price, fee_rate).max(0.0);
+        let complete = amount >= order.trade.amount;
+        if amount <= 0.0 || (!complete && order.trade.time_in_force == TimeInForce::FillOrKill) {
+            self.cancelled
+                .push((order.clone(), CancelReason::InsufficientBalance));
+            return None;
+        }
+
+        let fee = amount * price * fee_rate;
+        let index = order.trade.index;
+        let signed_amount = match order.trade.operator {
+            TradeOperator::Buy => amount,
+            _ => -amount,
+        };
+        let leverage = self.leverage(&order.trade);
+        self.settle(index, signed_amount, price, leverage);
+        self.cash -= fee;
+
+        if !complete {
+            let mut rest = order.clone();
+            rest.trade.amount -= amount;
+            self.cancelled
+                .push((rest, CancelReason::InsufficientBalance));
+        }
+        Some(Fill {
+            order_id: order.id,
+            candle: self.candle,
+            market_index: index,
+            operator: order.trade.operator,
+            order_type: order.trade.order_type,
+            price,
+            amount,
+            fee,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candle(open: f32, high: f32, low: f32, close: f32) -> Ticker {
+        Ticker {
+            open,
+            high,
+            low,
+            close,
+            ..Ticker::default()
+        }
+    }
+
+    fn order(operator: TradeOperator, order_type: OrderType, price: f32, stop_price: f32) -> Trade {
+        Trade {
+            order_type,
+            stop_price,
+            ..Trade::market(operator, 1, price, 1.0)
+        }
+    }
+
+    fn free_simulator(cash: f32) -> Simulator {
+        Simulator::new(
+            SimulatorConfig {
+                taker_fee: 0.0,
+                maker_fee: 0.0,
+                ..SimulatorConfig::default()
+            },
+            cash,
+        )
+    }
+
+    fn holding(amount: f32) -> Vec<Position> {
+        vec![
+            Position::default(),
+            Position {
+                amount,
+                entry_price: 100.0,
+                margin: 0.0,
+            },
+        ]
+    }
+
+    fn perpetual_simulator(cash: f32, funding_rate: f32) -> Simulator {
+        Simulator::new(
+            SimulatorConfig {
+                taker_fee: 0.0,
+                maker_fee: 0.0,
+                market_type: MarketType::Perpetual,
+                maintenance_margin_rate: 0.005,
+                funding_rate,
+                funding_interval: 2,
+            },
+            cash,
+        )
+    }
+
+    fn leveraged(operator: TradeOperator, amount: f32, leverage: TradeLeverage) -> Trade {
+        Trade {
+            leverage,
+            ..Trade::market(operator, 1, 0.0, amount)
+        }
+    }
+
+    #[test]
+    fn test_market_order_fills_at_next_open() {
+        let mut simulator = Simulator::new(
+            SimulatorConfig {
+                taker_fee: 0.01,
+                maker_fee: 0.0,
+                ..SimulatorConfig::default()
+            },
+            1000.0,
+        );
+        simulator.submit(&vec![Trade::market(TradeOperator::Buy, 1, 0.0, 2.0)]);
+        let fills = simulator.process_candle(|_| Some(candle(100.0, 110.0, 90.0, 105.0)));
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].price, 100.0);
+        assert_eq!(fills[0].fee, 2.0);
+        assert_eq!(simulator.cash(), 798.0);
+        assert_eq!(simulator.position(1), 2.0);
+        assert_eq!(simulator.equity(|_| 105.0), 1008.0);
+        assert!(simulator.open_orders().is_empty());
+        assert_eq!(simulator.fills(), &fills[..]);
+        assert_eq!(simulator.candle_count(), 1);
+    }
+
+    #[test]
+    fn test_limit_order_rests_until_touched() {
+        let mut simulator = free_simulator(1000.0);
+        simulator.submit(&vec![order(
+            TradeOperator::Buy,
+            OrderType::Limit,
+            95.0,
+            0.0,
+        )]);
+        assert!(simulator
+            .process_candle(|_| Some(candle(100.0, 110.0, 96.0, 105.0)))
+            .is_empty());
+        assert_eq!(simulator.open_orders().len(), 1);
+        //gapping below the limit fills at the better open
+        let fills = simulator.process_candle(|_| Some(candle(93.0, 94.0, 90.0, 92.0)));
+        assert_eq!(fills[0].price, 93.0);
+        assert_eq!(fills[0].candle, 1);
+
+        //a market without a candle keeps its orders
+        simulator.submit(&vec![order(
+            TradeOperator::Sell,
+            OrderType::Limit,
+            100.0,
+            0.0,
+        )]);
+        assert!(simulator.process_candle(|_| None).is_empty());
+        let fills = simulator.process_candle(|_| Some(candle(95.0, 101.0, 94.0, 99.0)));
+        assert_eq!(fills[0].price, 100.0);
+        assert_eq!(simulator.position(1), 0.0);
+    }
+
+    #[test]
+    fn test_stop_orders() {
+        let mut simulator = free_simulator(0.0);
+        simulator.positions = holding(2.0);
+        simulator.submit(&vec![
+            order(TradeOperator::Sell, OrderType::StopMarket, 0.0, 90.0),
+            order(TradeOperator::Sell, OrderType::StopLimit, 88.0, 92.0),
+        ]);
+        assert!(simulator
+            .process_candle(|_| Some(candle(100.0, 105.0, 93.0, 95.0)))
+            .is_empty());
+        //the stop-limit triggers at 92 but can't sell there after a gap to 85,
+        // the stop-market fills at the open
+        let fills = simulator.process_candle(|_| Some(candle(85.0, 87.0, 80.0, 86.0)));
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].price, 85.0);
+        assert!(simulator.open_orders()[0].triggered);
+        let fills = simulator.process_candle(|_| Some(candle(86.0, 89.0, 85.0, 88.0)));
+        assert_eq!(fills[0].price, 88.0);
+        assert_eq!(fills[0].order_type, OrderType::StopLimit);
+        assert_eq!(simulator.cash(), 173.0);
+    }
+
+    #[test]
+    fn test_oco_and_take_profit() {
+        let mut simulator = free_simulator(0.0);
+        simulator.positions = holding(2.0);
+        simulator.submit(&vec![
+            order(TradeOperator::Sell, OrderType::Oco, 110.0, 90.0),
+            order(TradeOperator::Sell, OrderType::Oco, 110.0, 90.0),
+        ]);
+        //take-profit leg
+        let fills = simulator.process_candle(|_| Some(candle(100.0, 112.0, 95.0, 105.0)));
+        assert_eq!(fills[0].price, 110.0);
+        assert_eq!(fills[1].price, 110.0);
+
+        //both legs inside one candle, the stop is assumed first
+        simulator.positions = holding(1.0);
+        simulator.submit(&vec![order(
+            TradeOperator::Sell,
+            OrderType::Oco,
+            110.0,
+            90.0,
+        )]);
+        let fills = simulator.process_candle(|_| Some(candle(100.0, 112.0, 85.0, 105.0)));
+        assert_eq!(fills[0].price, 90.0);
+
+        //nothing to take profit on
+        simulator.submit(&vec![order(
+            TradeOperator::Sell,
+            OrderType::TakeProfit,
+            110.0,
+            0.0,
+        )]);
+        assert!(simulator
+            .process_candle(|_| Some(candle(100.0, 112.0, 95.0, 105.0)))
+            .is_empty());
+        assert_eq!(
+            simulator.cancelled().last().unwrap().1,
+            CancelReason::InsufficientBalance
+        );
+    }
+
+    #[test]
+    fn test_time_in_force() {
+        let mut simulator = free_simulator(150.0);
+        let with_time_in_force = |time_in_force, price| Trade {
+            time_in_force,
+            ..order(TradeOperator::Buy, OrderType::Limit, price, 0.0)
+        };
+        let ids = simulator.submit(&vec![
+            with_time_in_force(TimeInForce::ImmediateOrCancel, 90.0),
+            with_time_in_force(TimeInForce::GoodForCandles(2), 90.0),
+            with_time_in_force(TimeInForce::GoodTilCancelled, 90.0),
+            Trade {
+                amount: 2.0,
+                ..with_time_in_force(TimeInForce::FillOrKill, 100.0)
+            },
+            Trade {
+                amount: -1.0,
+                ..with_time_in_force(TimeInForce::GoodTilCancelled, 100.0)
+            },
+        ]);
+        assert_eq!(ids, vec![0, 1, 2, 3]);
+        simulator.process_candle(|_| Some(candle(100.0, 100.0, 95.0, 95.0)));
+        let reasons: Vec<(usize, CancelReason)> = simulator
+            .cancelled()
+            .iter()
+            .map(|(order, reason)| (order.id, *reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (4, CancelReason::InvalidAmount),
+                (0, CancelReason::TimeInForce),
+                (3, CancelReason::InsufficientBalance),
+            ]
+        );
+        simulator.process_candle(|_| Some(candle(100.0, 100.0, 95.0, 95.0)));
+        assert_eq!(simulator.cancelled().last().unwrap().0.id, 1);
+        assert_eq!(
+            simulator.cancelled().last().unwrap().1,
+            CancelReason::Expired
+        );
+        assert!(simulator.cancel(2));
+        assert!(!simulator.cancel(2));
+        assert!(simulator.open_orders().is_empty());
+    }
+
+    #[test]
+    fn test_partial_fill_cancels_the_rest() {
+        let mut simulator = free_simulator(150.0);
+        simulator.submit(&vec![Trade::market(TradeOperator::Buy, 1, 0.0, 2.0)]);
+        let fills = simulator.process_candle(|_| Some(candle(100.0, 100.0, 100.0, 100.0)));
+        assert_eq!(fills[0].amount, 1.5);
+        assert_eq!(simulator.cash(), 0.0);
+        let (rest, reason) = &simulator.cancelled()[0];
+        assert_eq!(rest.trade.amount, 0.5);
+        assert_eq!(*reason, CancelReason::InsufficientBalance);
+    }
+
+    #[test]
+    fn test_leveraged_short() {
+        let mut simulator = perpetual_simulator(1000.0, 0.0);
+        simulator.submit(&vec![leveraged(
+            TradeOperator::Sell,
+            10.0,
+            TradeLeverage::X5,
+        )]);
+        simulator.process_candle(|_| Some(candle(100.0, 101.0, 95.0, 96.0)));
+        let position = simulator.position_state(1);
+        assert_eq!(position.amount, -10.0);
+        assert_eq!(position.margin, 200.0);
+        assert_eq!(simulator.cash(), 800.0);
+        assert_eq!(simulator.equity(|_| 96.0), 1040.0);
+
+        //buying back more than the short closes it and opens a long
+        simulator.submit(&vec![leveraged(
+            TradeOperator::Buy,
+            12.0,
+            TradeLeverage::X2,
+        )]);
+        simulator.process_candle(|_| Some(candle(90.0, 92.0, 89.0, 91.0)));
+        let position = simulator.position_state(1);
+        assert_eq!(position.amount, 2.0);
+        assert_eq!(position.entry_price, 90.0);
+        assert_eq!(position.margin, 90.0);
+        assert_eq!(simulator.cash(), 1010.0);
+        assert_eq!(simulator.equity(|_| 90.0), 1100.0);
+        assert!(simulator.liquidations().is_empty());
+    }
+
+    #[test]
+    fn test_funding() {
+        let mut simulator = perpetual_simulator(1000.0, 0.01);
+        simulator.submit(&vec![leveraged(TradeOperator::Buy, 1.0, TradeLeverage::X1)]);
+        simulator.process_candle(|_| Some(candle(100.0, 100.0, 100.0, 100.0)));
+        assert_eq!(simulator.funding_paid(), 0.0);
+        //paid on every second candle at the close
+        simulator.process_candle(|_| Some(candle(100.0, 100.0, 100.0, 100.0)));
+        assert_eq!(simulator.funding_paid(), 1.0);
+        assert_eq!(simulator.position_state(1).margin, 99.0);
+        assert_eq!(simulator.equity(|_| 100.0), 999.0);
+
+        //spot portfolios never pay funding
+        let mut spot = free_simulator(1000.0);
+        spot.submit(&vec![leveraged(TradeOperator::Buy, 1.0, TradeLeverage::X5)]);
+        spot.process_candle(|_| Some(candle(100.0, 100.0, 100.0, 100.0)));
+        spot.process_candle(|_| Some(candle(100.0, 100.0, 100.0, 100.0)));
+        assert_eq!(spot.funding_paid(), 0.0);
+        assert_eq!(spot.cash(), 900.0);
+    }
+
+    #[test]
+    fn test_liquidation() {
+        let position = Position {
+            amount: 1.0,
+            entry_price: 100.0,
+            margin: 20.0,
+        };
+        let liquidation_price = position.liquidation_price(0.0).unwrap();
+        assert_eq!(liquidation_price, 80.0);
+        assert!(position.liquidation_price(0.005).unwrap() > liquidation_price);
+
+        let mut simulator = perpetual_simulator(100.0, 0.0);
+        simulator.submit(&vec![
+            leveraged(TradeOperator::Buy, 1.0, TradeLeverage::X5),
+            Trade {
+                index: 2,
+                ..leveraged(TradeOperator::Sell, 1.0, TradeLeverage::X5)
+            },
+        ]);
+        simulator.process_candle(|_| Some(candle(100.0, 101.0, 99.0, 100.0)));
+        assert_eq!(simulator.cash(), 60.0);
+        //the long is liquidated at the low wick, the short survives
+        simulator.process_candle(|_| Some(candle(100.0, 105.0, 75.0, 98.0)));
+        let liquidations = simulator.liquidations();
+        assert_eq!(liquidations.len(), 1);
+        assert_eq!(liquidations[0].market_index, 1);
+        assert_eq!(liquidations[0].margin, 20.0);
+        assert!(liquidations[0].price > 80.0);
+        assert_eq!(simulator.position(1), 0.0);
+        assert_eq!(simulator.position(2), -1.0);
+        //a gap past the liquidation price costs no more than the margin
+        simulator.process_candle(|_| Some(candle(150.0, 150.0, 150.0, 150.0)));
+        assert_eq!(simulator.liquidations()[1].price, 150.0);
+        assert_eq!(simulator.cash(), 60.0);
+        assert_eq!(simulator.equity(|_| 150.0), 60.0);
+    }
+}
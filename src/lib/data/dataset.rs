@@ -0,0 +1,197 @@
+use crate::lib::data::importer::*;
+use crate::lib::op::ticker_store::*;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+///Gap statistics as stored in the manifest
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GapSummary {
+    pub gap_count: usize,
+    pub missing_count: usize,
+    pub longest_gap: usize,
+    pub coverage: f64,
+}
+
+impl From<&GapReport> for GapSummary {
+    fn from(report: &GapReport) -> GapSummary {
+        GapSummary {
+            gap_count: report.gap_count(),
+            missing_count: report.missing_count,
+            longest_gap: report.longest_gap,
+            coverage: report.coverage(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MarketEntry {
+    pub symbol: String,
+    pub interval: String,
+    pub ticker_size_ms: u64,
+    pub start_timestamp_ms: u64,
+    pub end_timestamp_ms: u64,
+    ///candles actually ingested, gap fills excluded
+    pub candle_count: usize,
+    ///candles dropped because an overlapping file already had their open time
+    pub duplicate_count: usize,
+    pub gap_policy: GapPolicy,
+    pub gaps: GapSummary,
+    ///file holding the market's candles, relative to the dataset directory
+    pub file: String,
+    pub source_files: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub markets: Vec<MarketEntry>,
+}
+
+impl Manifest {
+    pub fn get_market(&self, symbol: &str, interval: &str) -> Option<&MarketEntry> {
+        self.markets
+            .iter()
+            .find(|entry| entry.symbol.eq_ignore_ascii_case(symbol) && entry.interval == interval)
+    }
+}
+
+#[derive(Debug)]
+pub enum DatasetError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Csv(csv::Error),
+    Import(ImportError),
+    TickerStore(TickerStoreError),
+    UnknownMarket { symbol: String, interval: String },
+}
+
+impl Display for DatasetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DatasetError::Io(error) => write!(f, "{}", error),
+            DatasetError::Json(error) => write!(f, "invalid manifest, {}", error),
+            DatasetError::Csv(error) => write!(f, "{}", error),
+            DatasetError::Import(error) => write!(f, "{}", error),
+            DatasetError::TickerStore(error) => write!(f, "{}", error),
+            DatasetError::UnknownMarket { symbol, interval } => {
+                write!(f, "no {} {} market in the dataset", symbol, interval)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DatasetError {}
+
+impl From<std::io::Error> for DatasetError {
+    fn from(error: std::io::Error) -> DatasetError {
+        DatasetError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for DatasetError {
+    fn from(error: serde_json::Error) -> DatasetError {
+        DatasetError::Json(error)
+    }
+}
+
+impl From<csv::Error> for DatasetError {
+    fn from(error: csv::Error) -> DatasetError {
+        DatasetError::Csv(error)
+    }
+}
+
+impl From<ImportError> for DatasetError {
+    fn from(error: ImportError) -> DatasetError {
+        DatasetError::Import(error)
+    }
+}
+
+impl From<TickerStoreError> for DatasetError {
+    fn from(error: TickerStoreError) -> DatasetError {
+        DatasetError::TickerStore(error)
+    }
+}
+
+///A directory of one candle file per market plus a manifest describing them
+pub struct Dataset {
+    pub dir: PathBuf,
+    pub manifest: Manifest,
+}
+
+impl Dataset {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Dataset, DatasetError> {
+        let dir = dir.as_ref().to_path_buf();
+        let manifest = serde_json::from_reader(File::open(dir.join(MANIFEST_FILE_NAME))?)?;
+        Ok(Dataset { dir, manifest })
+    }
+
+    pub fn load_market(&self, symbol: &str, interval: &str) -> Result<TickerStore, DatasetError> {
+        let entry = self.manifest.get_market(symbol, interval).ok_or_else(|| {
+            DatasetError::UnknownMarket {
+                symbol: symbol.to_string(),
+                interval: interval.to_string(),
+            }
+        })?;
+        self.load_entry(entry)
+    }
+
+    ///Only real candles are stored, so the store is rebuilt by re-inserting them
+    /// under the gap policy recorded in the manifest
+    pub fn load_entry(&self, entry: &MarketEntry) -> Result<TickerStore, DatasetError> {
+        let import = CandleImporter::new(CandleProfile::named_headers(), RowErrorPolicy::Strict)
+            .read_path(self.dir.join(&entry.file))?;
+        let mut ticker_store = TickerStore::with_gap_policy(
+            entry.ticker_size_ms,
+            entry.start_timestamp_ms,
+            entry.gap_policy,
+        );
+        for candle in &import.candles {
+            ticker_store.insert_ticker(candle.open_time_ms, candle.to_ticker())?;
+        }
+        Ok(ticker_store)
+    }
+}
+
+///Writes the ingested tickers of a store in a layout `CandleProfile::named_headers` reads back,
+/// tickers synthesized for gaps are left out
+pub fn write_ticker_store(
+    ticker_store: &TickerStore,
+    path: impl AsRef<Path>,
+) -> Result<(), DatasetError> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record([
+        "timestamp",
+        "open",
+        "high",
+        "low",
+        "close",
+        "volume",
+        "trades",
+    ])?;
+    for (index, ticker) in ticker_store.tickers().iter().enumerate() {
+        if !ticker_store.is_valid(index) {
+            continue;
+        }
+        let timestamp = ticker_store.start_timestamp() + ticker_store.ticker_size() * index as u64;
+        writer.write_record(&[
+            timestamp.to_string(),
+            ticker.open.to_string(),
+            ticker.high.to_string(),
+            ticker.low.to_string(),
+            ticker.close.to_string(),
+            ticker.volume.to_string(),
+            ticker.trade_count.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn write_manifest(manifest: &Manifest, dir: impl AsRef<Path>) -> Result<(), DatasetError> {
+    let file = File::create(dir.as_ref().join(MANIFEST_FILE_NAME))?;
+    serde_json::to_writer_pretty(file, manifest)?;
+    Ok(())
+}
@@ -0,0 +1,573 @@
+use crate::lib::op::ticker_store::*;
+use barter_data::model::Candle;
+use chrono::{TimeZone, Utc};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TimestampUnit {
+    Seconds,
+    Milliseconds,
+    Microseconds,
+}
+
+impl TimestampUnit {
+    ///microseconds are cut to whole milliseconds, so a close time stays in its candle
+    pub fn to_millis(self, value: f64) -> f64 {
+        match self {
+            TimestampUnit::Seconds => value * 1000.0,
+            TimestampUnit::Milliseconds => value,
+            TimestampUnit::Microseconds => (value / 1000.0).floor(),
+        }
+    }
+}
+
+///A column is either picked by position or by its name in the header row
+#[derive(Clone, Debug, PartialEq)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl Display for Column {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Column::Index(index) => write!(f, "column {}", index),
+            Column::Name(name) => write!(f, "column \"{}\"", name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnMapping {
+    pub open_time: Column,
+    pub open: Column,
+    pub high: Column,
+    pub low: Column,
+    pub close: Column,
+    pub volume: Column,
+    pub close_time: Option<Column>,
+    pub trade_count: Option<Column>,
+}
+
+///Describes the layout of one exchange's candle export
+#[derive(Clone, Debug, PartialEq)]
+pub struct CandleProfile {
+    pub has_headers: bool,
+    pub delimiter: u8,
+    pub timestamp_unit: TimestampUnit,
+    pub columns: ColumnMapping,
+}
+
+impl CandleProfile {
+    ///Binance kline dumps: open time, open, high, low, close, volume, close time,
+    /// quote asset volume, trade count, taker buy base/quote volume, ignore
+    pub fn binance_klines() -> CandleProfile {
+        CandleProfile {
+            has_headers: false,
+            delimiter: b',',
+            timestamp_unit: TimestampUnit::Milliseconds,
+            columns: ColumnMapping {
+                open_time: Column::Index(0),
+                open: Column::Index(1),
+                high: Column::Index(2),
+                low: Column::Index(3),
+                close: Column::Index(4),
+                volume: Column::Index(5),
+                close_time: Some(Column::Index(6)),
+                trade_count: Some(Column::Index(8)),
+            },
+        }
+    }
+
+    ///Coinbase candles: time, low, high, open, close, volume
+    pub fn coinbase() -> CandleProfile {
+        CandleProfile {
+            has_headers: false,
+            delimiter: b',',
+            timestamp_unit: TimestampUnit::Seconds,
+            columns: ColumnMapping {
+                open_time: Column::Index(0),
+                low: Column::Index(1),
+                high: Column::Index(2),
+                open: Column::Index(3),
+                close: Column::Index(4),
+                volume: Column::Index(5),
+                close_time: None,
+                trade_count: None,
+            },
+        }
+    }
+
+    ///Kraken OHLCVT downloads: timestamp, open, high, low, close, volume, trades
+    pub fn kraken_ohlcvt() -> CandleProfile {
+        CandleProfile {
+            has_headers: false,
+            delimiter: b',',
+            timestamp_unit: TimestampUnit::Seconds,
+            columns: ColumnMapping {
+                open_time: Column::Index(0),
+                open: Column::Index(1),
+                high: Column::Index(2),
+                low: Column::Index(3),
+                close: Column::Index(4),
+                volume: Column::Index(5),
+                close_time: None,
+                trade_count: Some(Column::Index(6)),
+            },
+        }
+    }
+
+    ///Any file with a header row naming timestamp, open, high, low, close and volume columns,
+    /// close_time and trades are picked up when present
+    pub fn named_headers() -> CandleProfile {
+        let name = |name: &str| Column::Name(name.to_string());
+        CandleProfile {
+            has_headers: true,
+            delimiter: b',',
+            timestamp_unit: TimestampUnit::Milliseconds,
+            columns: ColumnMapping {
+                open_time: name("timestamp"),
+                open: name("open"),
+                high: name("high"),
+                low: name("low"),
+                close: name("close"),
+                volume: name("volume"),
+                close_time: Some(name("close_time")),
+                trade_count: Some(name("trades")),
+            },
+        }
+    }
+
+    pub fn with_timestamp_unit(mut self, timestamp_unit: TimestampUnit) -> CandleProfile {
+        self.timestamp_unit = timestamp_unit;
+        self
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RowErrorPolicy {
+    ///stop at the first bad row
+    Strict,
+    ///skip bad rows and report them in `CandleImport::errors`
+    Lenient,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImportErrorKind {
+    Io(String),
+    Csv(String),
+    ///a named column isn't in the header row
+    UnknownColumn(Column),
+    MissingField(Column),
+    InvalidNumber {
+        column: Column,
+        value: String,
+    },
+}
+
+///An import failure, `line` is the 1-based line of the file it happened on
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportError {
+    pub line: u64,
+    pub kind: ImportErrorKind,
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            ImportErrorKind::Io(error) => write!(f, "{}", error),
+            ImportErrorKind::Csv(error) => write!(f, "{}", error),
+            ImportErrorKind::UnknownColumn(column) => write!(f, "{} is not in the header", column),
+            ImportErrorKind::MissingField(column) => write!(f, "{} is missing", column),
+            ImportErrorKind::InvalidNumber { column, value } => {
+                write!(f, "{} has invalid number \"{}\"", column, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ImportedCandle {
+    pub open_time_ms: u64,
+    pub close_time_ms: Option<u64>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub trade_count: u64,
+}
+
+impl ImportedCandle {
+    pub fn to_ticker(self) -> Ticker {
+        Ticker {
+            open: self.open as f32,
+            high: self.high as f32,
+            low: self.low as f32,
+            close: self.close as f32,
+            volume: self.volume as f32,
+            trade_count: self.trade_count as f32,
+        }
+    }
+
+    pub fn to_candle(self) -> Candle {
+        Candle {
+            start_timestamp: Utc.timestamp_millis(self.open_time_ms as i64),
+            end_timestamp: Utc
+                .timestamp_millis(self.close_time_ms.unwrap_or(self.open_time_ms) as i64),
+            open: self.open,
+            high: self.high,
+            low: self.low,
+            close: self.close,
+            volume: self.volume,
+            trade_count: self.trade_count,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CandleImport {
+    pub candles: Vec<ImportedCandle>,
+    ///rows skipped under `RowErrorPolicy::Lenient`
+    pub errors: Vec<ImportError>,
+}
+
+impl CandleImport {
+    ///smallest step between consecutive open times, None with fewer than two distinct candles
+    pub fn infer_interval_ms(&self) -> Option<u64> {
+        self.candles
+            .windows(2)
+            .filter_map(|pair| pair[1].open_time_ms.checked_sub(pair[0].open_time_ms))
+            .filter(|step| *step > 0)
+            .min()
+    }
+
+    ///Builds a TickerStore from the imported candles, which must be sorted by open time,
+    /// the ticker size is inferred from the candles unless given
+    pub fn to_ticker_store(
+        &self,
+        ticker_size_ms: Option<u64>,
+        gap_policy: GapPolicy,
+    ) -> Result<TickerStore, TickerStoreError> {
+        let start_timestamp = self.candles.first().map_or(0, |candle| candle.open_time_ms);
+        let ticker_size = ticker_size_ms
+            .or_else(|| self.infer_interval_ms())
+            .unwrap_or(1);
+        let mut ticker_store =
+            TickerStore::with_gap_policy(ticker_size, start_timestamp, gap_policy);
+        for candle in &self.candles {
+            ticker_store.insert_ticker(candle.open_time_ms, candle.to_ticker())?;
+        }
+        Ok(ticker_store)
+    }
+}
+
+pub struct CandleImporter {
+    pub profile: CandleProfile,
+    pub row_error_policy: RowErrorPolicy,
+}
+
+///column positions resolved against the header row
+struct ResolvedColumns {
+    open_time: usize,
+    open: usize,
+    high: usize,
+    low: usize,
+    close: usize,
+    volume: usize,
+    close_time: Option<usize>,
+    trade_count: Option<usize>,
+}
+
+impl CandleImporter {
+    pub fn new(profile: CandleProfile, row_error_policy: RowErrorPolicy) -> CandleImporter {
+        CandleImporter {
+            profile,
+            row_error_policy,
+        }
+    }
+
+    pub fn read_path(&self, path: impl AsRef<Path>) -> Result<CandleImport, ImportError> {
+        let file = File::open(path).map_err(|error| ImportError {
+            line: 0,
+            kind: ImportErrorKind::Io(error.to_string()),
+        })?;
+        self.read(file)
+    }
+
+    pub fn read<R: Read>(&self, reader: R) -> Result<CandleImport, ImportError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(self.profile.has_headers)
+            .delimiter(self.profile.delimiter)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+
+        let headers = if self.profile.has_headers {
+            let headers = reader.headers().map_err(|error| ImportError {
+                line: 1,
+                kind: ImportErrorKind::Csv(error.to_string()),
+            })?;
+            Some(headers.clone())
+        } else {
+            None
+        };
+        let columns = self.resolve_columns(headers.as_ref())?;
+
+        let mut import = CandleImport::default();
+        for record in reader.records() {
+            let row = record
+                .map_err(|error| ImportError {
+                    line: error.position().map_or(0, |position| position.line()),
+                    kind: ImportErrorKind::Csv(error.to_string()),
+                })
+                .and_then(|record| self.parse_record(&record, &columns));
+            match (row, self.row_error_policy) {
+                (Ok(candle), _) => import.candles.push(candle),
+                (Err(error), RowErrorPolicy::Lenient) => import.errors.push(error),
+                (Err(error), RowErrorPolicy::Strict) => return Err(error),
+            }
+        }
+        Ok(import)
+    }
+
+    fn resolve_columns(
+        &self,
+        headers: Option<&csv::StringRecord>,
+    ) -> Result<ResolvedColumns, ImportError> {
+        let resolve = |column: &Column| -> Result<usize, ImportError> {
+            match (column, headers) {
+                (Column::Index(index), _) => Ok(*index),
+                (Column::Name(name), Some(headers)) => headers
+                    .iter()
+                    .position(|header| header.eq_ignore_ascii_case(name))
+                    .ok_or(ImportError {
+                        line: 1,
+                        kind: ImportErrorKind::UnknownColumn(column.clone()),
+                    }),
+                (Column::Name(_), None) => Err(ImportError {
+                    line: 1,
+                    kind: ImportErrorKind::UnknownColumn(column.clone()),
+                }),
+            }
+        };
+        //optional named columns are dropped when the header doesn't have them
+        let resolve_optional = |column: &Option<Column>| match column {
+            Some(column) => match resolve(column) {
+                Ok(index) => Ok(Some(index)),
+                Err(_) if matches!(column, Column::Name(_)) => Ok(None),
+                Err(error) => Err(error),
+            },
+            None => Ok(None),
+        };
+
+        let mapping = &self.profile.columns;
+        Ok(ResolvedColumns {
+            open_time: resolve(&mapping.open_time)?,
+            open: resolve(&mapping.open)?,
+            high: resolve(&mapping.high)?,
+            low: resolve(&mapping.low)?,
+            close: resolve(&mapping.close)?,
+            volume: resolve(&mapping.volume)?,
+            close_time: resolve_optional(&mapping.close_time)?,
+            trade_count: resolve_optional(&mapping.trade_count)?,
+        })
+    }
+
+    fn parse_record(
+        &self,
+        record: &csv::StringRecord,
+        columns: &ResolvedColumns,
+    ) -> Result<ImportedCandle, ImportError> {
+        let line = record.position().map_or(0, |position| position.line());
+        let mapping = &self.profile.columns;
+        let field = |index: usize, column: &Column| -> Result<f64, ImportError> {
+            let value = record.get(index).ok_or(ImportError {
+                line,
+                kind: ImportErrorKind::MissingField(column.clone()),
+            })?;
+            match value.parse::<f64>() {
+                Ok(number) if number.is_finite() => Ok(number),
+                _ => Err(ImportError {
+                    line,
+                    kind: ImportErrorKind::InvalidNumber {
+                        column: column.clone(),
+                        value: value.to_string(),
+                    },
+                }),
+            }
+        };
+        let timestamp = |index: usize, column: &Column| -> Result<u64, ImportError> {
+            let value = field(index, column)?;
+            if value < 0.0 {
+                return Err(ImportError {
+                    line,
+                    kind: ImportErrorKind::InvalidNumber {
+                        column: column.clone(),
+                        value: value.to_string(),
+                    },
+                });
+            }
+            Ok(self.profile.timestamp_unit.to_millis(value).round() as u64)
+        };
+
+        let open_time_ms = timestamp(columns.open_time, &mapping.open_time)?;
+        let open = field(columns.open, &mapping.open)?;
+        let high = field(columns.high, &mapping.high)?;
+        let low = field(columns.low, &mapping.low)?;
+        let close = field(columns.close, &mapping.close)?;
+        let volume = field(columns.volume, &mapping.volume)?;
+        let close_time_ms = match (columns.close_time, &mapping.close_time) {
+            (Some(index), Some(column)) => Some(timestamp(index, column)?),
+            _ => None,
+        };
+        let trade_count = match (columns.trade_count, &mapping.trade_count) {
+            (Some(index), Some(column)) => field(index, column)? as u64,
+            _ => 0,
+        };
+
+        Ok(ImportedCandle {
+            open_time_ms,
+            close_time_ms,
+            open,
+            high,
+            low,
+            close,
+            volume,
+            trade_count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BINANCE: &str = "\
+1608872400000,0.00000851,0.00016800,0.00000851,0.00011519,626269.10000000,1608873299999,68.83460862,4342,259171.40000000,29.09370704,0
+1608873300000,0.00011612,0.00013999,0.00011418,0.00011925,784197.20000000,1608874199999,97.79233721,6356,291204.30000000,36.52849216,0
+";
+
+    #[test]
+    fn test_binance_klines() {
+        let importer = CandleImporter::new(CandleProfile::binance_klines(), RowErrorPolicy::Strict);
+        let import = importer.read(BINANCE.as_bytes()).unwrap();
+        assert_eq!(import.candles.len(), 2);
+        let candle = import.candles[0];
+        assert_eq!(candle.open_time_ms, 1608872400000);
+        assert_eq!(candle.close_time_ms, Some(1608873299999));
+        assert_eq!(candle.high, 0.000168);
+        assert_eq!(candle.close, 0.00011519);
+        assert_eq!(candle.trade_count, 4342);
+        assert_eq!(import.infer_interval_ms(), Some(900000));
+
+        //newer spot archives have their times in microseconds
+        let profile =
+            CandleProfile::binance_klines().with_timestamp_unit(TimestampUnit::Microseconds);
+        let import = CandleImporter::new(profile, RowErrorPolicy::Strict)
+            .read("1608872400000000,1,2,0.5,1.5,10,1608873299999999,0,3,0,0,0\n".as_bytes())
+            .unwrap();
+        assert_eq!(import.candles[0].open_time_ms, 1608872400000);
+        assert_eq!(import.candles[0].close_time_ms, Some(1608873299999));
+    }
+
+    #[test]
+    fn test_coinbase_seconds() {
+        let importer = CandleImporter::new(CandleProfile::coinbase(), RowErrorPolicy::Strict);
+        let import = importer
+            .read("1609459200,1.0,4.0,2.0,3.0,10\n".as_bytes())
+            .unwrap();
+        let candle = import.candles[0];
+        assert_eq!(candle.open_time_ms, 1609459200000);
+        assert_eq!(
+            (candle.open, candle.high, candle.low, candle.close),
+            (2.0, 4.0, 1.0, 3.0)
+        );
+        assert_eq!(candle.close_time_ms, None);
+    }
+
+    #[test]
+    fn test_kraken_ohlcvt() {
+        let importer = CandleImporter::new(CandleProfile::kraken_ohlcvt(), RowErrorPolicy::Strict);
+        let import = importer
+            .read("1609459200,2.0,4.0,1.0,3.0,10,7\n".as_bytes())
+            .unwrap();
+        assert_eq!(import.candles[0].trade_count, 7);
+        assert_eq!(import.candles[0].open, 2.0);
+    }
+
+    #[test]
+    fn test_named_headers() {
+        let csv = "Close,Volume,Timestamp,Open,High,Low\n3,10,1609459200,2,4,1\n";
+        let profile = CandleProfile::named_headers().with_timestamp_unit(TimestampUnit::Seconds);
+        let import = CandleImporter::new(profile, RowErrorPolicy::Strict)
+            .read(csv.as_bytes())
+            .unwrap();
+        let candle = import.candles[0];
+        assert_eq!(candle.open_time_ms, 1609459200000);
+        assert_eq!(
+            (candle.open, candle.high, candle.low, candle.close),
+            (2.0, 4.0, 1.0, 3.0)
+        );
+        assert_eq!(candle.trade_count, 0);
+
+        let error = CandleImporter::new(CandleProfile::named_headers(), RowErrorPolicy::Strict)
+            .read("time,open,high,low,close,volume\n".as_bytes())
+            .unwrap_err();
+        assert_eq!(
+            error.kind,
+            ImportErrorKind::UnknownColumn(Column::Name("timestamp".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_row_error_policy() {
+        let csv = "1609459200,2.0,4.0,1.0,3.0,10,7\n1609459260,abc,4.0,1.0,3.0,10,7\n1609459320,2.0,4.0\n";
+        let error = CandleImporter::new(CandleProfile::kraken_ohlcvt(), RowErrorPolicy::Strict)
+            .read(csv.as_bytes())
+            .unwrap_err();
+        assert_eq!(error.line, 2);
+        assert_eq!(
+            error.kind,
+            ImportErrorKind::InvalidNumber {
+                column: Column::Index(1),
+                value: "abc".to_string()
+            }
+        );
+
+        let import = CandleImporter::new(CandleProfile::kraken_ohlcvt(), RowErrorPolicy::Lenient)
+            .read(csv.as_bytes())
+            .unwrap();
+        assert_eq!(import.candles.len(), 1);
+        assert_eq!(import.errors.len(), 2);
+        assert_eq!(import.errors[1].line, 3);
+        assert_eq!(
+            import.errors[1].kind,
+            ImportErrorKind::MissingField(Column::Index(3))
+        );
+    }
+
+    #[test]
+    fn test_to_ticker_store() {
+        let csv = "60,1,1,1,1,1\n120,2,2,2,2,2\n240,4,4,4,4,4\n";
+        let import = CandleImporter::new(CandleProfile::coinbase(), RowErrorPolicy::Strict)
+            .read(csv.as_bytes())
+            .unwrap();
+        let ticker_store = import
+            .to_ticker_store(None, GapPolicy::ForwardFill)
+            .unwrap();
+        assert_eq!(ticker_store.ticker_size(), 60000);
+        assert_eq!(ticker_store.start_timestamp(), 60000);
+        assert_eq!(ticker_store.get_ticker_count(), 4);
+        assert_eq!(ticker_store.gap_report().missing_count, 1);
+        assert_eq!(ticker_store.get_ticker(180000).close, 2.0);
+
+        assert!(import.to_ticker_store(None, GapPolicy::Reject).is_err());
+    }
+}
@@ -0,0 +1,362 @@
+use crate::lib::data::dataset::*;
+use crate::lib::data::importer::*;
+use crate::lib::op::ticker_store::*;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+///Symbol and interval taken from a Binance archive name such as `1INCHBTC-15m-2020-12.zip`
+#[derive(Clone, Debug, PartialEq)]
+pub struct KlineFileName {
+    pub symbol: String,
+    pub interval: String,
+    pub interval_ms: u64,
+}
+
+///None for `mo`, months have no fixed length so monthly archives end up ignored
+pub fn interval_to_ms(interval: &str) -> Option<u64> {
+    const SECOND: u64 = 1000;
+    const MINUTE: u64 = 60 * SECOND;
+    const HOUR: u64 = 60 * MINUTE;
+    const DAY: u64 = 24 * HOUR;
+    let split = interval.len().checked_sub(1)?;
+    let unit = match &interval[split..] {
+        "s" => SECOND,
+        "m" => MINUTE,
+        "h" => HOUR,
+        "d" => DAY,
+        "w" => 7 * DAY,
+        _ => return None,
+    };
+    let count = &interval[..split];
+    let count = count.parse::<u64>().ok().filter(|count| *count > 0)?;
+    Some(count * unit)
+}
+
+pub fn parse_kline_file_name(file_name: &str) -> Option<KlineFileName> {
+    let stem = file_name
+        .strip_suffix(".csv")
+        .or_else(|| file_name.strip_suffix(".zip"))?;
+    let mut parts = stem.split('-');
+    let symbol = parts.next().filter(|symbol| !symbol.is_empty())?;
+    let interval = parts.next()?;
+    Some(KlineFileName {
+        symbol: symbol.to_uppercase(),
+        interval: interval.to_string(),
+        interval_ms: interval_to_ms(interval)?,
+    })
+}
+
+pub struct IngestOptions {
+    pub profile: CandleProfile,
+    pub row_error_policy: RowErrorPolicy,
+    pub gap_policy: GapPolicy,
+}
+
+impl Default for IngestOptions {
+    fn default() -> IngestOptions {
+        IngestOptions {
+            profile: CandleProfile::binance_klines(),
+            row_error_policy: RowErrorPolicy::Lenient,
+            gap_policy: GapPolicy::ForwardFill,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct IngestReport {
+    pub manifest: Manifest,
+    ///rows skipped under `RowErrorPolicy::Lenient`, with the file they came from
+    pub skipped_rows: Vec<(PathBuf, ImportError)>,
+    ///markets that couldn't be written, e.g. because `GapPolicy::Reject` found a gap
+    pub failed_markets: Vec<(String, DatasetError)>,
+    ///files in the source directory whose name isn't a kline archive name
+    pub ignored_files: Vec<PathBuf>,
+}
+
+///Scans source_dir (recursively) for kline CSVs and zipped CSVs, merges the files of each
+/// symbol and interval, and writes one TickerStore per market plus a manifest into dataset_dir
+pub fn ingest_directory(
+    source_dir: impl AsRef<Path>,
+    dataset_dir: impl AsRef<Path>,
+    options: &IngestOptions,
+) -> Result<IngestReport, DatasetError> {
+    let dataset_dir = dataset_dir.as_ref();
+    std::fs::create_dir_all(dataset_dir)?;
+
+    let mut report = IngestReport::default();
+    //BTreeMap keeps the manifest ordered by symbol then interval
+    let mut markets: BTreeMap<(String, String), (KlineFileName, Vec<PathBuf>)> = BTreeMap::new();
+    for path in list_files(source_dir.as_ref())? {
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        match parse_kline_file_name(file_name) {
+            Some(kline_file) => markets
+                .entry((kline_file.symbol.clone(), kline_file.interval.clone()))
+                .or_insert_with(|| (kline_file, Vec::new()))
+                .1
+                .push(path),
+            None => report.ignored_files.push(path),
+        }
+    }
+
+    let importer = CandleImporter::new(options.profile.clone(), options.row_error_policy);
+    for (_, (kline_file, mut files)) in markets {
+        //archive names end in the date, so name order is chronological order whatever
+        // directory the files are in
+        files.sort_by(|a, b| a.file_name().cmp(&b.file_name()).then_with(|| a.cmp(b)));
+        match ingest_market(
+            &importer,
+            &kline_file,
+            &files,
+            dataset_dir,
+            options,
+            &mut report,
+        ) {
+            Ok(entry) => report.manifest.markets.push(entry),
+            Err(error) => {
+                //a store left by an earlier ingest would no longer match the manifest
+                let stale = dataset_dir.join(format!("{}.csv", market_file_stem(&kline_file)));
+                if let Err(remove_error) = std::fs::remove_file(stale) {
+                    if remove_error.kind() != std::io::ErrorKind::NotFound {
+                        return Err(remove_error.into());
+                    }
+                }
+                report
+                    .failed_markets
+                    .push((market_file_stem(&kline_file), error))
+            }
+        }
+    }
+
+    write_manifest(&report.manifest, dataset_dir)?;
+    Ok(report)
+}
+
+fn market_file_stem(kline_file: &KlineFileName) -> String {
+    format!("{}-{}", kline_file.symbol, kline_file.interval)
+}
+
+fn ingest_market(
+    importer: &CandleImporter,
+    kline_file: &KlineFileName,
+    files: &[PathBuf],
+    dataset_dir: &Path,
+    options: &IngestOptions,
+    report: &mut IngestReport,
+) -> Result<MarketEntry, DatasetError> {
+    let mut import = CandleImport::default();
+    for path in files {
+        for file_import in read_archive(importer, path)? {
+            import.candles.extend(file_import.candles);
+            report.skipped_rows.extend(
+                file_import
+                    .errors
+                    .into_iter()
+                    .map(|error| (path.clone(), error)),
+            );
+        }
+    }
+
+    //a stable sort keeps the first file's candle when overlapping files share an open time
+    import.candles.sort_by_key(|candle| candle.open_time_ms);
+    let candle_count_with_duplicates = import.candles.len();
+    import.candles.dedup_by_key(|candle| candle.open_time_ms);
+    let duplicate_count = candle_count_with_duplicates - import.candles.len();
+
+    let ticker_store = import.to_ticker_store(Some(kline_file.interval_ms), options.gap_policy)?;
+    let file = format!("{}.csv", market_file_stem(kline_file));
+    write_ticker_store(&ticker_store, dataset_dir.join(&file))?;
+
+    Ok(MarketEntry {
+        symbol: kline_file.symbol.clone(),
+        interval: kline_file.interval.clone(),
+        ticker_size_ms: kline_file.interval_ms,
+        start_timestamp_ms: ticker_store.start_timestamp(),
+        end_timestamp_ms: import
+            .candles
+            .last()
+            .map_or(0, |candle| candle.open_time_ms),
+        candle_count: import.candles.len(),
+        duplicate_count,
+        gap_policy: options.gap_policy,
+        gaps: GapSummary::from(&ticker_store.gap_report()),
+        file,
+        source_files: files
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect(),
+    })
+}
+
+///Reads a CSV, or every CSV inside a zip archive
+fn read_archive(importer: &CandleImporter, path: &Path) -> Result<Vec<CandleImport>, DatasetError> {
+    let is_zip = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+    if !is_zip {
+        return Ok(vec![importer.read_path(path)?]);
+    }
+
+    let mut archive = zip::ZipArchive::new(File::open(path)?)
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+    let mut imports = Vec::new();
+    for index in 0..archive.len() {
+        let entry = archive
+            .by_index(index)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+        if entry.name().ends_with(".csv") {
+            imports.push(importer.read(entry)?);
+        }
+    }
+    Ok(imports)
+}
+
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>, DatasetError> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(list_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("cryptosurferator-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn kline_rows(open_times: &[u64], close: f32) -> String {
+        open_times
+            .iter()
+            .map(|open_time| {
+                format!(
+                    "{},1,2,0.5,{},10,{},0,3,0,0,0\n",
+                    open_time,
+                    close,
+                    open_time + 59999
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_kline_file_name() {
+        assert_eq!(
+            parse_kline_file_name("1INCHBTC-15m-2020-12.zip"),
+            Some(KlineFileName {
+                symbol: "1INCHBTC".to_string(),
+                interval: "15m".to_string(),
+                interval_ms: 900000,
+            })
+        );
+        assert_eq!(interval_to_ms("1mo"), None);
+        assert_eq!(parse_kline_file_name("BTCUSDT-1mo-2021-01.csv"), None);
+        assert_eq!(
+            parse_kline_file_name("BTCUSDT-4h-2021-01-05.csv")
+                .unwrap()
+                .interval_ms,
+            14400000
+        );
+        assert_eq!(parse_kline_file_name("notes.txt"), None);
+        assert_eq!(parse_kline_file_name("BTCUSDT-abc-2021-01.csv"), None);
+    }
+
+    #[test]
+    fn test_ingest_directory() {
+        let source_dir = temp_dir("ingest-source");
+        let dataset_dir = temp_dir("ingest-dataset");
+        std::fs::create_dir_all(source_dir.join("nested")).unwrap();
+
+        //two overlapping csv files and a zipped one with a gap before it,
+        // the earliest one is nested so its path sorts last
+        std::fs::write(
+            source_dir.join("nested/ETHBTC-1m-2021-01.csv"),
+            kline_rows(&[0, 60000, 120000], 1.0),
+        )
+        .unwrap();
+        std::fs::write(
+            source_dir.join("ETHBTC-1m-2021-02.csv"),
+            kline_rows(&[120000, 180000], 2.0),
+        )
+        .unwrap();
+        let mut zip =
+            zip::ZipWriter::new(File::create(source_dir.join("ETHBTC-1m-2021-03.zip")).unwrap());
+        zip.start_file("ETHBTC-1m-2021-03.csv", zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(kline_rows(&[300000], 3.0).as_bytes())
+            .unwrap();
+        zip.finish().unwrap();
+        std::fs::write(
+            source_dir.join("LTCBTC-5m-2021-01.csv"),
+            kline_rows(&[0, 300000], 1.0),
+        )
+        .unwrap();
+        std::fs::write(source_dir.join("README.txt"), "").unwrap();
+        //monthly candles have no fixed interval and are reported instead of ingested
+        std::fs::write(
+            source_dir.join("ETHBTC-1mo-2021-01.csv"),
+            kline_rows(&[0], 1.0),
+        )
+        .unwrap();
+
+        let report =
+            ingest_directory(&source_dir, &dataset_dir, &IngestOptions::default()).unwrap();
+        assert_eq!(report.ignored_files.len(), 2);
+        assert!(report.failed_markets.is_empty());
+        assert_eq!(report.manifest.markets.len(), 2);
+
+        let entry = report.manifest.get_market("ETHBTC", "1m").unwrap();
+        assert_eq!(entry.candle_count, 5);
+        assert_eq!(entry.duplicate_count, 1);
+        assert_eq!(entry.source_files.len(), 3);
+        assert_eq!(entry.gaps.missing_count, 1);
+        assert_eq!(entry.end_timestamp_ms, 300000);
+
+        let dataset = Dataset::open(&dataset_dir).unwrap();
+        assert_eq!(dataset.manifest, report.manifest);
+        let ticker_store = dataset.load_market("ETHBTC", "1m").unwrap();
+        assert_eq!(ticker_store.get_ticker_count(), 6);
+        //the first file wins for the overlapping candle
+        assert_eq!(ticker_store.get_ticker(120000).close, 1.0);
+        assert_eq!(ticker_store.get_ticker(180000).close, 2.0);
+        assert!(!ticker_store.is_valid(4));
+        assert_eq!(ticker_store.get_ticker(300000).close, 3.0);
+        assert_eq!(
+            dataset
+                .load_market("LTCBTC", "5m")
+                .unwrap()
+                .get_ticker_count(),
+            2
+        );
+
+        let options = IngestOptions {
+            gap_policy: GapPolicy::Reject,
+            ..IngestOptions::default()
+        };
+        let report = ingest_directory(&source_dir, &dataset_dir, &options).unwrap();
+        assert_eq!(report.failed_markets.len(), 1);
+        assert_eq!(report.failed_markets[0].0, "ETHBTC-1m");
+        assert_eq!(report.manifest.markets.len(), 1);
+        //the store written by the first ingest is gone with its manifest entry
+        assert!(!dataset_dir.join("ETHBTC-1m.csv").exists());
+        assert!(dataset_dir.join("LTCBTC-5m.csv").exists());
+
+        std::fs::remove_dir_all(&source_dir).unwrap();
+        std::fs::remove_dir_all(&dataset_dir).unwrap();
+    }
+}
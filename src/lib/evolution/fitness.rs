@@ -0,0 +1,236 @@
+use crate::lib::backtest::replay::ReplayHistory;
+use crate::lib::backtest::runner::{BacktestConfig, BacktestReport};
+use crate::lib::op::operation::OperationList;
+use std::ops::Range;
+
+///A market subset and time range a program is scored on
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fold {
+    ///market indexes of the full history, renumbered from 0 in this order inside the fold
+    pub markets: Vec<usize>,
+    ///open times from start up to but excluding end, in ms
+    pub timestamps_ms: Range<i64>,
+}
+
+///How the scores of the folds combine into one fitness
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Aggregation {
+    Mean,
+    ///the lowest score
+    WorstCase,
+    Median,
+    ///mean minus the factor times the standard deviation
+    PenalisedVariance(f32),
+}
+
+impl Aggregation {
+    ///NaN when there are no scores or any of them is NaN
+    pub fn aggregate(&self, scores: &[f32]) -> f32 {
+        if scores.is_empty() || scores.iter().any(|score| score.is_nan()) {
+            return f32::NAN;
+        }
+        let mean = scores.iter().sum::<f32>() / scores.len() as f32;
+        match self {
+            Aggregation::Mean => mean,
+            Aggregation::WorstCase => scores.iter().copied().fold(f32::INFINITY, f32::min),
+            Aggregation::Median => {
+                let mut sorted = scores.to_vec();
+                sorted.sort_by(f32::total_cmp);
+                let middle = sorted.len() / 2;
+                if sorted.len().is_multiple_of(2) {
+                    (sorted[middle - 1] + sorted[middle]) / 2.0
+                } else {
+                    sorted[middle]
+                }
+            }
+            Aggregation::PenalisedVariance(factor) => {
+                let variance = scores
+                    .iter()
+                    .map(|score| (score - mean).powi(2))
+                    .sum::<f32>()
+                    / scores.len() as f32;
+                mean - factor * variance.sqrt()
+            }
+        }
+    }
+}
+
+///Every market on its own over count time ranges of equal length covering the history
+pub fn market_time_folds(history: &ReplayHistory, count: usize) -> Vec<Fold> {
+    let (first, last) = match (history.steps.first(), history.steps.last()) {
+        (Some(first), Some(last)) if count > 0 => (first.timestamp_ms, last.timestamp_ms + 1),
+        _ => return Vec::new(),
+    };
+    let bound = |index: usize| first + (last - first) * index as i64 / count as i64;
+    (0..history.symbols.len())
+        .flat_map(|market_index| {
+            (0..count).map(move |index| Fold {
+                markets: vec![market_index],
+                timestamps_ms: bound(index)..bound(index + 1),
+            })
+        })
+        .collect()
+}
+
+///Scores a program on several folds of a history so selection favours programs that hold
+/// up across markets and regimes instead of those fitted to a single one
+pub struct CrossValidation {
+    pub aggregation: Aggregation,
+    pub backtest: BacktestConfig,
+    ///steps before each fold replayed without trading so indicators have history
+    pub warmup_steps: usize,
+    folds: Vec<(Fold, ReplayHistory, Range<usize>)>,
+}
+
+impl CrossValidation {
+    pub fn new(
+        history: &ReplayHistory,
+        folds: Vec<Fold>,
+        aggregation: Aggregation,
+        backtest: BacktestConfig,
+        warmup_steps: usize,
+    ) -> CrossValidation {
+        let folds = folds
+            .into_iter()
+            .map(|fold| {
+                let history = history.select_markets(&fold.markets);
+                let window = history.step_range(fold.timestamps_ms.clone());
+                (fold, history, window)
+            })
+            .collect();
+        CrossValidation {
+            aggregation,
+            backtest,
+            warmup_steps,
+            folds,
+        }
+    }
+
+    pub fn folds(&self) -> impl Iterator<Item = &Fold> {
+        self.folds.iter().map(|(fold, _, _)| fold)
+    }
+
+    ///one backtest per fold, in fold order
+    pub fn reports(&self, program: &OperationList) -> Vec<BacktestReport> {
+        self.folds
+            .iter()
+            .map(|(_, history, window)| {
+                history.run(program, &self.backtest, window.clone(), self.warmup_steps)
+            })
+            .collect()
+    }
+
+    pub fn scores(
+        &self,
+        program: &OperationList,
+        score: impl Fn(&BacktestReport) -> f32,
+    ) -> Vec<f32> {
+        self.reports(program).iter().map(score).collect()
+    }
+
+    ///the aggregated scores, usable as the fitness of an evolver
+    pub fn fitness(&self, program: &OperationList, score: impl Fn(&BacktestReport) -> f32) -> f32 {
+        self.aggregation.aggregate(&self.scores(program, score))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::backtest::replay::ReplayStep;
+    use crate::lib::backtest::runner::StepCandle;
+    use crate::lib::backtest::simulator::SimulatorConfig;
+    use crate::lib::op::operand::Operand;
+    use crate::lib::op::operation::trade::*;
+    use crate::lib::op::operation::Operation;
+    use crate::lib::op::terminal_type::TerminalType;
+    use crate::lib::op::ticker_store::Ticker;
+
+    #[test]
+    fn test_aggregate() {
+        let scores = [3.0, -1.0, 1.0, 5.0];
+        assert_eq!(Aggregation::Mean.aggregate(&scores), 2.0);
+        assert_eq!(Aggregation::WorstCase.aggregate(&scores), -1.0);
+        assert_eq!(Aggregation::Median.aggregate(&scores), 2.0);
+        assert_eq!(Aggregation::Median.aggregate(&scores[..3]), 1.0);
+        //standard deviation of the scores is sqrt(5)
+        let penalised = Aggregation::PenalisedVariance(2.0).aggregate(&scores);
+        assert!((penalised - (2.0 - 2.0 * 5f32.sqrt())).abs() < 1e-5);
+        assert!(Aggregation::Mean.aggregate(&[]).is_nan());
+        assert!(Aggregation::WorstCase.aggregate(&[1.0, f32::NAN]).is_nan());
+    }
+
+    #[test]
+    fn test_cross_validation() {
+        //btc rises, eth falls, both over ten minutes
+        let history = ReplayHistory {
+            symbols: vec!["btcusdt".to_string(), "ethusdt".to_string()],
+            steps: (0..10)
+                .map(|index| {
+                    let timestamp_ms = index as i64 * 60_000;
+                    let candle = |market_index: usize, close: f32| StepCandle {
+                        market_index,
+                        timestamp_ms,
+                        ticker: Ticker {
+                            open: close,
+                            high: close,
+                            low: close,
+                            close,
+                            ..Ticker::default()
+                        },
+                    };
+                    ReplayStep {
+                        timestamp_ms,
+                        candles: vec![
+                            candle(0, 100.0 + index as f32),
+                            candle(1, 100.0 - index as f32),
+                        ],
+                        delisted: Vec::new(),
+                    }
+                })
+                .collect(),
+        };
+        let folds = market_time_folds(&history, 2);
+        assert_eq!(folds.len(), 4);
+        assert_eq!(folds[1].markets, vec![0]);
+        assert_eq!(folds[1].timestamps_ms, 270_000..540_001);
+        assert_eq!(folds[2].markets, vec![1]);
+
+        //every fold holds a single market, renumbered to 0
+        let buy = vec![Operation::Trade((
+            TradeOperator::Buy,
+            Operand::Terminal(TerminalType::MarketIndex(0)),
+            Operand::Terminal(TerminalType::Number(0.0)),
+            Operand::Terminal(TerminalType::Number(1.0)),
+            OrderType::Market,
+            TimeInForce::GoodTilCancelled,
+            Operand::None,
+            TradeLeverage::X1,
+        ))];
+        let backtest = BacktestConfig {
+            simulator: SimulatorConfig {
+                taker_fee: 0.0,
+                maker_fee: 0.0,
+                ..SimulatorConfig::default()
+            },
+            initial_cash: 1000.0,
+            ..BacktestConfig::default()
+        };
+        let mut validation =
+            CrossValidation::new(&history, folds.clone(), Aggregation::Mean, backtest, 2);
+        assert!(validation.folds().eq(folds.iter()));
+        let reports = validation.reports(&buy);
+        assert_eq!(reports[0].equity_curve.len(), 5);
+        assert_eq!(reports[1].equity_curve.len(), 5);
+
+        let scores = validation.scores(&buy, BacktestReport::total_return);
+        assert!(scores[0] > 0.0 && scores[1] > 0.0);
+        assert!(scores[2] < 0.0 && scores[3] < 0.0);
+        let mean = validation.fitness(&buy, BacktestReport::total_return);
+        assert!((mean - scores.iter().sum::<f32>() / 4.0).abs() < 1e-6);
+        validation.aggregation = Aggregation::WorstCase;
+        let worst = validation.fitness(&buy, BacktestReport::total_return);
+        assert!(worst < mean);
+        assert_eq!(worst, scores.iter().copied().fold(f32::INFINITY, f32::min));
+    }
+}
@@ -0,0 +1,317 @@
+use crate::lib::evolution::{compare_fitness, Evolver, EvolverConfig, Individual};
+use crate::lib::op::operation::OperationList;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+///Which islands send migrants to which
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Topology {
+    ///every island to the next one, the last to the first
+    Ring,
+    ///every island to all the others
+    FullyConnected,
+}
+
+impl Topology {
+    ///the islands island sends its migrants to
+    pub fn destinations(&self, island: usize, island_count: usize) -> Vec<usize> {
+        if island_count < 2 {
+            return Vec::new();
+        }
+        match self {
+            Topology::Ring => vec![(island + 1) % island_count],
+            Topology::FullyConnected => (0..island_count)
+                .filter(|destination| *destination != island)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MigrantSelection {
+    Best,
+    Random,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct IslandConfig {
+    ///its generator sets the instructions and terminals the island grows, generations is unused
+    pub evolver: EvolverConfig,
+    ///markets the fitness should score the island's programs on, all of them when empty
+    pub markets: Vec<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct IslandModelConfig {
+    pub islands: Vec<IslandConfig>,
+    pub topology: Topology,
+    ///generations every island evolves for
+    pub generations: usize,
+    ///generations between migrations, 0 keeps the islands apart
+    pub migration_interval: usize,
+    ///individuals every island sends to each of its destinations
+    pub migrants: usize,
+    pub migrant_selection: MigrantSelection,
+}
+
+impl Default for IslandModelConfig {
+    fn default() -> IslandModelConfig {
+        IslandModelConfig {
+            islands: vec![IslandConfig::default(); 4],
+            topology: Topology::Ring,
+            generations: 20,
+            migration_interval: 5,
+            migrants: 2,
+            migrant_selection: MigrantSelection::Best,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Island {
+    pub config: IslandConfig,
+    ///sorted best first
+    pub individuals: Vec<Individual>,
+}
+
+impl Island {
+    fn evolve<F>(&mut self, rng: &mut StdRng, generations: usize, fitness: &F)
+    where
+        F: Fn(&IslandConfig, &OperationList) -> f32,
+    {
+        let evolver = Evolver::new(self.config.evolver.clone());
+        let config = &self.config;
+        for _ in 0..generations {
+            let population = evolver.next_generation(rng, &self.individuals);
+            self.individuals =
+                Evolver::evaluate(population, &mut |program| fitness(config, program));
+        }
+    }
+}
+
+///The best individual of all islands
+pub fn best(islands: &[Island]) -> Option<&Individual> {
+    islands
+        .iter()
+        .filter_map(|island| island.individuals.first())
+        .min_by(|a, b| compare_fitness(a, b))
+}
+
+///Sub-populations evolving side by side on their own threads, exchanging individuals
+/// every migration interval. Runs are reproducible since every island draws from its own
+/// rng seeded by the one handed to evolve.
+pub struct IslandModel {
+    pub config: IslandModelConfig,
+}
+
+impl IslandModel {
+    pub fn new(config: IslandModelConfig) -> IslandModel {
+        IslandModel { config }
+    }
+
+    ///Migrants replace the worst individuals of their destination and are scored again
+    /// by the destination's fitness, since islands may score on different markets
+    fn migrate<F>(&self, rng: &mut impl Rng, islands: &mut [Island], fitness: &F)
+    where
+        F: Fn(&IslandConfig, &OperationList) -> f32,
+    {
+        let emigrants: Vec<Vec<OperationList>> = islands
+            .iter()
+            .map(|island| {
+                let migrants = self.config.migrants.min(island.individuals.len());
+                let chosen: Vec<&Individual> = match self.config.migrant_selection {
+                    MigrantSelection::Best => island.individuals.iter().take(migrants).collect(),
+                    MigrantSelection::Random => {
+                        island.individuals.choose_multiple(rng, migrants).collect()
+                    }
+                };
+                chosen
+                    .into_iter()
+                    .map(|individual| individual.program.clone())
+                    .collect()
+            })
+            .collect();
+
+        let mut immigrants: Vec<Vec<OperationList>> = vec![Vec::new(); islands.len()];
+        for (source, programs) in emigrants.into_iter().enumerate() {
+            for destination in self.config.topology.destinations(source, islands.len()) {
+                immigrants[destination].extend(programs.iter().cloned());
+            }
+        }
+
+        for (island, programs) in islands.iter_mut().zip(immigrants) {
+            let keep = island.individuals.len().saturating_sub(programs.len());
+            island.individuals.truncate(keep);
+            let config = &island.config;
+            island.individuals.extend(
+                Evolver::evaluate(programs, &mut |program| fitness(config, program))
+                    .into_iter()
+                    .take(config.evolver.population_size.saturating_sub(keep)),
+            );
+            island.individuals.sort_by(compare_fitness);
+        }
+    }
+
+    ///the islands after the last generation
+    pub fn evolve<F>(&self, rng: &mut impl Rng, fitness: F) -> Vec<Island>
+    where
+        F: Fn(&IslandConfig, &OperationList) -> f32 + Sync,
+    {
+        let mut rngs: Vec<StdRng> = self
+            .config
+            .islands
+            .iter()
+            .map(|_| StdRng::seed_from_u64(rng.gen()))
+            .collect();
+        let mut islands: Vec<Island> = self
+            .config
+            .islands
+            .iter()
+            .zip(&mut rngs)
+            .map(|(config, rng)| {
+                let population = Evolver::new(config.evolver.clone()).random_population(rng);
+                Island {
+                    config: config.clone(),
+                    individuals: Evolver::evaluate(population, &mut |program| {
+                        fitness(config, program)
+                    }),
+                }
+            })
+            .collect();
+
+        let mut generation = 0;
+        while generation < self.config.generations {
+            let generations = match self.config.migration_interval {
+                0 => self.config.generations - generation,
+                interval => interval.min(self.config.generations - generation),
+            };
+            std::thread::scope(|scope| {
+                for (island, rng) in islands.iter_mut().zip(&mut rngs) {
+                    let fitness = &fitness;
+                    scope.spawn(move || island.evolve(rng, generations, fitness));
+                }
+            });
+            generation += generations;
+            if self.config.migration_interval > 0 && generation < self.config.generations {
+                self.migrate(rng, &mut islands, &fitness);
+            }
+        }
+        islands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::op::environment::Env;
+    use crate::lib::op::operand::Operand;
+    use crate::lib::op::operation::Operation;
+    use crate::lib::op::terminal_type::TerminalType;
+    use crate::lib::op::type_check::evaluate_strict;
+
+    fn value(program: &OperationList) -> f32 {
+        evaluate_strict(program, &mut Vec::new(), &None, &TestEnv {})
+            .map(|value| value.to_f32())
+            .unwrap_or(f32::NAN)
+    }
+
+    fn constant(value: f32) -> Individual {
+        Individual {
+            program: vec![Operation::Identity(Operand::Terminal(
+                TerminalType::Number(value),
+            ))],
+            fitness: value,
+        }
+    }
+
+    #[test]
+    fn test_topology() {
+        assert_eq!(Topology::Ring.destinations(1, 4), vec![2]);
+        assert_eq!(Topology::Ring.destinations(3, 4), vec![0]);
+        assert_eq!(Topology::FullyConnected.destinations(1, 3), vec![0, 2]);
+        assert!(Topology::FullyConnected.destinations(0, 1).is_empty());
+    }
+
+    #[test]
+    fn test_migration_replaces_the_worst() {
+        let island = |values: &[f32], markets: Vec<usize>| Island {
+            config: IslandConfig {
+                evolver: EvolverConfig {
+                    population_size: values.len(),
+                    ..EvolverConfig::default()
+                },
+                markets,
+            },
+            individuals: values.iter().map(|value| constant(*value)).collect(),
+        };
+        let mut islands = vec![
+            island(&[9.0, 5.0, 1.0], vec![0]),
+            island(&[4.0, 3.0, 2.0], vec![1]),
+        ];
+        let model = IslandModel::new(IslandModelConfig {
+            topology: Topology::Ring,
+            migrants: 1,
+            migrant_selection: MigrantSelection::Best,
+            ..IslandModelConfig::default()
+        });
+        //the second island values programs twice as much
+        let fitness = |config: &IslandConfig, program: &OperationList| {
+            value(program) * (1 + config.markets[0]) as f32
+        };
+        model.migrate(&mut StdRng::seed_from_u64(0), &mut islands, &fitness);
+
+        let fitnesses = |island: &Island| -> Vec<f32> {
+            island
+                .individuals
+                .iter()
+                .map(|individual| individual.fitness)
+                .collect()
+        };
+        assert_eq!(fitnesses(&islands[0]), vec![9.0, 5.0, 4.0]);
+        assert_eq!(fitnesses(&islands[1]), vec![18.0, 4.0, 3.0]);
+        assert_eq!(best(&islands).unwrap().fitness, 18.0);
+    }
+
+    #[test]
+    fn test_evolve() {
+        let island = |markets| IslandConfig {
+            evolver: EvolverConfig {
+                population_size: 10,
+                ..EvolverConfig::default()
+            },
+            markets,
+        };
+        let model = IslandModel::new(IslandModelConfig {
+            islands: vec![island(vec![0]), island(vec![1]), island(Vec::new())],
+            topology: Topology::FullyConnected,
+            generations: 5,
+            migration_interval: 2,
+            migrants: 1,
+            migrant_selection: MigrantSelection::Random,
+        });
+        let fitness = |_: &IslandConfig, program: &OperationList| -(value(program) - 42.0).abs();
+        let first = model.evolve(&mut StdRng::seed_from_u64(2), fitness);
+        let second = model.evolve(&mut StdRng::seed_from_u64(2), fitness);
+        assert_eq!(first.len(), 3);
+        for (a, b) in first.iter().zip(&second) {
+            assert_eq!(a.individuals.len(), 10);
+            assert_eq!(a.config.markets, b.config.markets);
+            let fitness = |island: &Island| -> Vec<String> {
+                island
+                    .individuals
+                    .iter()
+                    .map(|individual| format!("{:?}", individual.fitness))
+                    .collect()
+            };
+            assert_eq!(fitness(a), fitness(b));
+            assert!(a
+                .individuals
+                .windows(2)
+                .all(|pair| compare_fitness(&pair[0], &pair[1]) != std::cmp::Ordering::Greater));
+        }
+    }
+
+    struct TestEnv {}
+    impl Env for TestEnv {}
+}
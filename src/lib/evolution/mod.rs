@@ -0,0 +1,354 @@
+pub mod fitness;
+pub mod island;
+pub mod nsga2;
+pub mod walk_forward;
+
+use crate::lib::op::generator::ProgramGenerator;
+use crate::lib::op::operation::OperationList;
+use crate::lib::op::type_check::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::cmp::Ordering;
+
+///A program and its fitness, higher is better
+#[derive(Clone, Debug)]
+pub struct Individual {
+    pub program: OperationList,
+    pub fitness: f32,
+}
+
+///best first, NaN fitness last
+pub fn compare_fitness(a: &Individual, b: &Individual) -> Ordering {
+    match (a.fitness.is_nan(), b.fitness.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => b.fitness.total_cmp(&a.fitness),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EvolverConfig {
+    pub population_size: usize,
+    pub generations: usize,
+    pub tournament_size: usize,
+    pub crossover_probability: f64,
+    pub mutation_probability: f64,
+    ///best individuals copied unchanged into the next generation
+    pub elitism: usize,
+    ///variation that would make a program longer than this keeps the parent instead
+    pub max_program_length: usize,
+    ///type of every program's final instruction
+    pub output: ValueType,
+    pub generator: ProgramGenerator,
+}
+
+impl Default for EvolverConfig {
+    fn default() -> EvolverConfig {
+        EvolverConfig {
+            population_size: 40,
+            generations: 10,
+            tournament_size: 3,
+            crossover_probability: 0.6,
+            mutation_probability: 0.3,
+            elitism: 2,
+            max_program_length: 64,
+            output: ValueType::Number,
+            generator: ProgramGenerator::default(),
+        }
+    }
+}
+
+///Generational linear GP with tournament selection. Programs stay well typed because
+/// variation only ever swaps an instruction for a sub-program of the same output type.
+pub struct Evolver {
+    pub config: EvolverConfig,
+}
+
+impl Evolver {
+    pub fn new(config: EvolverConfig) -> Evolver {
+        Evolver { config }
+    }
+
+    pub fn random_population(&self, rng: &mut impl Rng) -> Vec<OperationList> {
+        (0..self.config.population_size)
+            .map(|_| self.config.generator.generate(rng, self.config.output))
+            .collect()
+    }
+
+    ///sorted best first
+    pub fn evaluate(
+        population: Vec<OperationList>,
+        fitness: &mut impl FnMut(&OperationList) -> f32,
+    ) -> Vec<Individual> {
+        let mut individuals: Vec<Individual> = population
+            .into_iter()
+            .map(|program| Individual {
+                fitness: fitness(&program),
+                program,
+            })
+            .collect();
+        individuals.sort_by(compare_fitness);
+        individuals
+    }
+
+    fn tournament<'a>(&self, rng: &mut impl Rng, individuals: &'a [Individual]) -> &'a Individual {
+        (0..self.config.tournament_size.max(1))
+            .map(|_| {
+                individuals
+                    .choose(rng)
+                    .expect("tournament on an empty population")
+            })
+            .min_by(|a, b| compare_fitness(a, b))
+            .unwrap()
+    }
+
+    ///the elite followed by offspring of tournament winners, individuals has to be sorted best first
+    pub fn next_generation(
+        &self,
+        rng: &mut impl Rng,
+        individuals: &[Individual],
+    ) -> Vec<OperationList> {
+        let mut population: Vec<OperationList> = individuals
+            .iter()
+            .take(self.config.elitism.min(self.config.population_size))
+            .map(|individual| individual.program.clone())
+            .collect();
+        while population.len() < self.config.population_size {
+            population.push(breed(&self.config, rng, |rng| {
+                &self.tournament(rng, individuals).program
+            }));
+        }
+        population
+    }
+
+    ///the final generation sorted best first
+    pub fn evolve(
+        &self,
+        rng: &mut impl Rng,
+        mut fitness: impl FnMut(&OperationList) -> f32,
+    ) -> Vec<Individual> {
+        let mut individuals = Evolver::evaluate(self.random_population(rng), &mut fitness);
+        for _ in 0..self.config.generations {
+            let population = self.next_generation(rng, &individuals);
+            individuals = Evolver::evaluate(population, &mut fitness);
+        }
+        individuals
+    }
+}
+
+///A child of a selected parent, crossed with a second selected parent and mutated
+/// as often as config says
+pub fn breed<'a, R: Rng>(
+    config: &EvolverConfig,
+    rng: &mut R,
+    mut select: impl FnMut(&mut R) -> &'a OperationList,
+) -> OperationList {
+    let mut program = select(rng).clone();
+    if rng.gen_bool(config.crossover_probability) {
+        let donor = select(rng);
+        program = crossover(rng, &program, donor, config.max_program_length);
+    }
+    if rng.gen_bool(config.mutation_probability) {
+        program = mutate(rng, &config.generator, &program, config.max_program_length);
+    }
+    program
+}
+
+///instructions that evaluating the final instruction reaches
+pub fn effective_length(program: &OperationList) -> usize {
+    match program.len().checked_sub(1) {
+        Some(output) => reachable(program, output)
+            .into_iter()
+            .filter(|reached| *reached)
+            .count(),
+        None => 0,
+    }
+}
+
+///Replaces instruction index with replacement, whose last instruction takes its place.
+/// Pointers of the replacement are relative to its own start.
+fn splice(program: &OperationList, index: usize, replacement: &OperationList) -> OperationList {
+    let shift = replacement.len() - 1;
+    let mut spliced = program[..index].to_vec();
+    for operation in replacement {
+        let mut operation = operation.clone();
+        operation.map_pointers(|pointer| pointer + index);
+        spliced.push(operation);
+    }
+    for operation in &program[index + 1..] {
+        let mut operation = operation.clone();
+        operation.map_pointers(|pointer| {
+            if pointer < index {
+                pointer
+            } else {
+                pointer + shift
+            }
+        });
+        spliced.push(operation);
+    }
+    spliced
+}
+
+///the instructions instruction depends on and itself, renumbered from 0
+fn extract(program: &OperationList, instruction: usize) -> OperationList {
+    let reached = reachable(program, instruction);
+    let mut new_index = vec![0; program.len()];
+    let mut extracted = OperationList::new();
+    for (index, operation) in program.iter().enumerate().take(instruction + 1) {
+        if !reached[index] {
+            continue;
+        }
+        new_index[index] = extracted.len();
+        let mut operation = operation.clone();
+        operation.map_pointers(|pointer| new_index.get(pointer).copied().unwrap_or(0));
+        extracted.push(operation);
+    }
+    extracted
+}
+
+///Replaces a random instruction with a freshly generated sub-program of the same type
+pub fn mutate(
+    rng: &mut impl Rng,
+    generator: &ProgramGenerator,
+    program: &OperationList,
+    max_length: usize,
+) -> OperationList {
+    if program.is_empty() {
+        return program.clone();
+    }
+    let types = check_program(program).output_types;
+    let index = rng.gen_range(0..program.len());
+    let replacement = generator.generate(rng, types[index]);
+    if program.len() + replacement.len() - 1 > max_length {
+        return program.clone();
+    }
+    splice(program, index, &replacement)
+}
+
+///Replaces a random instruction of program with the sub-program behind an instruction
+/// of the same type in donor, program is returned unchanged when no types match
+pub fn crossover(
+    rng: &mut impl Rng,
+    program: &OperationList,
+    donor: &OperationList,
+    max_length: usize,
+) -> OperationList {
+    if program.is_empty() || donor.is_empty() {
+        return program.clone();
+    }
+    let types = check_program(program).output_types;
+    let donor_types = check_program(donor).output_types;
+    let index = rng.gen_range(0..program.len());
+    let candidates: Vec<usize> = (0..donor.len())
+        .filter(|donor_index| donor_types[*donor_index] == types[index])
+        .collect();
+    let donor_index = match candidates.choose(rng) {
+        Some(donor_index) => *donor_index,
+        None => return program.clone(),
+    };
+    let replacement = extract(donor, donor_index);
+    if program.len() + replacement.len() - 1 > max_length {
+        return program.clone();
+    }
+    splice(program, index, &replacement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::op::operand::Operand;
+    use crate::lib::op::operation::number::NumOperator;
+    use crate::lib::op::operation::Operation;
+    use crate::lib::op::terminal_type::TerminalType;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn number(value: f32) -> Operand {
+        Operand::Terminal(TerminalType::Number(value))
+    }
+
+    #[test]
+    fn test_splice_and_extract() {
+        let program = vec![
+            Operation::Identity(number(1.0)),
+            Operation::Identity(number(2.0)),
+            Operation::Number((NumOperator::Add, Operand::Pointer(0), Operand::Pointer(1))),
+        ];
+        let replacement = vec![
+            Operation::Identity(number(3.0)),
+            Operation::Number((NumOperator::Multiply, Operand::Pointer(0), number(4.0))),
+        ];
+        let spliced = splice(&program, 1, &replacement);
+        assert_eq!(spliced.len(), 4);
+        assert_eq!(
+            format!("{:?}", spliced[2]),
+            format!(
+                "{:?}",
+                Operation::Number((NumOperator::Multiply, Operand::Pointer(1), number(4.0)))
+            )
+        );
+        assert_eq!(
+            format!("{:?}", spliced[3]),
+            format!(
+                "{:?}",
+                Operation::Number((NumOperator::Add, Operand::Pointer(0), Operand::Pointer(2)))
+            )
+        );
+        assert_eq!(effective_length(&spliced), 4);
+
+        let extracted = extract(&spliced, 2);
+        assert_eq!(extracted.len(), 2);
+        assert_eq!(
+            format!("{:?}", extracted[1]),
+            format!("{:?}", replacement[1])
+        );
+    }
+
+    #[test]
+    fn test_variation_keeps_programs_well_typed() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let generator = ProgramGenerator::default();
+        for _ in 0..100 {
+            let program = generator.generate(&mut rng, ValueType::Number);
+            let donor = generator.generate(&mut rng, ValueType::Bool);
+            for child in [
+                mutate(&mut rng, &generator, &program, 64),
+                crossover(&mut rng, &program, &donor, 64),
+            ] {
+                let report = check_program(&child);
+                assert!(report.is_well_typed(), "{:?}", report.errors);
+                assert_eq!(report.output_types.last(), Some(&ValueType::Number));
+                assert!(child.len() <= 64.max(program.len()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_evolve_improves_fitness() {
+        //prefer programs that evaluate close to 42
+        let fitness = |program: &OperationList| {
+            let mut trade_list = Vec::new();
+            let value = evaluate_strict(program, &mut trade_list, &None, &TestEnv {})
+                .map(|value| value.to_f32())
+                .unwrap_or(f32::NAN);
+            -(value - 42.0).abs()
+        };
+        let evolver = Evolver::new(EvolverConfig {
+            population_size: 30,
+            generations: 8,
+            ..EvolverConfig::default()
+        });
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut first_fitness = fitness;
+        let first = Evolver::evaluate(evolver.random_population(&mut rng), &mut first_fitness);
+        let last = evolver.evolve(&mut StdRng::seed_from_u64(3), fitness);
+        assert!(last[0].fitness >= first[0].fitness);
+        assert!(last
+            .windows(2)
+            .all(|pair| compare_fitness(&pair[0], &pair[1]) != Ordering::Greater));
+    }
+
+    struct TestEnv {}
+    impl crate::lib::op::environment::Env for TestEnv {}
+}
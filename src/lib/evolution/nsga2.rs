@@ -0,0 +1,420 @@
+use crate::lib::backtest::runner::BacktestReport;
+use crate::lib::evolution::{breed, effective_length, EvolverConfig};
+use crate::lib::op::operation::OperationList;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::fmt::Display;
+
+///One of the things a program is judged on
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Objective {
+    Return,
+    MaxDrawdown,
+    TradeCount,
+    EffectiveLength,
+}
+
+impl Display for Objective {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Objective::Return => write!(f, "return"),
+            Objective::MaxDrawdown => write!(f, "max_drawdown"),
+            Objective::TradeCount => write!(f, "trade_count"),
+            Objective::EffectiveLength => write!(f, "effective_length"),
+        }
+    }
+}
+
+impl Objective {
+    pub fn is_maximised(&self) -> bool {
+        matches!(self, Objective::Return)
+    }
+
+    pub fn value(&self, report: &BacktestReport, program: &OperationList) -> f32 {
+        match self {
+            Objective::Return => report.total_return(),
+            Objective::MaxDrawdown => report.max_drawdown(),
+            Objective::TradeCount => report.fills.len() as f32,
+            Objective::EffectiveLength => effective_length(program) as f32,
+        }
+    }
+
+    ///the value turned around for minimised objectives so higher is always better
+    pub fn score(&self, value: f32) -> f32 {
+        if self.is_maximised() {
+            value
+        } else {
+            -value
+        }
+    }
+}
+
+///one score per objective, in the same order
+pub fn objective_scores(
+    objectives: &[Objective],
+    report: &BacktestReport,
+    program: &OperationList,
+) -> Vec<f32> {
+    objectives
+        .iter()
+        .map(|objective| objective.score(objective.value(report, program)))
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct ParetoIndividual {
+    pub program: OperationList,
+    ///higher is better on every score
+    pub scores: Vec<f32>,
+    ///the front the individual is in, 0 for the individuals nothing dominates
+    pub rank: usize,
+    ///how far apart the neighbours in the same front are, infinite on the edges of the front
+    pub crowding_distance: f32,
+}
+
+fn score_key(score: f32) -> f32 {
+    if score.is_nan() {
+        f32::NEG_INFINITY
+    } else {
+        score
+    }
+}
+
+///a is no worse than b on every score and better on at least one, NaN is worse than anything
+pub fn dominates(a: &[f32], b: &[f32]) -> bool {
+    let mut better = false;
+    for (a, b) in a.iter().zip(b) {
+        let (a, b) = (score_key(*a), score_key(*b));
+        if a < b {
+            return false;
+        }
+        better |= a > b;
+    }
+    better
+}
+
+///Indexes of scores grouped into fronts, each front only dominated by the fronts before it
+pub fn non_dominated_fronts(scores: &[Vec<f32>]) -> Vec<Vec<usize>> {
+    let mut dominated_by = vec![0; scores.len()];
+    let mut dominating: Vec<Vec<usize>> = vec![Vec::new(); scores.len()];
+    for a in 0..scores.len() {
+        for b in a + 1..scores.len() {
+            if dominates(&scores[a], &scores[b]) {
+                dominating[a].push(b);
+                dominated_by[b] += 1;
+            } else if dominates(&scores[b], &scores[a]) {
+                dominating[b].push(a);
+                dominated_by[a] += 1;
+            }
+        }
+    }
+
+    let mut fronts = Vec::new();
+    let mut front: Vec<usize> = (0..scores.len())
+        .filter(|index| dominated_by[*index] == 0)
+        .collect();
+    while !front.is_empty() {
+        let mut next = Vec::new();
+        for index in &front {
+            for dominated in &dominating[*index] {
+                dominated_by[*dominated] -= 1;
+                if dominated_by[*dominated] == 0 {
+                    next.push(*dominated);
+                }
+            }
+        }
+        fronts.push(front);
+        front = next;
+    }
+    fronts
+}
+
+///Crowding distance of every member of front, the sum over the scores of the normalised
+/// gap between its neighbours
+pub fn crowding_distances(scores: &[Vec<f32>], front: &[usize]) -> Vec<f32> {
+    let mut distances = vec![0.0; front.len()];
+    let objective_count = front.first().map_or(0, |index| scores[*index].len());
+    let columns = (0..objective_count).map(|objective| {
+        front
+            .iter()
+            .map(|index| score_key(scores[*index][objective]))
+            .collect::<Vec<f32>>()
+    });
+    for column in columns {
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|a, b| column[*a].total_cmp(&column[*b]));
+        let (first, last) = (order[0], order[order.len() - 1]);
+        distances[first] = f32::INFINITY;
+        distances[last] = f32::INFINITY;
+        let range = column[last] - column[first];
+        if !range.is_finite() || range == 0.0 {
+            continue;
+        }
+        for neighbours in order.windows(3) {
+            distances[neighbours[1]] += (column[neighbours[2]] - column[neighbours[0]]) / range;
+        }
+    }
+    distances
+}
+
+///lower rank first, the wider crowding distance among equal ranks
+pub fn crowded_compare(a: &ParetoIndividual, b: &ParetoIndividual) -> Ordering {
+    a.rank
+        .cmp(&b.rank)
+        .then(b.crowding_distance.total_cmp(&a.crowding_distance))
+}
+
+///Ranks programs by non-dominated sorting, sorted by crowded comparison
+pub fn rank(population: Vec<(OperationList, Vec<f32>)>) -> Vec<ParetoIndividual> {
+    let scores: Vec<Vec<f32>> = population
+        .iter()
+        .map(|(_, scores)| scores.clone())
+        .collect();
+    let mut ranks = vec![0; population.len()];
+    let mut distances = vec![0.0; population.len()];
+    for (rank, front) in non_dominated_fronts(&scores).iter().enumerate() {
+        for (index, distance) in front.iter().zip(crowding_distances(&scores, front)) {
+            ranks[*index] = rank;
+            distances[*index] = distance;
+        }
+    }
+    let mut individuals: Vec<ParetoIndividual> = population
+        .into_iter()
+        .enumerate()
+        .map(|(index, (program, scores))| ParetoIndividual {
+            program,
+            scores,
+            rank: ranks[index],
+            crowding_distance: distances[index],
+        })
+        .collect();
+    individuals.sort_by(crowded_compare);
+    individuals
+}
+
+///The individuals of the first front
+pub fn pareto_front(population: &[ParetoIndividual]) -> Vec<&ParetoIndividual> {
+    population
+        .iter()
+        .filter(|individual| individual.rank == 0)
+        .collect()
+}
+
+#[derive(Serialize)]
+struct ExportedProgram {
+    objectives: Vec<(String, f32)>,
+    instructions: Vec<String>,
+}
+
+///Writes the first front as JSON, every program with its objective values and its
+/// instructions in debug format
+pub fn write_pareto_front_json(
+    objectives: &[Objective],
+    population: &[ParetoIndividual],
+    writer: impl std::io::Write,
+) -> serde_json::Result<()> {
+    let exported: Vec<ExportedProgram> = pareto_front(population)
+        .into_iter()
+        .map(|individual| ExportedProgram {
+            objectives: objectives
+                .iter()
+                .zip(&individual.scores)
+                .map(|(objective, score)| (objective.to_string(), objective.score(*score)))
+                .collect(),
+            instructions: individual
+                .program
+                .iter()
+                .map(|operation| format!("{:?}", operation))
+                .collect(),
+        })
+        .collect();
+    serde_json::to_writer_pretty(writer, &exported)
+}
+
+///NSGA-II over typed linear GP programs. Population size, tournament size, variation and
+/// the generator come from config. Its elitism is unused, every generation the survivors
+/// are picked from parents and offspring together.
+pub struct Nsga2 {
+    pub config: EvolverConfig,
+}
+
+impl Nsga2 {
+    pub fn new(config: EvolverConfig) -> Nsga2 {
+        Nsga2 { config }
+    }
+
+    fn tournament<'a>(
+        &self,
+        rng: &mut impl Rng,
+        individuals: &'a [ParetoIndividual],
+    ) -> &'a ParetoIndividual {
+        (0..self.config.tournament_size.max(1))
+            .map(|_| {
+                individuals
+                    .choose(rng)
+                    .expect("tournament on an empty population")
+            })
+            .min_by(|a, b| crowded_compare(a, b))
+            .unwrap()
+    }
+
+    ///the final population sorted by crowded comparison, objectives scores a program
+    /// with higher better on every score
+    pub fn evolve(
+        &self,
+        rng: &mut impl Rng,
+        mut objectives: impl FnMut(&OperationList) -> Vec<f32>,
+    ) -> Vec<ParetoIndividual> {
+        let mut evaluate = |programs: Vec<OperationList>| -> Vec<(OperationList, Vec<f32>)> {
+            programs
+                .into_iter()
+                .map(|program| {
+                    let scores = objectives(&program);
+                    (program, scores)
+                })
+                .collect()
+        };
+        let population: Vec<OperationList> = (0..self.config.population_size)
+            .map(|_| self.config.generator.generate(rng, self.config.output))
+            .collect();
+        let mut individuals = rank(evaluate(population));
+
+        for _ in 0..self.config.generations {
+            if individuals.is_empty() {
+                break;
+            }
+            let offspring: Vec<OperationList> = (0..self.config.population_size)
+                .map(|_| {
+                    breed(&self.config, rng, |rng| {
+                        &self.tournament(rng, &individuals).program
+                    })
+                })
+                .collect();
+            let mut combined: Vec<(OperationList, Vec<f32>)> = individuals
+                .into_iter()
+                .map(|individual| (individual.program, individual.scores))
+                .collect();
+            combined.extend(evaluate(offspring));
+            individuals = rank(combined);
+            individuals.truncate(self.config.population_size);
+        }
+        individuals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::backtest::journal::Journal;
+    use crate::lib::backtest::runner::EquityPoint;
+    use crate::lib::op::environment::Env;
+    use crate::lib::op::type_check::evaluate_strict;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_fronts_and_crowding() {
+        let scores = vec![
+            vec![1.0, 5.0],
+            vec![2.0, 4.0],
+            vec![3.0, 1.0],
+            vec![1.0, 3.0],
+            vec![0.0, 0.0],
+            vec![f32::NAN, 9.0],
+        ];
+        assert!(dominates(&scores[1], &scores[3]));
+        assert!(!dominates(&scores[0], &scores[1]));
+        assert!(!dominates(&scores[0], &scores[0]));
+        //NaN loses to anything, yet 9 beats 5
+        assert!(!dominates(&scores[0], &scores[5]) && !dominates(&scores[5], &scores[0]));
+
+        let fronts = non_dominated_fronts(&scores);
+        assert_eq!(fronts, vec![vec![0, 1, 2, 5], vec![3], vec![4]]);
+
+        let distances = crowding_distances(&scores, &[0, 1, 2]);
+        assert_eq!(distances[0], f32::INFINITY);
+        assert_eq!(distances[2], f32::INFINITY);
+        //neighbours of the middle one are 2 apart on the first score and 4 on the second
+        assert!((distances[1] - (2.0 / 2.0 + 4.0 / 4.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_objectives() {
+        let equity = [100.0, 120.0, 90.0, 110.0];
+        let report = BacktestReport {
+            equity_curve: equity
+                .iter()
+                .map(|equity| EquityPoint {
+                    timestamp_ms: 0,
+                    equity: *equity,
+                    cash: *equity,
+                })
+                .collect(),
+            fills: Vec::new(),
+            liquidations: Vec::new(),
+            journal: Journal::new(),
+            risk_events: Vec::new(),
+            skipped_candles: Vec::new(),
+        };
+        assert!((report.max_drawdown() - 0.25).abs() < 1e-6);
+        let objectives = [
+            Objective::Return,
+            Objective::MaxDrawdown,
+            Objective::TradeCount,
+            Objective::EffectiveLength,
+        ];
+        let scores = objective_scores(&objectives, &report, &Vec::new());
+        assert!((scores[0] - 0.1).abs() < 1e-6);
+        assert!((scores[1] + 0.25).abs() < 1e-6);
+        assert_eq!(&scores[2..], &[0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_evolve_pareto_front() {
+        //close to 42 against short programs
+        let objectives = [Objective::Return, Objective::EffectiveLength];
+        let score = |program: &OperationList| {
+            let value = evaluate_strict(program, &mut Vec::new(), &None, &TestEnv {})
+                .map(|value| value.to_f32())
+                .unwrap_or(f32::NAN);
+            vec![-(value - 42.0).abs(), -(effective_length(program) as f32)]
+        };
+        let nsga2 = Nsga2::new(EvolverConfig {
+            population_size: 20,
+            generations: 5,
+            ..EvolverConfig::default()
+        });
+        let population = nsga2.evolve(&mut StdRng::seed_from_u64(5), score);
+        assert_eq!(population.len(), 20);
+        assert!(population
+            .windows(2)
+            .all(|pair| crowded_compare(&pair[0], &pair[1]) != Ordering::Greater));
+
+        let front = pareto_front(&population);
+        assert!(!front.is_empty());
+        for a in &front {
+            assert_eq!(a.scores, score(&a.program));
+            assert!(front.iter().all(|b| !dominates(&b.scores, &a.scores)));
+        }
+
+        let mut json = Vec::new();
+        write_pareto_front_json(&objectives, &population, &mut json).unwrap();
+        let exported: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        let exported = exported.as_array().unwrap();
+        assert_eq!(exported.len(), front.len());
+        assert_eq!(exported[0]["objectives"][1][0], "effective_length");
+        assert_eq!(
+            exported[0]["objectives"][1][1].as_f64().unwrap() as f32,
+            -front[0].scores[1]
+        );
+        assert_eq!(
+            exported[0]["instructions"].as_array().unwrap().len(),
+            front[0].program.len()
+        );
+    }
+
+    struct TestEnv {}
+    impl Env for TestEnv {}
+}
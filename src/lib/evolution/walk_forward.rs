@@ -0,0 +1,299 @@
+use crate::lib::backtest::replay::ReplayHistory;
+use crate::lib::backtest::runner::{BacktestConfig, BacktestReport, EquityPoint};
+use crate::lib::evolution::{Evolver, EvolverConfig};
+use crate::lib::op::operation::OperationList;
+use rand::Rng;
+use std::ops::Range;
+
+///Window lengths are in replay steps
+#[derive(Clone, Debug)]
+pub struct WalkForwardConfig {
+    pub train_steps: usize,
+    pub test_steps: usize,
+    ///How far each window moves on, 0 gives a single window. Steps below test_steps are
+    /// raised to it, since overlapping test windows would be stitched twice.
+    pub step: usize,
+    ///steps before each window replayed without trading so indicators have history
+    pub warmup_steps: usize,
+    pub backtest: BacktestConfig,
+    pub evolver: EvolverConfig,
+}
+
+impl Default for WalkForwardConfig {
+    fn default() -> WalkForwardConfig {
+        WalkForwardConfig {
+            train_steps: 500,
+            test_steps: 100,
+            step: 100,
+            warmup_steps: 50,
+            backtest: BacktestConfig::default(),
+            evolver: EvolverConfig::default(),
+        }
+    }
+}
+
+///Train and test ranges of every window that fits into len steps
+pub fn windows(len: usize, config: &WalkForwardConfig) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut windows = Vec::new();
+    let mut start = 0;
+    while start + config.train_steps + config.test_steps <= len {
+        let test_start = start + config.train_steps;
+        windows.push((
+            start..test_start,
+            test_start..test_start + config.test_steps,
+        ));
+        if config.step == 0 {
+            break;
+        }
+        start += config.step.max(config.test_steps);
+    }
+    windows
+}
+
+#[derive(Clone, Debug)]
+pub struct WalkForwardWindow {
+    pub train: Range<usize>,
+    pub test: Range<usize>,
+    ///the best program of the last generation evolved on the train window
+    pub champion: OperationList,
+    pub train_fitness: f32,
+    pub test_fitness: f32,
+    pub train_return: f32,
+    pub test_return: f32,
+    pub test_equity: Vec<EquityPoint>,
+}
+
+///How the champions held up out of sample, averaged over the windows
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DegradationStats {
+    pub mean_train_fitness: f32,
+    pub mean_test_fitness: f32,
+    ///train minus test fitness, positive when the champions did worse out of sample
+    pub mean_fitness_degradation: f32,
+    ///Out-of-sample return per step divided by in-sample return per step,
+    /// NaN when the champions made no money in sample
+    pub walk_forward_efficiency: f32,
+    ///share of test windows with a positive return
+    pub profitable_windows: f32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WalkForwardReport {
+    pub windows: Vec<WalkForwardWindow>,
+    ///the test windows chained so each starts with the equity the previous one ended with
+    pub out_of_sample_equity: Vec<EquityPoint>,
+}
+
+fn mean(values: impl Iterator<Item = f32>) -> f32 {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+    if count == 0 {
+        f32::NAN
+    } else {
+        sum / count as f32
+    }
+}
+
+impl WalkForwardReport {
+    pub fn out_of_sample_return(&self) -> f32 {
+        self.windows
+            .iter()
+            .map(|window| 1.0 + window.test_return)
+            .product::<f32>()
+            - 1.0
+    }
+
+    pub fn stats(&self) -> DegradationStats {
+        let windows = || self.windows.iter();
+        let per_step = |total: f32, range: &Range<usize>| total / range.len().max(1) as f32;
+        let train_rate = mean(windows().map(|window| per_step(window.train_return, &window.train)));
+        let test_rate = mean(windows().map(|window| per_step(window.test_return, &window.test)));
+        DegradationStats {
+            mean_train_fitness: mean(windows().map(|window| window.train_fitness)),
+            mean_test_fitness: mean(windows().map(|window| window.test_fitness)),
+            mean_fitness_degradation: mean(
+                windows().map(|window| window.train_fitness - window.test_fitness),
+            ),
+            walk_forward_efficiency: if train_rate > 0.0 {
+                test_rate / train_rate
+            } else {
+                f32::NAN
+            },
+            profitable_windows: mean(
+                windows().map(|window| (window.test_return > 0.0) as u8 as f32),
+            ),
+        }
+    }
+}
+
+///Evolves a champion on every train window with fitness scoring its backtest,
+/// then backtests the champion on the test window that follows
+pub fn walk_forward(
+    rng: &mut impl Rng,
+    history: &ReplayHistory,
+    config: &WalkForwardConfig,
+    fitness: impl Fn(&BacktestReport) -> f32,
+) -> WalkForwardReport {
+    let evolver = Evolver::new(config.evolver.clone());
+    let mut report = WalkForwardReport::default();
+    let mut equity = config.backtest.initial_cash;
+
+    for (train, test) in windows(history.len(), config) {
+        let run = |program: &OperationList, window: &Range<usize>| {
+            history.run(
+                program,
+                &config.backtest,
+                window.clone(),
+                config.warmup_steps,
+            )
+        };
+        let population = evolver.evolve(rng, |program| fitness(&run(program, &train)));
+        let champion = population[0].program.clone();
+        let train_report = run(&champion, &train);
+        let test_report = run(&champion, &test);
+
+        let scale = equity / config.backtest.initial_cash;
+        report
+            .out_of_sample_equity
+            .extend(test_report.equity_curve.iter().map(|point| EquityPoint {
+                equity: point.equity * scale,
+                cash: point.cash * scale,
+                ..*point
+            }));
+        if let Some(last) = report.out_of_sample_equity.last() {
+            equity = last.equity;
+        }
+
+        report.windows.push(WalkForwardWindow {
+            train,
+            test,
+            champion,
+            train_fitness: population[0].fitness,
+            test_fitness: fitness(&test_report),
+            train_return: train_report.total_return(),
+            test_return: test_report.total_return(),
+            test_equity: test_report.equity_curve,
+        });
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::backtest::replay::{ReplayHistory, ReplayStep};
+    use crate::lib::backtest::runner::StepCandle;
+    use crate::lib::backtest::simulator::SimulatorConfig;
+    use crate::lib::op::ticker_store::Ticker;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn history(closes: &[f32]) -> ReplayHistory {
+        ReplayHistory {
+            symbols: vec!["btcusdt".to_string()],
+            steps: closes
+                .iter()
+                .enumerate()
+                .map(|(index, close)| ReplayStep {
+                    timestamp_ms: index as i64 * 60_000,
+                    candles: vec![StepCandle {
+                        market_index: 0,
+                        timestamp_ms: index as i64 * 60_000,
+                        ticker: Ticker {
+                            open: *close,
+                            high: *close,
+                            low: *close,
+                            close: *close,
+                            ..Ticker::default()
+                        },
+                    }],
+                    delisted: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_windows() {
+        let config = WalkForwardConfig {
+            train_steps: 4,
+            test_steps: 2,
+            step: 2,
+            ..WalkForwardConfig::default()
+        };
+        assert_eq!(
+            windows(11, &config),
+            vec![(0..4, 4..6), (2..6, 6..8), (4..8, 8..10)]
+        );
+        assert!(windows(5, &config).is_empty());
+        let overlapping = WalkForwardConfig {
+            step: 1,
+            ..config.clone()
+        };
+        assert_eq!(windows(11, &overlapping), windows(11, &config));
+    }
+
+    #[test]
+    fn test_walk_forward() {
+        let closes: Vec<f32> = (0..30).map(|index| 100.0 + index as f32).collect();
+        let config = WalkForwardConfig {
+            train_steps: 10,
+            test_steps: 5,
+            step: 5,
+            warmup_steps: 3,
+            backtest: BacktestConfig {
+                simulator: SimulatorConfig {
+                    taker_fee: 0.0,
+                    maker_fee: 0.0,
+                    ..SimulatorConfig::default()
+                },
+                ..BacktestConfig::default()
+            },
+            evolver: EvolverConfig {
+                population_size: 12,
+                generations: 1,
+                ..EvolverConfig::default()
+            },
+        };
+        let report = walk_forward(
+            &mut StdRng::seed_from_u64(1),
+            &history(&closes),
+            &config,
+            BacktestReport::total_return,
+        );
+        assert_eq!(report.windows.len(), 4);
+        assert_eq!(report.out_of_sample_equity.len(), 20);
+        for window in &report.windows {
+            assert_eq!(window.train_fitness, window.train_return);
+            assert_eq!(window.test_fitness, window.test_return);
+            let rerun = history(&closes).run(
+                &window.champion,
+                &config.backtest,
+                window.test.clone(),
+                config.warmup_steps,
+            );
+            assert_eq!(rerun.total_return(), window.test_return);
+        }
+        //at least one champion trades on the rising prices
+        assert!(report
+            .out_of_sample_equity
+            .iter()
+            .any(|point| point.equity != 10000.0));
+        //the stitched curve carries each window's final equity into the next
+        let second = &report.windows[1];
+        let carried = report.out_of_sample_equity[4].equity / 10000.0;
+        assert!(
+            (report.out_of_sample_equity[5].equity - second.test_equity[0].equity * carried).abs()
+                < 1e-2
+        );
+        let last = report.out_of_sample_equity.last().unwrap().equity;
+        assert!((last / 10000.0 - 1.0 - report.out_of_sample_return()).abs() < 1e-4);
+
+        let stats = report.stats();
+        assert!(stats.profitable_windows >= 0.0 && stats.profitable_windows <= 1.0);
+        assert!(
+            (stats.mean_fitness_degradation - (stats.mean_train_fitness - stats.mean_test_fitness))
+                .abs()
+                < 1e-6
+        );
+    }
+}
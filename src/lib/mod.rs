@@ -1 +1,4 @@
-pub mod op;
\ No newline at end of file
+pub mod backtest;
+pub mod data;
+pub mod evolution;
+pub mod op;
@@ -1,4 +1,5 @@
-use super::operation::market_data::MarketData;
+use super::operation::market_data::{MarketData, OrderBook};
+use super::operation::number::{ListAlignment, NumericPolicy};
 
 // Operations can call the environment to get information about the outside world
 pub trait Env {
@@ -39,7 +40,28 @@ pub trait Env {
         2
     }
 
-    fn get_market_data(&self,market_index: usize, timestamp_start: f32, duration: f32) -> MarketData {
+    ///how NumOperators line up lists of different lengths
+    fn get_list_alignment(&self) -> ListAlignment {
+        ListAlignment::Shortest
+    }
+
+    ///how NumOperators treat out of domain inputs and non-finite results
+    fn get_numeric_policy(&self) -> NumericPolicy {
+        NumericPolicy::Protected
+    }
+
+    ///the unix time in milliseconds the program is evaluated at
+    fn get_current_timestamp_ms(&self) -> i64 {
+        0
+    }
+
+    ///open time in milliseconds of the market's first candle
+    fn get_market_listing_timestamp_ms(&self, _market_index: usize) -> i64 {
+        0
+    }
+
+    ///candles of a market from timestamp_start, both arguments are in milliseconds
+    fn get_market_data(&self,market_index: usize, timestamp_start: i64, duration: i64) -> MarketData {
         let market_data = MarketData {
             open: vec![1.0, 2.0, 3.0, 4.0, 5.0]
                 .into_iter()
@@ -59,6 +81,27 @@ pub trait Env {
         };
         market_data
     }
+
+    ///A mock book of five levels per side 1% apart around the market price, timestamp
+    /// is ignored. Envs with history return the latest snapshot at or before timestamp,
+    /// in both cases with at most depth levels per side.
+    fn get_order_book(&self, market_index: usize, _timestamp: i64, depth: usize) -> OrderBook {
+        let mid_price = self.get_market_price(market_index);
+        let mut order_book = OrderBook {
+            bid_price: vec![0.99, 0.98, 0.97, 0.96, 0.95]
+                .into_iter()
+                .map(|x| x * mid_price)
+                .collect(),
+            bid_volume: vec![1.0, 2.0, 3.0, 4.0, 5.0],
+            ask_price: vec![1.01, 1.02, 1.03, 1.04, 1.05]
+                .into_iter()
+                .map(|x| x * mid_price)
+                .collect(),
+            ask_volume: vec![1.0, 2.0, 3.0, 4.0, 5.0],
+        };
+        order_book.truncate(depth);
+        order_book
+    }
     
 }
 
@@ -0,0 +1,592 @@
+use crate::lib::op::operand::*;
+use crate::lib::op::operation::boolean::BoolOperator;
+use crate::lib::op::operation::constant::ConstantOperator;
+use crate::lib::op::operation::index::IndexOperator;
+use crate::lib::op::operation::indicator::{IndicatorOperator, IndicatorOutput};
+use crate::lib::op::operation::list_sort::{ListSortOperator, SortDirection};
+use crate::lib::op::operation::list_window::ListWindowOperator;
+use crate::lib::op::operation::market_data::MarketDataOperator;
+use crate::lib::op::operation::num_pick::NumPickOperator;
+use crate::lib::op::operation::number::NumOperator;
+use crate::lib::op::operation::order_book::OrderBookOperator;
+use crate::lib::op::operation::rolling::RollingOperator;
+use crate::lib::op::operation::trade::{OrderType, TimeInForce, TradeLeverage, TradeOperator};
+use crate::lib::op::operation::*;
+use crate::lib::op::terminal_type::*;
+use crate::lib::op::type_check::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+///Grows random programs where every operand has a type its slot accepts,
+/// so everything it generates passes `check_program`.
+/// Operands are filled by reusing an earlier instruction, by a terminal
+/// or by growing a new instruction of the wanted type in front of the current one.
+#[derive(Clone, Debug)]
+pub struct ProgramGenerator {
+    ///No new instructions are grown past this length unless a type can't be a terminal,
+    /// so programs can end up slightly longer
+    pub max_length: usize,
+    ///how many instructions deep an operand may grow new instructions
+    pub max_depth: usize,
+    ///chance an operand reuses an earlier instruction of the right type
+    pub reuse_probability: f64,
+    ///chance an operand that can be a terminal becomes one before max_depth is reached
+    pub terminal_probability: f64,
+    ///range of Number terminals
+    pub number_range: (f32, f32),
+    ///range of Int terminals, used for periods and windows and as the number of candles
+    /// market data looks back over or a timestamp is moved by
+    pub int_range: (i64, i64),
+    ///length of a candle, durations and timestamp offsets are whole candles
+    pub ticker_size_ms: i64,
+}
+
+impl Default for ProgramGenerator {
+    fn default() -> ProgramGenerator {
+        ProgramGenerator {
+            max_length: 32,
+            max_depth: 4,
+            reuse_probability: 0.3,
+            terminal_probability: 0.4,
+            number_range: (-10.0, 10.0),
+            int_range: (1, 64),
+            ticker_size_ms: 60_000,
+        }
+    }
+}
+
+fn choose<T: Clone>(rng: &mut impl Rng, options: &[T]) -> T {
+    options.choose(rng).unwrap().clone()
+}
+
+impl ProgramGenerator {
+    ///a program whose last instruction evaluates to output
+    pub fn generate(&self, rng: &mut impl Rng, output: ValueType) -> OperationList {
+        let mut operation_list = OperationList::new();
+        let mut types = Vec::new();
+        self.grow(rng, &mut operation_list, &mut types, output, 0);
+        operation_list
+    }
+
+    ///a random terminal, None for types only instructions produce
+    pub fn terminal(&self, rng: &mut impl Rng, value_type: ValueType) -> Option<TerminalType> {
+        let (number_min, number_max) = self.number_range;
+        let (int_min, int_max) = self.int_range;
+        match value_type {
+            ValueType::Number => Some(TerminalType::Number(rng.gen_range(number_min..=number_max))),
+            ValueType::Int => Some(TerminalType::Int(rng.gen_range(int_min..=int_max))),
+            ValueType::Bool => Some(TerminalType::Bool(rng.gen())),
+            ValueType::NumberList => {
+                let length = rng.gen_range(1..=8);
+                Some(TerminalType::NumberList(
+                    (0..length)
+                        .map(|_| rng.gen_range(number_min..=number_max))
+                        .collect(),
+                ))
+            }
+            ValueType::Timestamp | ValueType::MarketIndex => None,
+        }
+    }
+
+    ///a whole number of candles in milliseconds
+    fn candles_ms(&self, rng: &mut impl Rng) -> i64 {
+        let (int_min, int_max) = self.int_range;
+        rng.gen_range(int_min..=int_max)
+            .saturating_mul(self.ticker_size_ms)
+    }
+
+    ///a negative duration, so market data is read from the candles before the timestamp
+    pub fn lookback(&self, rng: &mut impl Rng) -> Operand {
+        Operand::Terminal(TerminalType::Int(-self.candles_ms(rng)))
+    }
+
+    ///an operand of one of the accepted types
+    pub fn operand(
+        &self,
+        rng: &mut impl Rng,
+        operation_list: &mut OperationList,
+        types: &mut Vec<ValueType>,
+        accepts: &[ValueType],
+        depth: usize,
+    ) -> Operand {
+        let value_type = choose(rng, accepts);
+        let reusable: Vec<usize> = (0..types.len())
+            .filter(|index| types[*index] == value_type)
+            .collect();
+        if !reusable.is_empty() && rng.gen_bool(self.reuse_probability) {
+            return Operand::Pointer(choose(rng, &reusable));
+        }
+        let stop_growing = depth >= self.max_depth
+            || operation_list.len() >= self.max_length
+            || rng.gen_bool(self.terminal_probability);
+        match self.terminal(rng, value_type) {
+            Some(terminal) if stop_growing || value_type == ValueType::Int => {
+                Operand::Terminal(terminal)
+            }
+            _ => Operand::Pointer(self.grow(rng, operation_list, types, value_type, depth + 1)),
+        }
+    }
+
+    ///appends an instruction evaluating to value_type, after any instructions its operands need,
+    /// and returns its index
+    fn grow(
+        &self,
+        rng: &mut impl Rng,
+        operation_list: &mut OperationList,
+        types: &mut Vec<ValueType>,
+        value_type: ValueType,
+        depth: usize,
+    ) -> usize {
+        let operation = self.operation(rng, operation_list, types, value_type, depth);
+        let known_types: &[ValueType] = types;
+        let output = output_type(&operation, &|operand: &Operand| match operand {
+            Operand::Pointer(pointer) => known_types[*pointer],
+            Operand::Terminal(terminal) => terminal.value_type(),
+            Operand::None => ValueType::Number,
+        });
+        debug_assert_eq!(output, value_type);
+        operation_list.push(operation);
+        types.push(output);
+        operation_list.len() - 1
+    }
+
+    fn operation(
+        &self,
+        rng: &mut impl Rng,
+        operation_list: &mut OperationList,
+        types: &mut Vec<ValueType>,
+        value_type: ValueType,
+        depth: usize,
+    ) -> Operation {
+        //the listing timestamp needs a market index instruction, so only while there is room to grow
+        let can_grow = depth < self.max_depth && operation_list.len() < self.max_length;
+        let mut operand = |rng: &mut _, accepts: &[ValueType]| {
+            self.operand(rng, operation_list, types, accepts, depth)
+        };
+        match value_type {
+            ValueType::Timestamp if can_grow && rng.gen_bool(0.5) => match rng.gen_range(0..2) {
+                0 => Operation::Constant((
+                    ConstantOperator::SelectedMarketListingTimestampMs,
+                    operand(rng, MARKET_INDEX),
+                )),
+                _ => {
+                    let operator = choose(rng, &[NumOperator::Add, NumOperator::Subtract]);
+                    let offset = Operand::Terminal(TerminalType::Int(self.candles_ms(rng)));
+                    Operation::Number((operator, operand(rng, TIMESTAMP), offset))
+                }
+            },
+            ValueType::Timestamp => {
+                Operation::Constant((ConstantOperator::CurrentTimestampMs, Operand::None))
+            }
+            ValueType::MarketIndex => Operation::Constant((
+                choose(
+                    rng,
+                    &[
+                        ConstantOperator::BtcMarketIndex,
+                        ConstantOperator::EthMarketIndex,
+                        ConstantOperator::USDTMarketIndex,
+                    ],
+                ),
+                Operand::None,
+            )),
+            ValueType::Bool => match rng.gen_range(0..3) {
+                0 => {
+                    let operator = choose(
+                        rng,
+                        &[BoolOperator::And, BoolOperator::Or, BoolOperator::Xor],
+                    );
+                    Operation::Bool((operator, operand(rng, BOOL), operand(rng, BOOL)))
+                }
+                1 => Operation::Bool((BoolOperator::Not, operand(rng, BOOL), Operand::None)),
+                _ => {
+                    let operator = choose(
+                        rng,
+                        &[
+                            BoolOperator::Equal,
+                            BoolOperator::NotEqual,
+                            BoolOperator::GreaterThan,
+                            BoolOperator::GreaterThanOrEqual,
+                            BoolOperator::LessThan,
+                            BoolOperator::LessThanOrEqual,
+                        ],
+                    );
+                    Operation::Bool((operator, operand(rng, NUMBER), operand(rng, NUMBER)))
+                }
+            },
+            //Ints are always terminals, this only happens if a caller asks for one
+            ValueType::Int => Operation::Identity(Operand::Terminal(
+                self.terminal(rng, ValueType::Int).unwrap(),
+            )),
+            ValueType::Number => match rng.gen_range(0..7) {
+                0 => {
+                    let operator = random_num_operator(rng);
+                    //two Ints would stay an Int
+                    let right = match operator.exact_type(ValueType::Int, ValueType::Int) {
+                        Some(_) => &[ValueType::Number],
+                        None => NUMBER,
+                    };
+                    Operation::Number((operator, operand(rng, NUMBER), operand(rng, right)))
+                }
+                1 => {
+                    if rng.gen_bool(0.1) {
+                        let quantile = operand(rng, NUMBER);
+                        Operation::NumPick((
+                            NumPickOperator::Quantile,
+                            operand(rng, LIST),
+                            quantile,
+                        ))
+                    } else {
+                        let operator = random_num_pick_operator(rng);
+                        Operation::NumPick((operator, operand(rng, LIST), Operand::None))
+                    }
+                }
+                2 => {
+                    let operator = match rng.gen_range(0..3) {
+                        0 => IndexOperator::First,
+                        1 => IndexOperator::Last,
+                        _ => IndexOperator::Operand(operand(rng, COUNT)),
+                    };
+                    Operation::Index((operator, operand(rng, LIST)))
+                }
+                3 => match rng.gen_range(0..3) {
+                    0 => Operation::Constant((
+                        ConstantOperator::MarketPrice,
+                        operand(rng, MARKET_INDEX),
+                    )),
+                    1 => Operation::Constant((ConstantOperator::PortfolioValue, Operand::None)),
+                    _ => Operation::Constant((random_number_constant(rng), Operand::None)),
+                },
+                4 => Operation::Indicator((
+                    random_indicator_operator(rng),
+                    IndicatorOutput::Latest,
+                    operand(rng, MARKET_INDEX),
+                    operand(rng, TIMESTAMP),
+                    self.lookback(rng),
+                    operand(rng, COUNT),
+                )),
+                5 => Operation::Branch((
+                    operand(rng, BOOL),
+                    operand(rng, &[ValueType::Number]),
+                    operand(rng, &[ValueType::Number]),
+                )),
+                _ => {
+                    let good_for_candles = TimeInForce::GoodForCandles(rng.gen_range(1..=16));
+                    Operation::Trade((
+                        choose(
+                            rng,
+                            &[
+                                TradeOperator::Buy,
+                                TradeOperator::Sell,
+                                TradeOperator::Nothing,
+                            ],
+                        ),
+                        operand(rng, MARKET_INDEX),
+                        operand(rng, NUMBER),
+                        operand(rng, NUMBER),
+                        choose(
+                            rng,
+                            &[
+                                OrderType::Market,
+                                OrderType::Limit,
+                                OrderType::StopMarket,
+                                OrderType::StopLimit,
+                                OrderType::TakeProfit,
+                                OrderType::Oco,
+                            ],
+                        ),
+                        choose(
+                            rng,
+                            &[
+                                TimeInForce::GoodTilCancelled,
+                                TimeInForce::ImmediateOrCancel,
+                                TimeInForce::FillOrKill,
+                                good_for_candles,
+                            ],
+                        ),
+                        operand(rng, NUMBER),
+                        choose(
+                            rng,
+                            &[TradeLeverage::X1, TradeLeverage::X2, TradeLeverage::X5],
+                        ),
+                    ))
+                }
+            },
+            ValueType::NumberList => match rng.gen_range(0..11) {
+                0 => Operation::MarketData((
+                    random_market_data_operator(rng),
+                    operand(rng, MARKET_INDEX),
+                    operand(rng, TIMESTAMP),
+                    self.lookback(rng),
+                )),
+                1 => {
+                    let operator = random_num_operator(rng);
+                    let right = if operator.is_unary() { NUMBER } else { NUMERIC };
+                    Operation::Number((operator, operand(rng, LIST), operand(rng, right)))
+                }
+                2 => {
+                    let operator = choose(
+                        rng,
+                        &[
+                            RollingOperator::Mean,
+                            RollingOperator::Std,
+                            RollingOperator::Min,
+                            RollingOperator::Max,
+                            RollingOperator::ZScore,
+                            RollingOperator::Quantile,
+                            RollingOperator::Skew,
+                            RollingOperator::Kurtosis,
+                            RollingOperator::Correlation,
+                        ],
+                    );
+                    let list = operand(rng, LIST);
+                    let window = operand(rng, COUNT);
+                    let argument = match operator {
+                        RollingOperator::Quantile => operand(rng, NUMBER),
+                        RollingOperator::Correlation => operand(rng, LIST),
+                        _ => Operand::None,
+                    };
+                    Operation::Rolling((operator, list, window, argument))
+                }
+                3 => {
+                    let operator = choose(
+                        rng,
+                        &[
+                            ListWindowOperator::Slice,
+                            ListWindowOperator::Take,
+                            ListWindowOperator::Skip,
+                            ListWindowOperator::Lag,
+                            ListWindowOperator::Diff,
+                            ListWindowOperator::PctChange,
+                        ],
+                    );
+                    let list = operand(rng, LIST);
+                    let count = operand(rng, COUNT);
+                    let end = match operator {
+                        ListWindowOperator::Slice => operand(rng, COUNT),
+                        _ => Operand::None,
+                    };
+                    Operation::ListWindow((operator, list, count, end))
+                }
+                4 => Operation::ListSort((
+                    choose(
+                        rng,
+                        &[
+                            ListSortOperator::Sort,
+                            ListSortOperator::ArgSort,
+                            ListSortOperator::Rank,
+                        ],
+                    ),
+                    choose(rng, &[SortDirection::Ascending, SortDirection::Descending]),
+                    operand(rng, LIST),
+                    Operand::None,
+                )),
+                //element expressions stay Operand::None, a grown expression wouldn't use the element
+                5 => Operation::Map((operand(rng, LIST), Operand::None)),
+                6 => Operation::Filter((operand(rng, LIST), Operand::None)),
+                7 => Operation::Zip((
+                    random_num_operator(rng),
+                    operand(rng, LIST),
+                    operand(rng, LIST),
+                )),
+                8 => Operation::Indicator((
+                    random_indicator_operator(rng),
+                    IndicatorOutput::Series,
+                    operand(rng, MARKET_INDEX),
+                    operand(rng, TIMESTAMP),
+                    self.lookback(rng),
+                    operand(rng, COUNT),
+                )),
+                9 => Operation::MarketSort((Operand::None,)),
+                _ => Operation::OrderBook((
+                    choose(
+                        rng,
+                        &[
+                            OrderBookOperator::BidPrice,
+                            OrderBookOperator::BidVolume,
+                            OrderBookOperator::AskPrice,
+                            OrderBookOperator::AskVolume,
+                        ],
+                    ),
+                    operand(rng, MARKET_INDEX),
+                    operand(rng, TIMESTAMP),
+                    operand(rng, COUNT),
+                )),
+            },
+        }
+    }
+}
+
+pub fn random_num_operator(rng: &mut impl Rng) -> NumOperator {
+    choose(
+        rng,
+        &[
+            NumOperator::Add,
+            NumOperator::Subtract,
+            NumOperator::Multiply,
+            NumOperator::Divide,
+            NumOperator::Modulo,
+            NumOperator::Min,
+            NumOperator::Max,
+            NumOperator::Cos,
+            NumOperator::Sin,
+            NumOperator::Tan,
+            NumOperator::Pow,
+            NumOperator::Log,
+            NumOperator::Abs,
+            NumOperator::Sqrt,
+            NumOperator::Exp,
+            NumOperator::Tanh,
+            NumOperator::Sign,
+            NumOperator::Neg,
+            NumOperator::Floor,
+            NumOperator::Round,
+        ],
+    )
+}
+
+///every pick but Quantile, which needs an operand
+pub fn random_num_pick_operator(rng: &mut impl Rng) -> NumPickOperator {
+    choose(
+        rng,
+        &[
+            NumPickOperator::Average,
+            NumPickOperator::Sum,
+            NumPickOperator::Max,
+            NumPickOperator::Min,
+            NumPickOperator::Med,
+            NumPickOperator::Std,
+            NumPickOperator::Length,
+            NumPickOperator::Iqr,
+            NumPickOperator::Mode,
+            NumPickOperator::Slope,
+            NumPickOperator::RSquared,
+            NumPickOperator::First,
+            NumPickOperator::Last,
+            NumPickOperator::CountAboveZero,
+            NumPickOperator::GeometricMean,
+            NumPickOperator::Autocorrelation,
+        ],
+    )
+}
+
+pub fn random_number_constant(rng: &mut impl Rng) -> ConstantOperator {
+    choose(
+        rng,
+        &[
+            ConstantOperator::Zero,
+            ConstantOperator::One,
+            ConstantOperator::Two,
+            ConstantOperator::Three,
+            ConstantOperator::Four,
+            ConstantOperator::Five,
+            ConstantOperator::Six,
+            ConstantOperator::Seven,
+            ConstantOperator::Eight,
+            ConstantOperator::Nine,
+            ConstantOperator::Ten,
+            ConstantOperator::PI,
+            ConstantOperator::GoldenRatio,
+            ConstantOperator::EulerNumber,
+        ],
+    )
+}
+
+pub fn random_market_data_operator(rng: &mut impl Rng) -> MarketDataOperator {
+    choose(
+        rng,
+        &[
+            MarketDataOperator::Open,
+            MarketDataOperator::High,
+            MarketDataOperator::Low,
+            MarketDataOperator::Close,
+            MarketDataOperator::Volume,
+            MarketDataOperator::TradeCount,
+        ],
+    )
+}
+
+pub fn random_indicator_operator(rng: &mut impl Rng) -> IndicatorOperator {
+    choose(
+        rng,
+        &[
+            IndicatorOperator::Sma,
+            IndicatorOperator::Ema,
+            IndicatorOperator::Wma,
+            IndicatorOperator::Rsi,
+            IndicatorOperator::MacdLine,
+            IndicatorOperator::MacdSignal,
+            IndicatorOperator::MacdHistogram,
+            IndicatorOperator::BollingerUpper,
+            IndicatorOperator::BollingerMiddle,
+            IndicatorOperator::BollingerLower,
+            IndicatorOperator::Atr,
+            IndicatorOperator::StochasticK,
+            IndicatorOperator::StochasticD,
+            IndicatorOperator::WindowedObv,
+            IndicatorOperator::Vwap,
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::op::environment::Env;
+    use crate::lib::op::operation::trade::TradeList;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    struct DefaultEnv {}
+    impl Env for DefaultEnv {}
+
+    #[test]
+    fn test_generated_programs_are_well_typed() {
+        let generator = ProgramGenerator::default();
+        let env = DefaultEnv {};
+        let mut rng = StdRng::seed_from_u64(7);
+        let outputs = [
+            ValueType::Number,
+            ValueType::NumberList,
+            ValueType::Bool,
+            ValueType::Timestamp,
+            ValueType::MarketIndex,
+        ];
+        for round in 0..200 {
+            let output = outputs[round % outputs.len()];
+            let operation_list = generator.generate(&mut rng, output);
+            let report = check_program(&operation_list);
+            assert!(report.is_well_typed(), "{:?}", report.errors);
+            assert_eq!(report.output_types.last(), Some(&output));
+
+            let mut trade_list = TradeList::new();
+            let value = evaluate_strict(&operation_list, &mut trade_list, &None, &env).unwrap();
+            assert_eq!(value.value_type(), output);
+        }
+    }
+
+    #[test]
+    fn test_generation_respects_limits() {
+        let generator = ProgramGenerator {
+            max_length: 1,
+            max_depth: 0,
+            ..ProgramGenerator::default()
+        };
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..50 {
+            //operands of the root are terminals or timestamp and market index constants
+            let operation_list = generator.generate(&mut rng, ValueType::Number);
+            assert!(operation_list.len() <= 3, "{:?}", operation_list);
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_program() {
+        let generator = ProgramGenerator::default();
+        let generate = |seed| {
+            let operation_list =
+                generator.generate(&mut StdRng::seed_from_u64(seed), ValueType::Number);
+            format!("{:?}", operation_list)
+        };
+        assert_eq!(generate(3), generate(3));
+    }
+}
@@ -1,5 +1,8 @@
+pub mod generator;
 pub mod operand;
 pub mod operation;
+pub mod order_book_store;
 pub mod terminal_type;
 pub mod ticker_store;
+pub mod type_check;
 pub mod environment;
@@ -3,6 +3,7 @@ use crate::lib::op::operation::*;
 use crate::lib::op::terminal_type::*;
 
 use super::environment::Env;
+#[derive(Clone, Debug)]
 pub enum Operand {
     Pointer(usize),
     Terminal(TerminalType),
@@ -1,6 +1,7 @@
 use crate::lib::op::operand::*;
 
 //boolean operator that works on two values of the same type
+#[derive(Clone, Debug)]
 pub enum BoolOperator {
     Equal,
     NotEqual,
@@ -1,5 +1,6 @@
 use crate::lib::op::operand::*;
 
+#[derive(Clone, Debug)]
 pub enum ConstantOperator {
     PortfolioValue, //Total value of all assets in usdt
     MarketPrice,    //operand is the index of market
@@ -26,10 +27,12 @@ pub enum ConstantOperator {
     Seven,
     Eight,
     Nine,
-    Ten, 
+    Ten,
+    ///the list element a ListSort key, Map or Filter expression is being evaluated for
+    Element,
     // SentimentValue //sentiment score of an asset
     // CurrentOperationIndex,
     // PreviousOperationIndex,
 }
 
-pub type ConstantOperation = (ConstantOperator, Operand);
\ No newline at end of file
+pub type ConstantOperation = (ConstantOperator, Operand);
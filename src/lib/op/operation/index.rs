@@ -1,4 +1,5 @@
 use crate::lib::op::operand::*;
+#[derive(Clone, Debug)]
 pub enum IndexOperator {
     Last,
     First,
@@ -0,0 +1,417 @@
+use crate::lib::op::operand::*;
+use crate::lib::op::operation::market_data::MarketData;
+
+///Every indicator produces one value per candle once its period has filled,
+/// so the series is shorter than the market data and lines up with it at the most recent candle
+#[derive(Clone, Debug)]
+pub enum IndicatorOperator {
+    Sma,
+    Ema,
+    Wma,
+    ///Wilder's relative strength index, 0 to 100
+    Rsi,
+    ///EMA(period) - EMA(slow), slow is period * 26 / 12 so period 12 gives the usual 12/26/9 MACD
+    MacdLine,
+    ///EMA of the MACD line over period * 9 / 12
+    MacdSignal,
+    MacdHistogram,
+    ///SMA plus two standard deviations
+    BollingerUpper,
+    BollingerMiddle,
+    ///SMA minus two standard deviations
+    BollingerLower,
+    ///Wilder's average true range
+    Atr,
+    ///%K, where the close sits in the period's high-low range, 0 to 100
+    StochasticK,
+    ///%D, the 3 candle SMA of %K
+    StochasticD,
+    ///On-balance volume of the last period candles only. Unlike the cumulative OBV
+    /// its level doesn't depend on how far back the market data starts.
+    WindowedObv,
+    ///volume weighted typical price over the period
+    Vwap,
+}
+
+#[derive(Clone, Debug)]
+pub enum IndicatorOutput {
+    ///the whole indicator series
+    Series,
+    ///only the most recent value, 0 if the market data is shorter than the period
+    Latest,
+}
+
+type MarketIndex = Operand;
+type MarketDataTimestampStart = Operand;
+type MarketDataDuration = Operand;
+type IndicatorPeriod = Operand;
+
+pub type IndicatorOperation = (
+    IndicatorOperator,
+    IndicatorOutput,
+    MarketIndex,
+    MarketDataTimestampStart,
+    MarketDataDuration,
+    IndicatorPeriod,
+);
+
+pub fn compute_indicator(
+    operator: &IndicatorOperator,
+    market_data: &MarketData,
+    period: usize,
+) -> Vec<f32> {
+    let period = period.max(1);
+    let close = &market_data.close;
+    match operator {
+        IndicatorOperator::Sma => sma(close, period),
+        IndicatorOperator::Ema => ema(close, period),
+        IndicatorOperator::Wma => wma(close, period),
+        IndicatorOperator::Rsi => rsi(close, period),
+        IndicatorOperator::MacdLine => macd(close, period).0,
+        IndicatorOperator::MacdSignal => macd(close, period).1,
+        IndicatorOperator::MacdHistogram => macd(close, period).2,
+        IndicatorOperator::BollingerUpper => bollinger(close, period, 2.0).0,
+        IndicatorOperator::BollingerMiddle => sma(close, period),
+        IndicatorOperator::BollingerLower => bollinger(close, period, 2.0).1,
+        IndicatorOperator::Atr => atr(&market_data.high, &market_data.low, close, period),
+        IndicatorOperator::StochasticK => {
+            stochastic_k(&market_data.high, &market_data.low, close, period)
+        }
+        IndicatorOperator::StochasticD => sma(
+            &stochastic_k(&market_data.high, &market_data.low, close, period),
+            3,
+        ),
+        IndicatorOperator::WindowedObv => windowed_obv(close, &market_data.volume, period),
+        IndicatorOperator::Vwap => vwap(
+            &market_data.high,
+            &market_data.low,
+            close,
+            &market_data.volume,
+            period,
+        ),
+    }
+}
+
+pub fn sma(values: &[f32], period: usize) -> Vec<f32> {
+    if period == 0 || values.len() < period {
+        return vec![];
+    }
+    let mut sum: f32 = values[..period].iter().sum();
+    let mut result = vec![sum / period as f32];
+    for i in period..values.len() {
+        sum += values[i] - values[i - period];
+        result.push(sum / period as f32);
+    }
+    result
+}
+
+///seeded with the SMA of the first period values
+pub fn ema(values: &[f32], period: usize) -> Vec<f32> {
+    let seed = match sma(&values[..period.min(values.len())], period).first() {
+        Some(seed) => *seed,
+        None => return vec![],
+    };
+    let alpha = 2.0 / (period as f32 + 1.0);
+    let mut result = vec![seed];
+    for value in &values[period..] {
+        let previous = result[result.len() - 1];
+        result.push(alpha * value + (1.0 - alpha) * previous);
+    }
+    result
+}
+
+///the most recent value weighs period, the oldest 1
+pub fn wma(values: &[f32], period: usize) -> Vec<f32> {
+    if period == 0 || values.len() < period {
+        return vec![];
+    }
+    let weight_sum = (period * (period + 1) / 2) as f32;
+    values
+        .windows(period)
+        .map(|window| {
+            window
+                .iter()
+                .enumerate()
+                .map(|(i, value)| (i + 1) as f32 * value)
+                .sum::<f32>()
+                / weight_sum
+        })
+        .collect()
+}
+
+pub fn rsi(values: &[f32], period: usize) -> Vec<f32> {
+    if period == 0 || values.len() <= period {
+        return vec![];
+    }
+    let changes: Vec<f32> = values.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    let to_rsi = |gain: f32, loss: f32| {
+        if loss == 0.0 && gain == 0.0 {
+            50.0
+        } else if loss == 0.0 {
+            100.0
+        } else {
+            100.0 - 100.0 / (1.0 + gain / loss)
+        }
+    };
+
+    let mut gain = changes[..period].iter().map(|c| c.max(0.0)).sum::<f32>() / period as f32;
+    let mut loss = changes[..period].iter().map(|c| (-c).max(0.0)).sum::<f32>() / period as f32;
+    let mut result = vec![to_rsi(gain, loss)];
+    for change in &changes[period..] {
+        gain = (gain * (period - 1) as f32 + change.max(0.0)) / period as f32;
+        loss = (loss * (period - 1) as f32 + (-change).max(0.0)) / period as f32;
+        result.push(to_rsi(gain, loss));
+    }
+    result
+}
+
+///tail-aligned a - b
+fn subtract_series(a: &[f32], b: &[f32]) -> Vec<f32> {
+    let length = a.len().min(b.len());
+    a[a.len() - length..]
+        .iter()
+        .zip(&b[b.len() - length..])
+        .map(|(a, b)| a - b)
+        .collect()
+}
+
+///(line, signal, histogram)
+pub fn macd(values: &[f32], period: usize) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+    let slow_period =
+        ((period as f32 * 26.0 / 12.0).round() as usize).max(period.saturating_add(1));
+    let signal_period = ((period as f32 * 9.0 / 12.0).round() as usize).max(1);
+    let line = subtract_series(&ema(values, period), &ema(values, slow_period));
+    let signal = ema(&line, signal_period);
+    let histogram = subtract_series(&line, &signal);
+    (line, signal, histogram)
+}
+
+///(upper, lower) bands, using the population standard deviation of each window
+pub fn bollinger(values: &[f32], period: usize, deviations: f32) -> (Vec<f32>, Vec<f32>) {
+    sma(values, period)
+        .iter()
+        .zip(values.windows(period.max(1)))
+        .map(|(mean, window)| {
+            let variance = window
+                .iter()
+                .map(|value| (value - mean).powi(2))
+                .sum::<f32>()
+                / period as f32;
+            let width = deviations * variance.sqrt();
+            (mean + width, mean - width)
+        })
+        .unzip()
+}
+
+pub fn atr(high: &[f32], low: &[f32], close: &[f32], period: usize) -> Vec<f32> {
+    let length = high.len().min(low.len()).min(close.len());
+    //the true range needs the previous close, so it starts at the second candle
+    let true_range: Vec<f32> = (1..length)
+        .map(|i| {
+            (high[i] - low[i])
+                .max((high[i] - close[i - 1]).abs())
+                .max((low[i] - close[i - 1]).abs())
+        })
+        .collect();
+    if period == 0 || true_range.len() < period {
+        return vec![];
+    }
+    let mut average = true_range[..period].iter().sum::<f32>() / period as f32;
+    let mut result = vec![average];
+    for range in &true_range[period..] {
+        average = (average * (period - 1) as f32 + range) / period as f32;
+        result.push(average);
+    }
+    result
+}
+
+///50 when the period's high and low are equal
+pub fn stochastic_k(high: &[f32], low: &[f32], close: &[f32], period: usize) -> Vec<f32> {
+    let length = high.len().min(low.len()).min(close.len());
+    if period == 0 || length < period {
+        return vec![];
+    }
+    (period - 1..length)
+        .map(|i| {
+            let window = i + 1 - period..=i;
+            let highest = high[window.clone()]
+                .iter()
+                .copied()
+                .fold(f32::MIN, f32::max);
+            let lowest = low[window].iter().copied().fold(f32::MAX, f32::min);
+            if highest == lowest {
+                50.0
+            } else {
+                100.0 * (close[i] - lowest) / (highest - lowest)
+            }
+        })
+        .collect()
+}
+
+///volume counts up when a candle closed above the one before and down when below,
+/// summed over period candles
+pub fn windowed_obv(close: &[f32], volume: &[f32], period: usize) -> Vec<f32> {
+    let length = close.len().min(volume.len());
+    let signed_volume: Vec<f32> = (1..length)
+        .map(|i| {
+            if close[i] > close[i - 1] {
+                volume[i]
+            } else if close[i] < close[i - 1] {
+                -volume[i]
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    sma(&signed_volume, period)
+        .into_iter()
+        .map(|average| average * period as f32)
+        .collect()
+}
+
+///the typical price is (high + low + close) / 3, a window without volume gives its last typical price
+pub fn vwap(high: &[f32], low: &[f32], close: &[f32], volume: &[f32], period: usize) -> Vec<f32> {
+    let length = high.len().min(low.len()).min(close.len()).min(volume.len());
+    if period == 0 || length < period {
+        return vec![];
+    }
+    let typical_price: Vec<f32> = (0..length)
+        .map(|i| (high[i] + low[i] + close[i]) / 3.0)
+        .collect();
+    (period - 1..length)
+        .map(|i| {
+            let window = i + 1 - period..=i;
+            let total_volume: f32 = volume[window.clone()].iter().sum();
+            if total_volume == 0.0 {
+                typical_price[i]
+            } else {
+                window.map(|j| typical_price[j] * volume[j]).sum::<f32>() / total_volume
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Vec<f32>, expected: Vec<f32>) {
+        assert_eq!(
+            actual.len(),
+            expected.len(),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+        for (a, e) in actual.iter().zip(&expected) {
+            assert!((a - e).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    const HIGH: [f32; 4] = [2.0, 3.0, 6.0, 4.0];
+    const LOW: [f32; 4] = [1.0, 1.0, 2.0, 3.0];
+    const CLOSE: [f32; 4] = [1.5, 2.0, 5.0, 3.5];
+
+    #[test]
+    fn test_moving_averages() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_close(sma(&values, 3), vec![2.0, 3.0, 4.0]);
+        assert_close(ema(&values, 3), vec![2.0, 3.0, 4.0]);
+        assert_close(ema(&[2.0, 4.0, 8.0], 1), vec![2.0, 4.0, 8.0]);
+        assert_close(wma(&values, 3), vec![14.0 / 6.0, 20.0 / 6.0, 26.0 / 6.0]);
+        assert_close(sma(&values, 6), vec![]);
+        assert_close(ema(&[], 3), vec![]);
+    }
+
+    #[test]
+    fn test_rsi() {
+        assert_close(rsi(&[1.0, 2.0, 3.0, 2.0, 3.0], 2), vec![100.0, 50.0, 75.0]);
+        assert_close(rsi(&[1.0, 1.0, 1.0], 2), vec![50.0]);
+        assert_close(rsi(&[1.0, 2.0], 2), vec![]);
+    }
+
+    #[test]
+    fn test_macd() {
+        let (line, signal, histogram) = macd(&[3.0; 40], 12);
+        assert_eq!(line.len(), 40 - 26 + 1);
+        assert_eq!(signal.len(), line.len() - 9 + 1);
+        assert_eq!(histogram.len(), signal.len());
+        assert!(line
+            .iter()
+            .chain(&signal)
+            .chain(&histogram)
+            .all(|v| v.abs() < 1e-5));
+
+        let values: Vec<f32> = (0..40).map(|i| i as f32).collect();
+        let (line, signal, histogram) = macd(&values, 12);
+        //on a straight line the fast EMA leads the slow one by the difference in their lags
+        assert!((line[line.len() - 1] - 7.0).abs() < 1e-3);
+        assert_close(histogram, subtract_series(&line, &signal));
+    }
+
+    #[test]
+    fn test_bollinger() {
+        let (upper, lower) = bollinger(&[1.0, 3.0, 3.0], 2, 2.0);
+        assert_close(upper, vec![4.0, 3.0]);
+        assert_close(lower, vec![0.0, 3.0]);
+    }
+
+    #[test]
+    fn test_atr_and_stochastic() {
+        assert_close(atr(&HIGH, &LOW, &CLOSE, 2), vec![3.0, 2.5]);
+        assert_close(stochastic_k(&HIGH, &LOW, &CLOSE, 2), vec![50.0, 80.0, 37.5]);
+        assert_close(stochastic_k(&[1.0], &[1.0], &[1.0], 1), vec![50.0]);
+    }
+
+    #[test]
+    fn test_volume_indicators() {
+        assert_close(
+            windowed_obv(
+                &[1.0, 2.0, 1.0, 1.0, 3.0],
+                &[10.0, 20.0, 30.0, 40.0, 50.0],
+                2,
+            ),
+            vec![-10.0, -30.0, 50.0],
+        );
+        let prices = [1.0, 2.0, 3.0];
+        assert_close(
+            vwap(&prices, &prices, &prices, &[1.0, 1.0, 2.0], 2),
+            vec![1.5, 8.0 / 3.0],
+        );
+        assert_close(vwap(&prices, &prices, &prices, &[0.0; 3], 3), vec![3.0]);
+    }
+
+    #[test]
+    fn test_huge_period() {
+        let market_data = MarketData {
+            high: HIGH.to_vec(),
+            low: LOW.to_vec(),
+            close: CLOSE.to_vec(),
+            volume: vec![1.0; 4],
+            ..MarketData::default()
+        };
+        //a Number(1e30) period operand saturates to usize::MAX
+        for operator in [
+            IndicatorOperator::Sma,
+            IndicatorOperator::Ema,
+            IndicatorOperator::Wma,
+            IndicatorOperator::Rsi,
+            IndicatorOperator::MacdLine,
+            IndicatorOperator::MacdSignal,
+            IndicatorOperator::MacdHistogram,
+            IndicatorOperator::BollingerUpper,
+            IndicatorOperator::BollingerMiddle,
+            IndicatorOperator::BollingerLower,
+            IndicatorOperator::Atr,
+            IndicatorOperator::StochasticK,
+            IndicatorOperator::StochasticD,
+            IndicatorOperator::WindowedObv,
+            IndicatorOperator::Vwap,
+        ] {
+            assert_close(
+                compute_indicator(&operator, &market_data, usize::MAX),
+                vec![],
+            );
+        }
+    }
+}
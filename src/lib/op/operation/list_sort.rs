@@ -0,0 +1,115 @@
+use crate::lib::op::operand::*;
+use std::cmp::Ordering;
+
+#[derive(Clone, Debug)]
+pub enum ListSortOperator {
+    ///the elements in sorted order
+    Sort,
+    ///the indices that would sort the list
+    ArgSort,
+    ///the position each element would take in the sorted list
+    Rank,
+}
+
+#[derive(Clone, Debug)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+type NumberList = Operand;
+///evaluated once per element with the element as context,
+/// Operand::None sorts the elements by their own value
+type SortKey = Operand;
+
+pub type ListSortOperation = (ListSortOperator, SortDirection, NumberList, SortKey);
+
+///Stable argsort, equal keys keep their original order and NaN keys go last in both directions
+pub fn arg_sort(keys: &[f32], direction: &SortDirection) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..keys.len()).collect();
+    indices.sort_by(|a, b| {
+        let (key_a, key_b) = (keys[*a], keys[*b]);
+        match (key_a.is_nan(), key_b.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => match direction {
+                SortDirection::Ascending => key_a.total_cmp(&key_b),
+                SortDirection::Descending => key_b.total_cmp(&key_a),
+            },
+        }
+    });
+    indices
+}
+
+///keys must have one entry per element of list
+pub fn sort_list(
+    operator: &ListSortOperator,
+    direction: &SortDirection,
+    list: &[f32],
+    keys: &[f32],
+) -> Vec<f32> {
+    let order = arg_sort(keys, direction);
+    match operator {
+        ListSortOperator::Sort => order.iter().map(|index| list[*index]).collect(),
+        ListSortOperator::ArgSort => order.iter().map(|index| *index as f32).collect(),
+        ListSortOperator::Rank => {
+            let mut ranks = vec![0.0; order.len()];
+            for (rank, index) in order.iter().enumerate() {
+                ranks[*index] = rank as f32;
+            }
+            ranks
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_list() {
+        let list = vec![3.0, f32::NAN, 1.0, 2.0, 1.0];
+        let sorted = sort_list(
+            &ListSortOperator::Sort,
+            &SortDirection::Ascending,
+            &list,
+            &list,
+        );
+        assert_eq!(sorted[..4], [1.0, 1.0, 2.0, 3.0]);
+        assert!(sorted[4].is_nan());
+
+        let sorted = sort_list(
+            &ListSortOperator::Sort,
+            &SortDirection::Descending,
+            &list,
+            &list,
+        );
+        assert_eq!(sorted[..4], [3.0, 2.0, 1.0, 1.0]);
+        assert!(sorted[4].is_nan());
+
+        //ties keep their original order
+        assert_eq!(
+            sort_list(
+                &ListSortOperator::ArgSort,
+                &SortDirection::Ascending,
+                &list,
+                &list
+            ),
+            vec![2.0, 4.0, 3.0, 0.0, 1.0]
+        );
+        assert_eq!(
+            sort_list(
+                &ListSortOperator::Rank,
+                &SortDirection::Ascending,
+                &list,
+                &list
+            ),
+            vec![3.0, 4.0, 0.0, 2.0, 1.0]
+        );
+        assert_eq!(
+            sort_list(&ListSortOperator::Sort, &SortDirection::Ascending, &[], &[]),
+            Vec::<f32>::new()
+        );
+    }
+}
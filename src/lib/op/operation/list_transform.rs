@@ -0,0 +1,101 @@
+use crate::lib::op::operand::*;
+use crate::lib::op::operation::number::*;
+
+type NumberList = Operand;
+///evaluated once per element with the element as context,
+/// Operand::None stands for the element itself
+type ElementExpression = Operand;
+
+///replaces every element with the value of the expression
+pub type MapOperation = (NumberList, ElementExpression);
+///keeps the elements the expression is truthy for
+pub type FilterOperation = (NumberList, ElementExpression);
+///element-wise arithmetic between two lists lined up by `Env::get_list_alignment`,
+/// unlike Operation::Number a number operand is treated as a one element list
+pub type ZipOperation = (NumOperator, NumberList, NumberList);
+
+///Element-wise arithmetic between two lists aligned at their last element,
+/// the alignment decides what happens to the longer list's extra elements
+pub fn apply_to_lists(
+    operator: &NumOperator,
+    left: &[f32],
+    right: &[f32],
+    alignment: ListAlignment,
+    policy: NumericPolicy,
+) -> Vec<f32> {
+    let function = operator.func(policy);
+    let length = match alignment {
+        _ if left.is_empty() || right.is_empty() => 0,
+        ListAlignment::Shortest => left.len().min(right.len()),
+        ListAlignment::Longest => left.len().max(right.len()),
+        ListAlignment::Strict if left.len() != right.len() => 0,
+        ListAlignment::Strict => left.len(),
+    };
+    //element i of the result lines up with element i + list.len() - length of each list,
+    // which saturates to the first element for a padded list
+    let element = |list: &[f32], i: usize| list[(i + list.len()).saturating_sub(length)];
+    (0..length)
+        .map(|i| function(element(left, i), element(right, i)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_to_lists() {
+        let long = [1.0, 2.0, 3.0, 4.0];
+        let short = [10.0, 20.0];
+        assert_eq!(
+            apply_to_lists(
+                &NumOperator::Add,
+                &long,
+                &short,
+                ListAlignment::Shortest,
+                NumericPolicy::Ieee
+            ),
+            vec![13.0, 24.0]
+        );
+        assert_eq!(
+            apply_to_lists(
+                &NumOperator::Add,
+                &short,
+                &long,
+                ListAlignment::Longest,
+                NumericPolicy::Ieee
+            ),
+            vec![11.0, 12.0, 13.0, 24.0]
+        );
+        assert_eq!(
+            apply_to_lists(
+                &NumOperator::Add,
+                &long,
+                &short,
+                ListAlignment::Strict,
+                NumericPolicy::Ieee
+            ),
+            Vec::<f32>::new()
+        );
+        assert_eq!(
+            apply_to_lists(
+                &NumOperator::Multiply,
+                &short,
+                &short,
+                ListAlignment::Strict,
+                NumericPolicy::Ieee
+            ),
+            vec![100.0, 400.0]
+        );
+        assert_eq!(
+            apply_to_lists(
+                &NumOperator::Add,
+                &[],
+                &long,
+                ListAlignment::Longest,
+                NumericPolicy::Ieee
+            ),
+            Vec::<f32>::new()
+        );
+    }
+}
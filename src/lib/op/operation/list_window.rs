@@ -0,0 +1,138 @@
+use crate::lib::op::operand::*;
+
+///Counts and positions are clamped to the list, so none of these can index out of bounds.
+/// Lists are series ordered oldest to newest.
+#[derive(Clone, Debug)]
+pub enum ListWindowOperator {
+    ///elements from start up to but excluding end, negative positions count back from the end
+    Slice,
+    ///the last n elements
+    Take,
+    ///drops the first n elements
+    Skip,
+    ///the series as it was k steps ago, i.e. without its last k elements
+    Lag,
+    ///x[i] - x[i - k]
+    Diff,
+    ///x[i] / x[i - k] - 1, with 0 where x[i - k] is 0
+    PctChange,
+}
+
+type ListWindowList = Operand;
+///n or k, the start for Slice
+type ListWindowCount = Operand;
+///the end for Slice, Operand::None for the others
+type ListWindowEnd = Operand;
+
+pub type ListWindowOperation = (
+    ListWindowOperator,
+    ListWindowList,
+    ListWindowCount,
+    ListWindowEnd,
+);
+
+///negative and NaN counts become 0
+fn clamp_count(count: f32, len: usize) -> usize {
+    if count > 0.0 {
+        (count as usize).min(len)
+    } else {
+        0
+    }
+}
+
+fn clamp_position(position: f32, len: usize) -> usize {
+    if position.is_nan() {
+        0
+    } else if position < 0.0 {
+        len.saturating_sub((-position) as usize)
+    } else {
+        (position as usize).min(len)
+    }
+}
+
+pub fn slice(list: &[f32], start: f32, end: f32) -> Vec<f32> {
+    let start = clamp_position(start, list.len());
+    let end = clamp_position(end, list.len());
+    if start >= end {
+        vec![]
+    } else {
+        list[start..end].to_vec()
+    }
+}
+
+pub fn take(list: &[f32], count: f32) -> Vec<f32> {
+    list[list.len() - clamp_count(count, list.len())..].to_vec()
+}
+
+pub fn skip(list: &[f32], count: f32) -> Vec<f32> {
+    list[clamp_count(count, list.len())..].to_vec()
+}
+
+pub fn lag(list: &[f32], steps: f32) -> Vec<f32> {
+    list[..list.len() - clamp_count(steps, list.len())].to_vec()
+}
+
+///pairs every element with the one steps before it, so the result is steps shorter than list
+fn with_lagged(list: &[f32], steps: f32, function: fn(f32, f32) -> f32) -> Vec<f32> {
+    let steps = clamp_count(steps, list.len());
+    list[steps..]
+        .iter()
+        .zip(list)
+        .map(|(current, lagged)| function(*current, *lagged))
+        .collect()
+}
+
+pub fn diff(list: &[f32], steps: f32) -> Vec<f32> {
+    with_lagged(list, steps, |current, lagged| current - lagged)
+}
+
+pub fn pct_change(list: &[f32], steps: f32) -> Vec<f32> {
+    with_lagged(list, steps, |current, lagged| {
+        if lagged == 0.0 {
+            0.0
+        } else {
+            current / lagged - 1.0
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIST: [f32; 5] = [1.0, 2.0, 4.0, 8.0, 16.0];
+
+    #[test]
+    fn test_slice() {
+        assert_eq!(slice(&LIST, 1.0, 3.0), vec![2.0, 4.0]);
+        assert_eq!(slice(&LIST, -2.0, 5.0), vec![8.0, 16.0]);
+        assert_eq!(slice(&LIST, 0.0, -3.0), vec![1.0, 2.0]);
+        assert_eq!(slice(&LIST, -100.0, 100.0), LIST.to_vec());
+        assert_eq!(slice(&LIST, 3.0, 1.0), Vec::<f32>::new());
+        assert_eq!(slice(&[], 0.0, 2.0), Vec::<f32>::new());
+    }
+
+    #[test]
+    fn test_take_skip() {
+        assert_eq!(take(&LIST, 2.0), vec![8.0, 16.0]);
+        assert_eq!(take(&LIST, 10.0), LIST.to_vec());
+        assert_eq!(take(&LIST, -1.0), Vec::<f32>::new());
+        assert_eq!(take(&LIST, f32::NAN), Vec::<f32>::new());
+        assert_eq!(skip(&LIST, 3.0), vec![8.0, 16.0]);
+        assert_eq!(skip(&LIST, 10.0), Vec::<f32>::new());
+        assert_eq!(skip(&LIST, -1.0), LIST.to_vec());
+    }
+
+    #[test]
+    fn test_lag_diff_pct_change() {
+        assert_eq!(lag(&LIST, 2.0), vec![1.0, 2.0, 4.0]);
+        assert_eq!(lag(&LIST, 0.0), LIST.to_vec());
+        assert_eq!(lag(&LIST, 9.0), Vec::<f32>::new());
+        assert_eq!(diff(&LIST, 1.0), vec![1.0, 2.0, 4.0, 8.0]);
+        assert_eq!(diff(&LIST, 2.0), vec![3.0, 6.0, 12.0]);
+        assert_eq!(diff(&LIST, 0.0), vec![0.0; 5]);
+        assert_eq!(pct_change(&LIST, 1.0), vec![1.0; 4]);
+        assert_eq!(pct_change(&[0.0, 1.0, 2.0], 1.0), vec![0.0, 1.0]);
+        assert_eq!(pct_change(&[], 1.0), Vec::<f32>::new());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::lib::op::operand::*;
 
 //binary constant operators
+#[derive(Clone, Debug)]
 pub enum MarketDataOperator {
     Volume,
     TradeCount,
@@ -8,8 +9,6 @@ pub enum MarketDataOperator {
     High,
     Low,
     Close,
-    // OrderBookBids,
-    // OrderBookAsks,
 }
 
 type MarketIndex = Operand;
@@ -25,7 +24,9 @@ pub type MarketDataOperation = (
     MarketDataDuration,
 );
 
- 
+///Level 2 snapshot, bids are ordered from the best (highest) price down
+/// and asks from the best (lowest) price up
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct OrderBook {
     pub bid_price: Vec<f32>,
     pub bid_volume: Vec<f32>,
@@ -33,7 +34,17 @@ pub struct OrderBook {
     pub ask_volume: Vec<f32>,
 }
 
-#[derive(Debug, Clone)]
+impl OrderBook {
+    ///keeps only the best depth levels on each side
+    pub fn truncate(&mut self, depth: usize) {
+        self.bid_price.truncate(depth);
+        self.bid_volume.truncate(depth);
+        self.ask_price.truncate(depth);
+        self.ask_volume.truncate(depth);
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct MarketData {
     pub open: Vec<f32>,
     pub high: Vec<f32>,
@@ -2,10 +2,16 @@ pub mod boolean;
 pub mod branch;
 pub mod constant;
 pub mod index;
+pub mod indicator;
+pub mod list_sort;
+pub mod list_transform;
+pub mod list_window;
 pub mod market_data;
 pub mod market_sort;
 pub mod num_pick;
 pub mod number;
+pub mod order_book;
+pub mod rolling;
 pub mod trade;
 
 use crate::lib::op::environment::Env;
@@ -15,12 +21,19 @@ use boolean::*;
 use branch::*;
 use constant::*;
 use index::*;
+use indicator::*;
+use list_sort::*;
+use list_transform::*;
+use list_window::*;
 use market_data::*;
 use market_sort::*;
 use num_pick::*;
 use number::*;
+use order_book::*;
+use rolling::*;
 use trade::*;
 
+#[derive(Clone, Debug)]
 pub enum Operation {
     Branch(BranchOperation),
     Bool(BoolOperation),
@@ -33,6 +46,14 @@ pub enum Operation {
     Index(IndexOperation),
     Identity(Operand),
     MarketSort(MarketSortOperation),
+    OrderBook(OrderBookOperation),
+    ListSort(ListSortOperation),
+    Map(MapOperation),
+    Filter(FilterOperation),
+    Zip(ZipOperation),
+    ListWindow(ListWindowOperation),
+    Indicator(IndicatorOperation),
+    Rolling(RollingOperation),
 }
 
 pub type Context = Option<TerminalType>;
@@ -48,27 +69,115 @@ impl Operation {
     ) -> TerminalType {
         match self {
             Operation::MarketSort((operand,)) => {
-                let mut market_index_list = env.get_market_index_list();
-                market_index_list.sort_by(|market_index_a, market_index_b| {
-                    let value_a = operand
-                        .evaluate(
-                            operation_list,
-                            trade_list,
-                            &Some(TerminalType::Number(*market_index_a)),
-                            env,
-                        )
-                        .to_f32(); //call with market_index_a
-                    let value_b = operand
-                        .evaluate(
-                            operation_list,
-                            trade_list,
-                            &Some(TerminalType::Number(*market_index_b)),
-                            env,
-                        )
-                        .to_f32(); //call with market_index_b
-                    value_a.partial_cmp(&value_b).unwrap()
-                });
-                TerminalType::NumberList(market_index_list)
+                let market_index_list = env.get_market_index_list();
+                let keys = evaluate_sort_keys(
+                    &market_index_list,
+                    operand,
+                    operation_list,
+                    trade_list,
+                    env,
+                );
+                TerminalType::NumberList(sort_list(
+                    &ListSortOperator::Sort,
+                    &SortDirection::Ascending,
+                    &market_index_list,
+                    &keys,
+                ))
+            }
+            Operation::ListSort((operator, direction, list_operand, key_operand)) => {
+                let list = list_operand
+                    .evaluate(operation_list, trade_list, context, env)
+                    .to_list();
+                let keys = evaluate_sort_keys(&list, key_operand, operation_list, trade_list, env);
+                TerminalType::NumberList(sort_list(operator, direction, &list, &keys))
+            }
+            Operation::Map((list_operand, expression)) => {
+                let list = list_operand
+                    .evaluate(operation_list, trade_list, context, env)
+                    .to_list();
+                let values =
+                    evaluate_per_element(&list, expression, operation_list, trade_list, env);
+                TerminalType::NumberList(values.iter().map(TerminalType::to_f32).collect())
+            }
+            Operation::Filter((list_operand, predicate)) => {
+                let list = list_operand
+                    .evaluate(operation_list, trade_list, context, env)
+                    .to_list();
+                let keep = evaluate_per_element(&list, predicate, operation_list, trade_list, env);
+                TerminalType::NumberList(
+                    list.into_iter()
+                        .zip(keep)
+                        .filter(|(_, keep)| keep.to_bool())
+                        .map(|(element, _)| element)
+                        .collect(),
+                )
+            }
+            Operation::ListWindow((operator, list_operand, count_operand, end_operand)) => {
+                let list = list_operand
+                    .evaluate(operation_list, trade_list, context, env)
+                    .to_list();
+                let count = count_operand
+                    .evaluate(operation_list, trade_list, context, env)
+                    .to_f32();
+                TerminalType::NumberList(match operator {
+                    ListWindowOperator::Slice => {
+                        let end = end_operand
+                            .evaluate(operation_list, trade_list, context, env)
+                            .to_f32();
+                        slice(&list, count, end)
+                    }
+                    ListWindowOperator::Take => take(&list, count),
+                    ListWindowOperator::Skip => skip(&list, count),
+                    ListWindowOperator::Lag => lag(&list, count),
+                    ListWindowOperator::Diff => diff(&list, count),
+                    ListWindowOperator::PctChange => pct_change(&list, count),
+                })
+            }
+            Operation::Rolling((operator, list_operand, window_operand, argument_operand)) => {
+                let list = list_operand
+                    .evaluate(operation_list, trade_list, context, env)
+                    .to_list();
+                let window = clamp_window(
+                    window_operand
+                        .evaluate(operation_list, trade_list, context, env)
+                        .to_f32(),
+                );
+                TerminalType::NumberList(match operator {
+                    RollingOperator::Mean => rolling_mean(&list, window),
+                    RollingOperator::Std => rolling_std(&list, window),
+                    RollingOperator::Min => rolling_min(&list, window),
+                    RollingOperator::Max => rolling_max(&list, window),
+                    RollingOperator::ZScore => rolling_z_score(&list, window),
+                    RollingOperator::Quantile => {
+                        let quantile = argument_operand
+                            .evaluate(operation_list, trade_list, context, env)
+                            .to_f32();
+                        rolling_quantile(&list, window, quantile)
+                    }
+                    RollingOperator::Skew => rolling_skew(&list, window),
+                    RollingOperator::Kurtosis => rolling_kurtosis(&list, window),
+                    RollingOperator::Correlation => {
+                        let other = argument_operand
+                            .evaluate(operation_list, trade_list, context, env)
+                            .to_list();
+                        rolling_correlation(&list, &other, window)
+                    }
+                })
+            }
+            Operation::Zip((operator, operand_left, operand_right)) => {
+                let left = operand_left
+                    .evaluate(operation_list, trade_list, context, env)
+                    .to_list();
+                let right = operand_right
+                    .evaluate(operation_list, trade_list, context, env)
+                    .to_list();
+                TerminalType::NumberList(apply_to_lists(
+                    operator,
+                    &left,
+                    &right,
+                    env.get_list_alignment(),
+                    env.get_numeric_policy(),
+                ))
             }
             Operation::Identity(operand) => {
                 operand.evaluate(operation_list, trade_list, context, env)
@@ -80,16 +189,17 @@ impl Operation {
 
                 let index = match operator {
                     IndexOperator::First => 0,
-                    IndexOperator::Last => list.len() - 1,
+                    IndexOperator::Last => list.len().saturating_sub(1),
                     IndexOperator::Operand(operand) => {
                         let index = operand
                             .evaluate(operation_list, trade_list, context, env)
                             .to_usize();
-                        index.max(0).min(list.len() - 1)
+                        index.max(0).min(list.len().saturating_sub(1))
                     }
                 };
 
-                TerminalType::Number(list[index])
+                //an empty list has no element to pick
+                TerminalType::Number(list.get(index).copied().unwrap_or(0.0))
             }
             Operation::Constant((operator, operand)) => match operator {
                 ConstantOperator::MarketPrice => {
@@ -119,15 +229,49 @@ impl Operation {
                 ConstantOperator::PI => TerminalType::Number(3.141592653589793),
                 ConstantOperator::GoldenRatio => TerminalType::Number(1.618033988749895),
                 ConstantOperator::EulerNumber => TerminalType::Number(2.718281828459045),
+                ConstantOperator::BtcMarketIndex => {
+                    TerminalType::MarketIndex(env.get_btc_market_index())
+                }
+                ConstantOperator::EthMarketIndex => {
+                    TerminalType::MarketIndex(env.get_eth_market_index())
+                }
+                ConstantOperator::USDTMarketIndex => {
+                    TerminalType::MarketIndex(env.get_usdt_market_index())
+                }
+                ConstantOperator::CurrentTimestampMs => {
+                    TerminalType::Timestamp(env.get_current_timestamp_ms())
+                }
+                ConstantOperator::SelectedMarketListingTimestampMs => {
+                    let market_index = operand.evaluate(operation_list, trade_list, context, env);
+                    TerminalType::Timestamp(
+                        env.get_market_listing_timestamp_ms(market_index.to_usize()),
+                    )
+                }
+                ConstantOperator::Element => context.clone().unwrap_or(TerminalType::Number(0.0)),
                 _ => TerminalType::Number(0.0),
             },
             Operation::Number((operator, operand_left, operand_right)) => {
                 let left = operand_left.evaluate(operation_list, trade_list, context, env);
                 let right = operand_right.evaluate(operation_list, trade_list, context, env);
-                TerminalType::Number(operator.func()(left.to_f32(), right.to_f32()))
+                broadcast(
+                    operator,
+                    &left,
+                    &right,
+                    env.get_list_alignment(),
+                    env.get_numeric_policy(),
+                )
             }
 
-            Operation::Trade((operator, market_index, market_price, market_amount)) => {
+            Operation::Trade((
+                operator,
+                market_index,
+                market_price,
+                market_amount,
+                order_type,
+                time_in_force,
+                stop_price,
+                leverage,
+            )) => {
                 let market_index = market_index
                     .evaluate(operation_list, trade_list, context, env)
                     .to_usize();
@@ -137,11 +281,18 @@ impl Operation {
                 let market_amount = market_amount
                     .evaluate(operation_list, trade_list, context, env)
                     .to_f32();
+                let stop_price = stop_price
+                    .evaluate(operation_list, trade_list, context, env)
+                    .to_f32();
                 trade_list.push(trade::Trade {
                     operator: *operator,
                     index: market_index,
                     price: market_price,
                     amount: market_amount,
+                    order_type: *order_type,
+                    time_in_force: *time_in_force,
+                    stop_price,
+                    leverage: *leverage,
                 });
                 TerminalType::Number(1.0)
             }
@@ -149,36 +300,24 @@ impl Operation {
                 let left_value = operand_left.evaluate(operation_list, trade_list, context, env);
                 let right_value = operand_right.evaluate(operation_list, trade_list, context, env);
                 match operator {
-                    BoolOperator::Equal => {
-                        TerminalType::Number((left_value == right_value) as i32 as f32)
-                    }
-                    BoolOperator::NotEqual => {
-                        TerminalType::Number((left_value != right_value) as i32 as f32)
-                    }
-                    BoolOperator::GreaterThan => {
-                        TerminalType::Number((left_value > right_value) as i32 as f32)
-                    }
-                    BoolOperator::LessThan => {
-                        TerminalType::Number((left_value < right_value) as i32 as f32)
-                    }
+                    BoolOperator::Equal => TerminalType::Bool(left_value == right_value),
+                    BoolOperator::NotEqual => TerminalType::Bool(left_value != right_value),
+                    BoolOperator::GreaterThan => TerminalType::Bool(left_value > right_value),
+                    BoolOperator::LessThan => TerminalType::Bool(left_value < right_value),
                     BoolOperator::GreaterThanOrEqual => {
-                        TerminalType::Number((left_value >= right_value) as i32 as f32)
+                        TerminalType::Bool(left_value >= right_value)
                     }
-                    BoolOperator::LessThanOrEqual => {
-                        TerminalType::Number((left_value <= right_value) as i32 as f32)
+                    BoolOperator::LessThanOrEqual => TerminalType::Bool(left_value <= right_value),
+                    BoolOperator::And => {
+                        TerminalType::Bool(left_value.to_bool() && right_value.to_bool())
                     }
-                    BoolOperator::And => TerminalType::Number(
-                        (left_value.to_bool() && right_value.to_bool()) as i32 as f32,
-                    ),
-                    BoolOperator::Or => TerminalType::Number(
-                        (left_value.to_bool() || right_value.to_bool()) as i32 as f32,
-                    ),
-                    BoolOperator::Not => {
-                        TerminalType::Number((!left_value.to_bool()) as i32 as f32)
+                    BoolOperator::Or => {
+                        TerminalType::Bool(left_value.to_bool() || right_value.to_bool())
+                    }
+                    BoolOperator::Not => TerminalType::Bool(!left_value.to_bool()),
+                    BoolOperator::Xor => {
+                        TerminalType::Bool(left_value.to_bool() ^ right_value.to_bool())
                     }
-                    BoolOperator::Xor => TerminalType::Number(
-                        (left_value.to_bool() ^ right_value.to_bool()) as i32 as f32,
-                    ),
                 }
             }
             Operation::Branch((operand_operator, operand_left, operand_right)) => {
@@ -217,15 +356,13 @@ impl Operation {
                 timestamp_start_operand,
                 timestamp_duration_operand,
             )) => {
-                //if context exists use it instead of market_index_operand
-                // market_index_operand will only be evaluated normally if there is no context and itself is Operand::None
-                // if there is a context, it will be used if it market_index_operand == Operand::None
-                let market_index_value = match (market_index_operand, context) {
-                    (Operand::None, Context::Some(context_terminal_type)) => {
-                        context_terminal_type.clone()
-                    }
-                    _ => market_index_operand.evaluate(operation_list, trade_list, context, env),
-                };
+                let market_index_value = evaluate_market_index(
+                    market_index_operand,
+                    operation_list,
+                    trade_list,
+                    context,
+                    env,
+                );
 
                 let timestamp_start_value =
                     timestamp_start_operand.evaluate(operation_list, trade_list, context, env);
@@ -234,8 +371,8 @@ impl Operation {
 
                 let market_data = env.get_market_data(
                     market_index_value.to_usize(),
-                    timestamp_start_value.to_f32(),
-                    timestamp_duration_value.to_f32(),
+                    timestamp_start_value.to_i64(),
+                    timestamp_duration_value.to_i64(),
                 );
                 match market_data_operator {
                     MarketDataOperator::Open => TerminalType::NumberList(market_data.open),
@@ -248,13 +385,127 @@ impl Operation {
                     }
                 }
             }
-            Operation::NumPick((num_pick_operator, operand)) => {
+            Operation::NumPick((num_pick_operator, operand, quantile_operand)) => {
                 let operand_value = operand
                     .evaluate(operation_list, trade_list, context, env)
                     .to_list();
-                let function = get_function_by_num_pick_operator(num_pick_operator);
+                let quantile_value = match num_pick_operator {
+                    NumPickOperator::Quantile => quantile_operand
+                        .evaluate(operation_list, trade_list, context, env)
+                        .to_f32(),
+                    _ => f32::NAN,
+                };
+                TerminalType::Number(num_pick(num_pick_operator, operand_value, quantile_value))
+            }
+            Operation::Indicator((
+                indicator_operator,
+                indicator_output,
+                market_index_operand,
+                timestamp_start_operand,
+                timestamp_duration_operand,
+                period_operand,
+            )) => {
+                let market_index_value = evaluate_market_index(
+                    market_index_operand,
+                    operation_list,
+                    trade_list,
+                    context,
+                    env,
+                );
+                let timestamp_start_value =
+                    timestamp_start_operand.evaluate(operation_list, trade_list, context, env);
+                let timestamp_duration_value =
+                    timestamp_duration_operand.evaluate(operation_list, trade_list, context, env);
+                let period_value =
+                    period_operand.evaluate(operation_list, trade_list, context, env);
+
+                let market_data = env.get_market_data(
+                    market_index_value.to_usize(),
+                    timestamp_start_value.to_i64(),
+                    timestamp_duration_value.to_i64(),
+                );
+                let series =
+                    compute_indicator(indicator_operator, &market_data, period_value.to_usize());
+                match indicator_output {
+                    IndicatorOutput::Series => TerminalType::NumberList(series),
+                    IndicatorOutput::Latest => {
+                        TerminalType::Number(series.last().copied().unwrap_or(0.0))
+                    }
+                }
+            }
+            Operation::OrderBook((
+                order_book_operator,
+                market_index_operand,
+                timestamp_operand,
+                depth_operand,
+            )) => {
+                let market_index_value = evaluate_market_index(
+                    market_index_operand,
+                    operation_list,
+                    trade_list,
+                    context,
+                    env,
+                );
+                let timestamp_value =
+                    timestamp_operand.evaluate(operation_list, trade_list, context, env);
+                let depth_value = depth_operand.evaluate(operation_list, trade_list, context, env);
+
+                let order_book = env.get_order_book(
+                    market_index_value.to_usize(),
+                    timestamp_value.to_i64(),
+                    depth_value.to_usize(),
+                );
+                match order_book_operator {
+                    OrderBookOperator::BidPrice => TerminalType::NumberList(order_book.bid_price),
+                    OrderBookOperator::BidVolume => TerminalType::NumberList(order_book.bid_volume),
+                    OrderBookOperator::AskPrice => TerminalType::NumberList(order_book.ask_price),
+                    OrderBookOperator::AskVolume => TerminalType::NumberList(order_book.ask_volume),
+                }
+            }
+        }
+    }
+
+    ///every operand of the operation, including the ones held by operators,
+    /// so variation operators can rewrite pointers
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Operation::Branch((condition, left, right)) => vec![condition, left, right],
+            Operation::Bool((_, left, right)) => vec![left, right],
+            Operation::Trade((_, market_index, price, amount, _, _, stop_price, _)) => {
+                vec![market_index, price, amount, stop_price]
+            }
+            Operation::MarketData((_, market_index, timestamp_start, duration)) => {
+                vec![market_index, timestamp_start, duration]
+            }
+            Operation::NumPick((_, list, quantile)) => vec![list, quantile],
+            Operation::Number((_, left, right)) => vec![left, right],
+            Operation::Constant((_, operand)) => vec![operand],
+            Operation::Index((operator, list)) => match operator {
+                IndexOperator::Operand(index) => vec![list, index],
+                _ => vec![list],
+            },
+            Operation::Identity(operand) => vec![operand],
+            Operation::MarketSort((key,)) => vec![key],
+            Operation::OrderBook((_, market_index, timestamp, depth)) => {
+                vec![market_index, timestamp, depth]
+            }
+            Operation::ListSort((_, _, list, key)) => vec![list, key],
+            Operation::Map((list, expression)) => vec![list, expression],
+            Operation::Filter((list, expression)) => vec![list, expression],
+            Operation::Zip((_, left, right)) => vec![left, right],
+            Operation::ListWindow((_, list, count, end)) => vec![list, count, end],
+            Operation::Indicator((_, _, market_index, timestamp_start, duration, period)) => {
+                vec![market_index, timestamp_start, duration, period]
+            }
+            Operation::Rolling((_, list, window, argument)) => vec![list, window, argument],
+        }
+    }
 
-                TerminalType::Number(function(operand_value))
+    ///rewrites every pointer with map
+    pub fn map_pointers(&mut self, map: impl Fn(usize) -> usize) {
+        for operand in self.operands_mut() {
+            if let Operand::Pointer(pointer) = operand {
+                *pointer = map(*pointer);
             }
         }
     }
@@ -267,18 +518,69 @@ impl Operation {
     //             new_operand_left.mutate();
     //             new_operand_right.mutate();
     //             Operation::Branch((operation.0.0, new_operand_left, new_operand_right))
-            
+
     //         }
     //         _ => panic!("Not implemented"),
     //     }
     // }
 }
 
+//if context exists use it instead of market_index_operand
+// market_index_operand will only be evaluated normally if there is no context and itself is Operand::None
+// if there is a context, it will be used if it market_index_operand == Operand::None
+fn evaluate_market_index(
+    market_index_operand: &Operand,
+    operation_list: &OperationList,
+    trade_list: &mut TradeList,
+    context: &Context,
+    env: &impl Env,
+) -> TerminalType {
+    match (market_index_operand, context) {
+        (Operand::None, Context::Some(context_terminal_type)) => context_terminal_type.clone(),
+        _ => market_index_operand.evaluate(operation_list, trade_list, context, env),
+    }
+}
+
+///evaluates operand once per element with the element as context,
+/// Operand::None evaluates to the element itself
+fn evaluate_per_element(
+    list: &[f32],
+    operand: &Operand,
+    operation_list: &OperationList,
+    trade_list: &mut TradeList,
+    env: &impl Env,
+) -> Vec<TerminalType> {
+    list.iter()
+        .map(|element| match operand {
+            Operand::None => TerminalType::Number(*element),
+            _ => operand.evaluate(
+                operation_list,
+                trade_list,
+                &Some(TerminalType::Number(*element)),
+                env,
+            ),
+        })
+        .collect()
+}
+
+fn evaluate_sort_keys(
+    list: &[f32],
+    key_operand: &Operand,
+    operation_list: &OperationList,
+    trade_list: &mut TradeList,
+    env: &impl Env,
+) -> Vec<f32> {
+    evaluate_per_element(list, key_operand, operation_list, trade_list, env)
+        .iter()
+        .map(TerminalType::to_f32)
+        .collect()
+}
+
 //tests
 #[cfg(test)]
 
 mod tests {
-    use crate::lib::op::{environment::Env, operand::*, operation::*, terminal_type::*};
+    use crate::lib::op::{environment::Env, operation::*, terminal_type::*};
 
     struct DefaultEnv {}
     impl Env for DefaultEnv {}
@@ -327,7 +629,7 @@ mod tests {
         ));
         let terminal_type =
             operation.evaluate(&operation_list, &mut trade_list, &context, &DefaultEnv);
-        assert_eq!(terminal_type, TerminalType::Number(0.0));
+        assert!(matches!(terminal_type, TerminalType::Bool(false)));
     }
     #[test]
     fn test_branch_operation() {
@@ -369,10 +671,20 @@ mod tests {
         let operation = Operation::NumPick((
             NumPickOperator::Max,
             Operand::Terminal(TerminalType::NumberList(vec![1.0, 2.0, 3.0])),
+            Operand::None,
         ));
         let terminal_type =
             operation.evaluate(&operation_list, &mut trade_list, &context, &default_env);
         assert_eq!(terminal_type, TerminalType::Number(3.0));
+
+        let operation = Operation::NumPick((
+            NumPickOperator::Quantile,
+            Operand::Terminal(TerminalType::NumberList(vec![5.0, 1.0, 3.0])),
+            Operand::Terminal(TerminalType::Number(0.25)),
+        ));
+        let terminal_type =
+            operation.evaluate(&operation_list, &mut trade_list, &context, &default_env);
+        assert_eq!(terminal_type, TerminalType::Number(2.0));
     }
 
     #[test]
@@ -384,6 +696,7 @@ mod tests {
             Operation::NumPick((
                 NumPickOperator::Max,
                 Operand::Terminal(TerminalType::NumberList(vec![1.0, 2.0, 3.0])),
+                Operand::None,
             )),
             Operation::Bool((
                 BoolOperator::GreaterThan,
@@ -416,12 +729,20 @@ mod tests {
                 Operand::Terminal(TerminalType::Number(1.0)),
                 Operand::Terminal(TerminalType::Number(2.0)),
                 Operand::Terminal(TerminalType::Number(3.0)),
+                OrderType::Market,
+                TimeInForce::GoodTilCancelled,
+                Operand::None,
+                TradeLeverage::X1,
             )),
             Operation::Trade((
                 TradeOperator::Sell,
                 Operand::Terminal(TerminalType::Number(1.0)),
                 Operand::Terminal(TerminalType::Number(2.0)),
                 Operand::Terminal(TerminalType::Number(3.0)),
+                OrderType::Market,
+                TimeInForce::GoodTilCancelled,
+                Operand::None,
+                TradeLeverage::X1,
             )),
             Operation::MarketData((
                 MarketDataOperator::High,
@@ -429,8 +750,8 @@ mod tests {
                 Operand::Terminal(TerminalType::Number(1.0)),
                 Operand::Terminal(TerminalType::Number(1.0)),
             )),
-            Operation::NumPick((NumPickOperator::Average, Operand::Pointer(2))),
-            Operation::NumPick((NumPickOperator::Max, Operand::Pointer(2))),
+            Operation::NumPick((NumPickOperator::Average, Operand::Pointer(2), Operand::None)),
+            Operation::NumPick((NumPickOperator::Max, Operand::Pointer(2), Operand::None)),
             Operation::Bool((
                 BoolOperator::LessThan,
                 Operand::Pointer(4),
@@ -475,7 +796,7 @@ mod tests {
                 Operand::Terminal(TerminalType::Number(1.0)),
                 Operand::Terminal(TerminalType::Number(1.0)),
             )),
-            Operation::NumPick((NumPickOperator::Length, Operand::Pointer(2))),
+            Operation::NumPick((NumPickOperator::Length, Operand::Pointer(2), Operand::None)),
             Operation::Bool((
                 BoolOperator::GreaterThan,
                 Operand::Pointer(3),
@@ -505,6 +826,46 @@ mod tests {
         assert_eq!(terminal_type, TerminalType::Number(1.0));
     }
 
+    #[test]
+    fn test_order_book_operation() {
+        let default_env = DefaultEnv {};
+        let context = None;
+        let mut trade_list = TradeList::new();
+        let operation_list = vec![
+            Operation::OrderBook((
+                OrderBookOperator::AskPrice,
+                Operand::Terminal(TerminalType::Number(1.0)),
+                Operand::Terminal(TerminalType::Number(0.0)),
+                Operand::Terminal(TerminalType::Number(2.0)),
+            )),
+            Operation::OrderBook((
+                OrderBookOperator::BidPrice,
+                Operand::Terminal(TerminalType::Number(1.0)),
+                Operand::Terminal(TerminalType::Number(0.0)),
+                Operand::Terminal(TerminalType::Number(2.0)),
+            )),
+            Operation::Index((IndexOperator::First, Operand::Pointer(0))),
+            Operation::Index((IndexOperator::First, Operand::Pointer(1))),
+            //spread between the best ask and the best bid
+            Operation::Number((
+                NumOperator::Subtract,
+                Operand::Pointer(2),
+                Operand::Pointer(3),
+            )),
+        ];
+
+        let asks =
+            operation_list[0].evaluate(&operation_list, &mut trade_list, &context, &default_env);
+        assert_eq!(asks.to_list(), vec![2.02, 2.04]);
+        let spread = operation_list[operation_list.len() - 1].evaluate(
+            &operation_list,
+            &mut trade_list,
+            &context,
+            &default_env,
+        );
+        assert!((spread.to_f32() - 0.04).abs() < 1e-5);
+    }
+
     #[test]
 
     fn test_market_sort() {
@@ -517,8 +878,8 @@ mod tests {
             fn get_market_data(
                 &self,
                 market_index: usize,
-                timestamp_start: f32,
-                duration: f32,
+                timestamp_start: i64,
+                duration: i64,
             ) -> MarketData {
                 let market_data = MarketData {
                     close: vec![],
@@ -545,7 +906,7 @@ mod tests {
                 Operand::Pointer(0),
                 Operand::Pointer(0),
             )),
-            Operation::NumPick((NumPickOperator::Max, Operand::Pointer(1))),
+            Operation::NumPick((NumPickOperator::Max, Operand::Pointer(1), Operand::None)),
             Operation::MarketSort((Operand::Pointer(2),)),
             Operation::Index((IndexOperator::Last, Operand::Pointer(3))),
         ];
@@ -558,4 +919,368 @@ mod tests {
         );
         assert_eq!(market_index, TerminalType::Number(6.0));
     }
+
+    #[test]
+    fn test_list_sort() {
+        let default_env = DefaultEnv {};
+        let context = None;
+        let mut trade_list = TradeList::new();
+        let operation_list = vec![
+            Operation::Identity(Operand::Terminal(TerminalType::NumberList(vec![
+                -3.0, 1.0, -2.0, 4.0,
+            ]))),
+            //sort key: the square of each element
+            Operation::Constant((ConstantOperator::Element, Operand::None)),
+            Operation::Number((
+                NumOperator::Multiply,
+                Operand::Pointer(1),
+                Operand::Pointer(1),
+            )),
+            Operation::ListSort((
+                ListSortOperator::Sort,
+                SortDirection::Descending,
+                Operand::Pointer(0),
+                Operand::Pointer(2),
+            )),
+            Operation::ListSort((
+                ListSortOperator::ArgSort,
+                SortDirection::Ascending,
+                Operand::Pointer(0),
+                Operand::None,
+            )),
+            Operation::ListSort((
+                ListSortOperator::Rank,
+                SortDirection::Ascending,
+                Operand::Pointer(0),
+                Operand::Pointer(2),
+            )),
+        ];
+        let evaluate = |index: usize, trade_list: &mut TradeList| {
+            operation_list[index].evaluate(&operation_list, trade_list, &context, &default_env)
+        };
+
+        assert_eq!(
+            evaluate(3, &mut trade_list).to_list(),
+            vec![4.0, -3.0, -2.0, 1.0]
+        );
+        assert_eq!(
+            evaluate(4, &mut trade_list).to_list(),
+            vec![0.0, 2.0, 1.0, 3.0]
+        );
+        assert_eq!(
+            evaluate(5, &mut trade_list).to_list(),
+            vec![2.0, 0.0, 1.0, 3.0]
+        );
+    }
+
+    #[test]
+    fn test_map_filter_zip() {
+        let default_env = DefaultEnv {};
+        let context = None;
+        let mut trade_list = TradeList::new();
+        let operation_list = vec![
+            Operation::MarketData((
+                MarketDataOperator::Close,
+                Operand::Terminal(TerminalType::Number(1.0)),
+                Operand::Terminal(TerminalType::Number(0.0)),
+                Operand::Terminal(TerminalType::Number(0.0)),
+            )),
+            Operation::Constant((ConstantOperator::Element, Operand::None)),
+            //double every close
+            Operation::Number((
+                NumOperator::Multiply,
+                Operand::Pointer(1),
+                Operand::Terminal(TerminalType::Number(2.0)),
+            )),
+            Operation::Map((Operand::Pointer(0), Operand::Pointer(2))),
+            //keep closes above 2
+            Operation::Bool((
+                BoolOperator::GreaterThan,
+                Operand::Pointer(1),
+                Operand::Terminal(TerminalType::Number(2.0)),
+            )),
+            Operation::Filter((Operand::Pointer(0), Operand::Pointer(4))),
+            //doubled closes minus the closes above 2, aligned at the most recent value
+            Operation::Zip((
+                NumOperator::Subtract,
+                Operand::Pointer(3),
+                Operand::Pointer(5),
+            )),
+        ];
+        let evaluate = |index: usize, trade_list: &mut TradeList| {
+            operation_list[index]
+                .evaluate(&operation_list, trade_list, &context, &default_env)
+                .to_list()
+        };
+
+        assert_eq!(evaluate(3, &mut trade_list), vec![2.0, 4.0, 6.0, 8.0, 10.0]);
+        assert_eq!(evaluate(5, &mut trade_list), vec![3.0, 4.0, 5.0]);
+        assert_eq!(evaluate(6, &mut trade_list), vec![3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn test_number_operation_on_lists() {
+        struct LongestEnv {}
+        impl Env for LongestEnv {
+            fn get_list_alignment(&self) -> ListAlignment {
+                ListAlignment::Longest
+            }
+        }
+        let context = None;
+        let mut trade_list = TradeList::new();
+        let operation_list = vec![
+            Operation::MarketData((
+                MarketDataOperator::Close,
+                Operand::Terminal(TerminalType::Number(1.0)),
+                Operand::Terminal(TerminalType::Number(0.0)),
+                Operand::Terminal(TerminalType::Number(0.0)),
+            )),
+            Operation::MarketData((
+                MarketDataOperator::Open,
+                Operand::Terminal(TerminalType::Number(2.0)),
+                Operand::Terminal(TerminalType::Number(0.0)),
+                Operand::Terminal(TerminalType::Number(0.0)),
+            )),
+            //ratio between two series
+            Operation::Number((
+                NumOperator::Divide,
+                Operand::Pointer(1),
+                Operand::Pointer(0),
+            )),
+            //spread against a scalar
+            Operation::Number((
+                NumOperator::Subtract,
+                Operand::Pointer(0),
+                Operand::Terminal(TerminalType::Number(1.0)),
+            )),
+            Operation::Number((
+                NumOperator::Add,
+                Operand::Pointer(0),
+                Operand::Terminal(TerminalType::NumberList(vec![10.0, 20.0])),
+            )),
+        ];
+        let ratio =
+            operation_list[2].evaluate(&operation_list, &mut trade_list, &context, &DefaultEnv {});
+        assert_eq!(ratio.to_list(), vec![2.0, 2.0, 2.0, 2.0, 2.0]);
+        let spread =
+            operation_list[3].evaluate(&operation_list, &mut trade_list, &context, &DefaultEnv {});
+        assert_eq!(spread.to_list(), vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+        let shortest =
+            operation_list[4].evaluate(&operation_list, &mut trade_list, &context, &DefaultEnv {});
+        assert_eq!(shortest.to_list(), vec![14.0, 25.0]);
+        let longest =
+            operation_list[4].evaluate(&operation_list, &mut trade_list, &context, &LongestEnv {});
+        assert_eq!(longest.to_list(), vec![11.0, 12.0, 13.0, 14.0, 25.0]);
+    }
+
+    #[test]
+    fn test_list_window_operation() {
+        let default_env = DefaultEnv {};
+        let context = None;
+        let mut trade_list = TradeList::new();
+        let operation_list = vec![
+            Operation::MarketData((
+                MarketDataOperator::Close,
+                Operand::Terminal(TerminalType::Number(1.0)),
+                Operand::Terminal(TerminalType::Number(0.0)),
+                Operand::Terminal(TerminalType::Number(0.0)),
+            )),
+            Operation::ListWindow((
+                ListWindowOperator::Take,
+                Operand::Pointer(0),
+                Operand::Terminal(TerminalType::Number(3.0)),
+                Operand::None,
+            )),
+            //one step returns of the last three closes
+            Operation::ListWindow((
+                ListWindowOperator::PctChange,
+                Operand::Pointer(1),
+                Operand::Terminal(TerminalType::Number(1.0)),
+                Operand::None,
+            )),
+            //close minus the close two steps earlier, lined up by the default alignment
+            Operation::ListWindow((
+                ListWindowOperator::Lag,
+                Operand::Pointer(0),
+                Operand::Terminal(TerminalType::Number(2.0)),
+                Operand::None,
+            )),
+            Operation::Number((
+                NumOperator::Subtract,
+                Operand::Pointer(0),
+                Operand::Pointer(3),
+            )),
+            Operation::ListWindow((
+                ListWindowOperator::Slice,
+                Operand::Pointer(0),
+                Operand::Terminal(TerminalType::Number(-4.0)),
+                Operand::Terminal(TerminalType::Number(-2.0)),
+            )),
+        ];
+        let evaluate = |index: usize, trade_list: &mut TradeList| {
+            operation_list[index]
+                .evaluate(&operation_list, trade_list, &context, &default_env)
+                .to_list()
+        };
+
+        assert_eq!(evaluate(1, &mut trade_list), vec![3.0, 4.0, 5.0]);
+        assert_eq!(evaluate(2, &mut trade_list), vec![4.0 / 3.0 - 1.0, 0.25]);
+        assert_eq!(evaluate(4, &mut trade_list), vec![2.0, 2.0, 2.0]);
+        assert_eq!(evaluate(5, &mut trade_list), vec![2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_indicator_operation() {
+        let default_env = DefaultEnv {};
+        let context = None;
+        let mut trade_list = TradeList::new();
+        let indicator = |operator, output, period: f32| {
+            Operation::Indicator((
+                operator,
+                output,
+                Operand::Terminal(TerminalType::Number(1.0)),
+                Operand::Terminal(TerminalType::Number(0.0)),
+                Operand::Terminal(TerminalType::Number(0.0)),
+                Operand::Terminal(TerminalType::Number(period)),
+            ))
+        };
+        let operation_list = vec![
+            indicator(IndicatorOperator::Sma, IndicatorOutput::Series, 2.0),
+            indicator(IndicatorOperator::Sma, IndicatorOutput::Latest, 2.0),
+            indicator(IndicatorOperator::Rsi, IndicatorOutput::Latest, 3.0),
+            //period longer than the data
+            indicator(IndicatorOperator::Ema, IndicatorOutput::Latest, 10.0),
+            //the 3 candle SMA above the market price
+            indicator(IndicatorOperator::Sma, IndicatorOutput::Latest, 3.0),
+            Operation::Constant((
+                ConstantOperator::MarketPrice,
+                Operand::Terminal(TerminalType::Number(1.0)),
+            )),
+            Operation::Bool((
+                BoolOperator::GreaterThan,
+                Operand::Pointer(4),
+                Operand::Pointer(5),
+            )),
+        ];
+        let evaluate = |index: usize, trade_list: &mut TradeList| {
+            operation_list[index].evaluate(&operation_list, trade_list, &context, &default_env)
+        };
+
+        assert_eq!(
+            evaluate(0, &mut trade_list).to_list(),
+            vec![1.5, 2.5, 3.5, 4.5]
+        );
+        assert_eq!(evaluate(1, &mut trade_list), TerminalType::Number(4.5));
+        assert_eq!(evaluate(2, &mut trade_list), TerminalType::Number(100.0));
+        assert_eq!(evaluate(3, &mut trade_list), TerminalType::Number(0.0));
+        assert_eq!(evaluate(6, &mut trade_list), TerminalType::Number(1.0));
+    }
+
+    #[test]
+    fn test_rolling_operation() {
+        let default_env = DefaultEnv {};
+        let context = None;
+        let mut trade_list = TradeList::new();
+        let list = |values: Vec<f32>| Operand::Terminal(TerminalType::NumberList(values));
+        let number = |value: f32| Operand::Terminal(TerminalType::Number(value));
+        let operation_list = vec![
+            Operation::Identity(list(vec![1.0, 3.0, 5.0, 7.0])),
+            Operation::Rolling((
+                RollingOperator::Mean,
+                Operand::Pointer(0),
+                number(2.0),
+                Operand::None,
+            )),
+            Operation::Rolling((
+                RollingOperator::Quantile,
+                Operand::Pointer(0),
+                number(3.0),
+                number(1.0),
+            )),
+            Operation::Rolling((
+                RollingOperator::Correlation,
+                Operand::Pointer(0),
+                number(4.0),
+                list(vec![4.0, 3.0, 2.0, 1.0]),
+            )),
+            //a negative window is a window of 1
+            Operation::Rolling((
+                RollingOperator::Std,
+                Operand::Pointer(0),
+                number(-3.0),
+                Operand::None,
+            )),
+        ];
+        let evaluate = |index: usize, trade_list: &mut TradeList| {
+            operation_list[index]
+                .evaluate(&operation_list, trade_list, &context, &default_env)
+                .to_list()
+        };
+
+        assert_eq!(evaluate(1, &mut trade_list), vec![1.0, 2.0, 4.0, 6.0]);
+        assert_eq!(evaluate(2, &mut trade_list), vec![1.0, 3.0, 5.0, 7.0]);
+        assert_eq!(evaluate(3, &mut trade_list), vec![0.0, -1.0, -1.0, -1.0]);
+        assert_eq!(evaluate(4, &mut trade_list), vec![0.0; 4]);
+    }
+
+    #[test]
+    fn test_typed_terminals() {
+        struct TimedEnv {}
+        impl Env for TimedEnv {
+            fn get_current_timestamp_ms(&self) -> i64 {
+                1_700_000_000_123
+            }
+
+            fn get_market_data(
+                &self,
+                market_index: usize,
+                timestamp_start: i64,
+                duration: i64,
+            ) -> MarketData {
+                MarketData {
+                    close: vec![
+                        market_index as f32,
+                        (timestamp_start % 1000) as f32,
+                        duration as f32,
+                    ],
+                    ..MarketData::default()
+                }
+            }
+        }
+        let timed_env = TimedEnv {};
+        let context = None;
+        let mut trade_list = TradeList::new();
+        let operation_list = vec![
+            Operation::Constant((ConstantOperator::CurrentTimestampMs, Operand::None)),
+            Operation::Constant((ConstantOperator::BtcMarketIndex, Operand::None)),
+            Operation::MarketData((
+                MarketDataOperator::Close,
+                Operand::Pointer(1),
+                Operand::Pointer(0),
+                Operand::Terminal(TerminalType::Int(60_000)),
+            )),
+            Operation::Bool((
+                BoolOperator::GreaterThan,
+                Operand::Pointer(0),
+                Operand::Terminal(TerminalType::Timestamp(1_700_000_000_122)),
+            )),
+        ];
+        let evaluate = |index: usize, trade_list: &mut TradeList| {
+            operation_list[index].evaluate(&operation_list, trade_list, &context, &timed_env)
+        };
+
+        assert_eq!(evaluate(0, &mut trade_list).to_i64(), 1_700_000_000_123);
+        assert!(matches!(
+            evaluate(1, &mut trade_list),
+            TerminalType::MarketIndex(1)
+        ));
+        assert_eq!(
+            evaluate(2, &mut trade_list).to_list(),
+            vec![1.0, 123.0, 60_000.0]
+        );
+        assert!(matches!(
+            evaluate(3, &mut trade_list),
+            TerminalType::Bool(true)
+        ));
+    }
 }
@@ -1,4 +1,5 @@
 use crate::lib::op::operand::*;
+#[derive(Clone, Debug)]
 pub enum NumPickOperator {
     Average,
     Sum,
@@ -7,50 +8,252 @@ pub enum NumPickOperator {
     Med,
     Std,
     Length,
+    ///linearly interpolated quantile, the quantile operand is clamped to 0..1
+    Quantile,
+    ///interquartile range, Q3 - Q1
+    Iqr,
+    ///most frequent value, the smallest one on ties
+    Mode,
+    ///least squares slope of the list against its index
+    Slope,
+    ///coefficient of determination of the least squares line, 0 for a flat list
+    RSquared,
+    First,
+    Last,
+    CountAboveZero,
+    ///0 unless every element is positive
+    GeometricMean,
+    ///lag-1 autocorrelation
+    Autocorrelation,
 }
 
-///Pick operations collapase a list of types into a single type
-pub type NumPickOperation = (NumPickOperator, Operand);
+type NumPickList = Operand;
+///only read by Quantile, Operand::None for the others
+type NumPickQuantile = Operand;
 
-pub fn get_function_by_num_pick_operator(operator: &NumPickOperator) -> fn(Vec<f32>) -> f32 {
-    match operator {
-        NumPickOperator::Average => |list| list.iter().sum::<f32>() / list.len() as f32,
+///Pick operations collapase a list of types into a single type.
+/// Every pick returns 0 for an empty list instead of NaN.
+pub type NumPickOperation = (NumPickOperator, NumPickList, NumPickQuantile);
+
+///the pick of operator, quantile_value is the quantile Quantile picks
+pub fn num_pick(operator: &NumPickOperator, list: Vec<f32>, quantile_value: f32) -> f32 {
+    match get_function_by_num_pick_operator(operator) {
+        Some(function) => function(list),
+        None => quantile(&list, quantile_value),
+    }
+}
+
+///None for Quantile, the only pick that needs more than the list
+fn get_function_by_num_pick_operator(operator: &NumPickOperator) -> Option<fn(Vec<f32>) -> f32> {
+    let function: fn(Vec<f32>) -> f32 = match operator {
+        NumPickOperator::Average => |list| mean(&list),
         NumPickOperator::Sum => |list| list.iter().sum::<f32>(),
         NumPickOperator::Max => |list| {
-            if list.len() == 0 {
+            if list.is_empty() {
                 0.0
             } else {
                 list.into_iter().reduce(f32::max).unwrap()
             }
         },
         NumPickOperator::Min => |list| {
-            if list.len() == 0 {
+            if list.is_empty() {
                 0.0
             } else {
                 list.into_iter().reduce(f32::min).unwrap()
             }
         },
-        NumPickOperator::Med => |list| {
-            let mut sorted = list.clone();
-            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
-
-            let len = sorted.len();
-            if len % 2 == 0 {
-                (sorted.get(len / 2).unwrap_or(&0.0) + sorted.get((len / 2) + 1).unwrap_or(&0.0))
-                    / 2.0
-            } else {
-                *sorted.get(len / 2).unwrap_or(&0.0)
-            }
-        },
+        NumPickOperator::Med => |list| quantile(&list, 0.5),
         NumPickOperator::Std => |list| {
             //calculate the standard deviation of list
-            let mean = list.iter().sum::<f32>() / list.len() as f32;
+            let mean = mean(&list);
             let mut sum = 0.0;
             for number in list.iter() {
                 sum += (number - mean).powi(2);
             }
-            (sum / (list.len()) as f32).sqrt()
+            (sum / list.len().max(1) as f32).sqrt()
         },
         NumPickOperator::Length => |list| list.len() as f32,
+        NumPickOperator::Quantile => return None,
+        NumPickOperator::Iqr => |list| quantile(&list, 0.75) - quantile(&list, 0.25),
+        NumPickOperator::Mode => |list| mode(&list),
+        NumPickOperator::Slope => |list| linear_regression(&list).0,
+        NumPickOperator::RSquared => |list| linear_regression(&list).1,
+        NumPickOperator::First => |list| list.first().copied().unwrap_or(0.0),
+        NumPickOperator::Last => |list| list.last().copied().unwrap_or(0.0),
+        NumPickOperator::CountAboveZero => |list| list.iter().filter(|v| **v > 0.0).count() as f32,
+        NumPickOperator::GeometricMean => |list| {
+            if list.is_empty() || !list.iter().all(|v| *v > 0.0) {
+                0.0
+            } else {
+                (list.iter().map(|v| v.ln()).sum::<f32>() / list.len() as f32).exp()
+            }
+        },
+        NumPickOperator::Autocorrelation => |list| autocorrelation(&list),
+    };
+    Some(function)
+}
+
+fn mean(list: &[f32]) -> f32 {
+    if list.is_empty() {
+        0.0
+    } else {
+        list.iter().sum::<f32>() / list.len() as f32
+    }
+}
+
+fn sorted(list: &[f32]) -> Vec<f32> {
+    let mut sorted = list.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    sorted
+}
+
+///A NaN quantile picks NaN from a non-empty list, NaN elements sort last
+pub fn quantile(list: &[f32], quantile: f32) -> f32 {
+    if list.is_empty() {
+        return 0.0;
+    }
+    if quantile.is_nan() {
+        return f32::NAN;
+    }
+    let quantile = quantile.clamp(0.0, 1.0);
+    let sorted = sorted(list);
+    let rank = quantile * (sorted.len() - 1) as f32;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f32)
+}
+
+fn mode(list: &[f32]) -> f32 {
+    let sorted = sorted(list);
+    let mut best = (0.0, 0);
+    let mut start = 0;
+    for end in 1..=sorted.len() {
+        if end == sorted.len() || sorted[end] != sorted[start] {
+            if end - start > best.1 {
+                best = (sorted[start], end - start);
+            }
+            start = end;
+        }
+    }
+    best.0
+}
+
+///(slope, r squared), both 0 for lists shorter than 2
+fn linear_regression(list: &[f32]) -> (f32, f32) {
+    if list.len() < 2 {
+        return (0.0, 0.0);
+    }
+    let n = list.len() as f32;
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = mean(list);
+    let mut covariance = 0.0;
+    let mut variance_x = 0.0;
+    let mut variance_y = 0.0;
+    for (x, y) in list.iter().enumerate() {
+        let dx = x as f32 - mean_x;
+        let dy = y - mean_y;
+        covariance += dx * dy;
+        variance_x += dx * dx;
+        variance_y += dy * dy;
+    }
+    let slope = covariance / variance_x;
+    let r_squared = if variance_y > 0.0 {
+        covariance * covariance / (variance_x * variance_y)
+    } else {
+        0.0
+    };
+    (slope, r_squared)
+}
+
+fn autocorrelation(list: &[f32]) -> f32 {
+    if list.len() < 2 {
+        return 0.0;
+    }
+    let mean = mean(list);
+    let variance: f32 = list.iter().map(|v| (v - mean).powi(2)).sum();
+    if variance.is_nan() || variance <= 0.0 {
+        return 0.0;
+    }
+    let covariance: f32 = list
+        .windows(2)
+        .map(|pair| (pair[0] - mean) * (pair[1] - mean))
+        .sum();
+    covariance / variance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pick(operator: NumPickOperator, list: &[f32]) -> f32 {
+        num_pick(&operator, list.to_vec(), 0.5)
+    }
+
+    #[test]
+    fn test_median_and_quantiles() {
+        assert_eq!(pick(NumPickOperator::Med, &[4.0, 1.0, 3.0, 2.0]), 2.5);
+        assert_eq!(pick(NumPickOperator::Med, &[3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(quantile(&[1.0, 2.0, 3.0, 4.0, 5.0], 0.1), 1.4);
+        assert_eq!(quantile(&[1.0, 2.0, 3.0], 2.0), 3.0);
+        assert!(num_pick(&NumPickOperator::Quantile, vec![1.0, 2.0, 3.0], f32::NAN).is_nan());
+        assert_eq!(num_pick(&NumPickOperator::Quantile, vec![], f32::NAN), 0.0);
+        assert_eq!(pick(NumPickOperator::Iqr, &[1.0, 2.0, 3.0, 4.0, 5.0]), 2.0);
+        //NaN elements sort last instead of panicking
+        assert_eq!(pick(NumPickOperator::Med, &[f32::NAN, 1.0, 2.0]), 2.0);
+    }
+
+    #[test]
+    fn test_mode() {
+        assert_eq!(pick(NumPickOperator::Mode, &[3.0, 1.0, 3.0, 2.0]), 3.0);
+        assert_eq!(pick(NumPickOperator::Mode, &[2.0, 1.0]), 1.0);
+    }
+
+    #[test]
+    fn test_regression_and_autocorrelation() {
+        assert_eq!(pick(NumPickOperator::Slope, &[1.0, 3.0, 5.0]), 2.0);
+        assert_eq!(pick(NumPickOperator::RSquared, &[1.0, 3.0, 5.0]), 1.0);
+        assert_eq!(pick(NumPickOperator::RSquared, &[2.0, 2.0]), 0.0);
+        assert_eq!(pick(NumPickOperator::RSquared, &[1.0, 3.0, 1.0]), 0.0);
+        assert_eq!(
+            pick(NumPickOperator::Autocorrelation, &[1.0, -1.0, 1.0, -1.0]),
+            -0.75
+        );
+        assert_eq!(pick(NumPickOperator::Autocorrelation, &[1.0, 1.0]), 0.0);
+    }
+
+    #[test]
+    fn test_simple_picks() {
+        let list = [-1.0, 2.0, 0.0, 4.0];
+        assert_eq!(pick(NumPickOperator::First, &list), -1.0);
+        assert_eq!(pick(NumPickOperator::Last, &list), 4.0);
+        assert_eq!(pick(NumPickOperator::CountAboveZero, &list), 2.0);
+        assert_eq!(pick(NumPickOperator::GeometricMean, &list), 0.0);
+        assert!((pick(NumPickOperator::GeometricMean, &[1.0, 4.0, 16.0]) - 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_empty_lists_pick_zero() {
+        let operators = vec![
+            NumPickOperator::Average,
+            NumPickOperator::Sum,
+            NumPickOperator::Max,
+            NumPickOperator::Min,
+            NumPickOperator::Med,
+            NumPickOperator::Std,
+            NumPickOperator::Length,
+            NumPickOperator::Quantile,
+            NumPickOperator::Iqr,
+            NumPickOperator::Mode,
+            NumPickOperator::Slope,
+            NumPickOperator::RSquared,
+            NumPickOperator::First,
+            NumPickOperator::Last,
+            NumPickOperator::CountAboveZero,
+            NumPickOperator::GeometricMean,
+            NumPickOperator::Autocorrelation,
+        ];
+        for operator in operators {
+            assert_eq!(pick(operator, &[]), 0.0);
+        }
     }
 }
@@ -1,5 +1,9 @@
 use crate::lib::op::operand::*;
+use crate::lib::op::operation::list_transform::apply_to_lists;
+use crate::lib::op::terminal_type::*;
+use crate::lib::op::type_check::ValueType;
 
+#[derive(Clone, Debug)]
 pub enum NumOperator {
     Add,
     Subtract,
@@ -13,10 +17,73 @@ pub enum NumOperator {
     Tan,
     Pow,
     Log,
+    Abs,
+    Sqrt,
+    Exp,
+    Tanh,
+    ///-1, 0 or 1
+    Sign,
+    Neg,
+    Floor,
+    Round,
+}
+
+///What a NumOperator does with inputs outside its domain and with non-finite results
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NumericPolicy {
+    ///plain IEEE 754 float arithmetic, NaN and infinities pass through
+    Ieee,
+    ///Classic GP protected operators: x / 0 is 1, x % 0 is x, sqrt and log use |x|
+    /// and log of 0 is 0. Any result that is still not finite becomes 0.
+    Protected,
+    ///IEEE arithmetic with NaN results replaced by 0, infinities are kept
+    NanToZero,
+    ///IEEE arithmetic with NaN results replaced by 0 and everything else clamped to +-limit
+    Saturating(f32),
+}
+
+impl NumericPolicy {
+    pub fn clean(self, value: f32) -> f32 {
+        match self {
+            NumericPolicy::Ieee => value,
+            NumericPolicy::Protected if !value.is_finite() => 0.0,
+            NumericPolicy::Protected => value,
+            NumericPolicy::NanToZero | NumericPolicy::Saturating(_) if value.is_nan() => 0.0,
+            NumericPolicy::NanToZero => value,
+            NumericPolicy::Saturating(limit) => value.clamp(-limit.abs(), limit.abs()),
+        }
+    }
 }
 
 impl NumOperator {
-    pub fn func(&self) -> fn(f32, f32) -> f32 {
+    ///unary operators only use their left operand
+    pub fn is_unary(&self) -> bool {
+        matches!(
+            self,
+            NumOperator::Cos
+                | NumOperator::Sin
+                | NumOperator::Tan
+                | NumOperator::Abs
+                | NumOperator::Sqrt
+                | NumOperator::Exp
+                | NumOperator::Tanh
+                | NumOperator::Sign
+                | NumOperator::Neg
+                | NumOperator::Floor
+                | NumOperator::Round
+        )
+    }
+
+    ///the operator under policy, unary operators ignore their second argument
+    pub fn func(&self, policy: NumericPolicy) -> impl Fn(f32, f32) -> f32 {
+        let function = match policy {
+            NumericPolicy::Protected => self.protected_func(),
+            _ => self.ieee_func(),
+        };
+        move |a, b| policy.clean(function(a, b))
+    }
+
+    fn ieee_func(&self) -> fn(f32, f32) -> f32 {
         match self {
             NumOperator::Add => |a, b| a + b,
             NumOperator::Subtract => |a, b| a - b,
@@ -30,8 +97,339 @@ impl NumOperator {
             NumOperator::Tan => |a, _| a.tan(),
             NumOperator::Pow => |a, b| a.powf(b),
             NumOperator::Log => |a, b| a.log(b),
+            NumOperator::Abs => |a, _| a.abs(),
+            NumOperator::Sqrt => |a, _| a.sqrt(),
+            NumOperator::Exp => |a, _| a.exp(),
+            NumOperator::Tanh => |a, _| a.tanh(),
+            NumOperator::Sign => |a, _| {
+                if a > 0.0 {
+                    1.0
+                } else if a < 0.0 {
+                    -1.0
+                } else {
+                    0.0
+                }
+            },
+            NumOperator::Neg => |a, _| -a,
+            NumOperator::Floor => |a, _| a.floor(),
+            NumOperator::Round => |a, _| a.round(),
+        }
+    }
+
+    ///The type of the exact integer arithmetic on Ints and Timestamps, None when the
+    /// operands go through f32. A Timestamp moved by an Int stays a Timestamp and the
+    /// distance between two Timestamps is an Int of milliseconds.
+    pub fn exact_type(&self, left: ValueType, right: ValueType) -> Option<ValueType> {
+        match (self, left, right) {
+            (
+                NumOperator::Add
+                | NumOperator::Subtract
+                | NumOperator::Multiply
+                | NumOperator::Min
+                | NumOperator::Max,
+                ValueType::Int,
+                ValueType::Int,
+            ) => Some(ValueType::Int),
+            (NumOperator::Add, ValueType::Timestamp, ValueType::Int)
+            | (NumOperator::Add, ValueType::Int, ValueType::Timestamp)
+            | (NumOperator::Subtract, ValueType::Timestamp, ValueType::Int) => {
+                Some(ValueType::Timestamp)
+            }
+            (NumOperator::Subtract, ValueType::Timestamp, ValueType::Timestamp) => {
+                Some(ValueType::Int)
+            }
+            _ => None,
+        }
+    }
+
+    ///exact_type's arithmetic, saturating at the i64 bounds
+    pub fn apply_exact(&self, left: &TerminalType, right: &TerminalType) -> Option<TerminalType> {
+        use TerminalType::{Int, Timestamp};
+        let value = match (self, left, right) {
+            (NumOperator::Add, Int(a), Int(b)) => Int(a.saturating_add(*b)),
+            (NumOperator::Subtract, Int(a), Int(b)) => Int(a.saturating_sub(*b)),
+            (NumOperator::Multiply, Int(a), Int(b)) => Int(a.saturating_mul(*b)),
+            (NumOperator::Min, Int(a), Int(b)) => Int(*a.min(b)),
+            (NumOperator::Max, Int(a), Int(b)) => Int(*a.max(b)),
+            (NumOperator::Add, Timestamp(timestamp), Int(duration))
+            | (NumOperator::Add, Int(duration), Timestamp(timestamp)) => {
+                Timestamp(timestamp.saturating_add(*duration))
+            }
+            (NumOperator::Subtract, Timestamp(timestamp), Int(duration)) => {
+                Timestamp(timestamp.saturating_sub(*duration))
+            }
+            (NumOperator::Subtract, Timestamp(a), Timestamp(b)) => Int(a.saturating_sub(*b)),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    fn protected_func(&self) -> fn(f32, f32) -> f32 {
+        match self {
+            NumOperator::Divide => |a, b| if b == 0.0 { 1.0 } else { a / b },
+            NumOperator::Modulo => |a, b| if b == 0.0 { a } else { a % b },
+            NumOperator::Sqrt => |a, _| a.abs().sqrt(),
+            NumOperator::Pow => |a, b| a.abs().powf(b),
+            NumOperator::Log => |a, b| {
+                let base = b.abs().ln();
+                if a == 0.0 || base == 0.0 {
+                    0.0
+                } else {
+                    a.abs().ln() / base
+                }
+            },
+            _ => self.ieee_func(),
         }
     }
 }
 
 pub type NumOperation = (NumOperator, Operand, Operand);
+
+///How two lists of different lengths are lined up for element-wise arithmetic,
+/// lists are always aligned at their last element, the most recent value of a series
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ListAlignment {
+    ///drop the longer list's extra leading elements
+    Shortest,
+    ///pad the shorter list at the front with its first element
+    Longest,
+    ///lists of different lengths produce an empty list
+    Strict,
+}
+
+///applies operator element-wise when either side is a list, a number on the other side
+/// is used against every element. Ints and Timestamps keep their millisecond precision
+/// where `NumOperator::exact_type` allows it.
+pub fn broadcast(
+    operator: &NumOperator,
+    left: &TerminalType,
+    right: &TerminalType,
+    alignment: ListAlignment,
+    policy: NumericPolicy,
+) -> TerminalType {
+    if let Some(value) = operator.apply_exact(left, right) {
+        return value;
+    }
+    let function = operator.func(policy);
+    match (left, right) {
+        (TerminalType::NumberList(left), TerminalType::NumberList(right)) => {
+            TerminalType::NumberList(apply_to_lists(operator, left, right, alignment, policy))
+        }
+        (TerminalType::NumberList(list), _) => {
+            let right = right.to_f32();
+            TerminalType::NumberList(list.iter().map(|a| function(*a, right)).collect())
+        }
+        (_, TerminalType::NumberList(list)) => {
+            let left = left.to_f32();
+            TerminalType::NumberList(list.iter().map(|b| function(left, *b)).collect())
+        }
+        _ => TerminalType::Number(function(left.to_f32(), right.to_f32())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_broadcast() {
+        let list = TerminalType::NumberList(vec![2.0, 4.0]);
+        let number = TerminalType::Number(2.0);
+        assert_eq!(
+            broadcast(
+                &NumOperator::Divide,
+                &list,
+                &number,
+                ListAlignment::Shortest,
+                NumericPolicy::Ieee
+            )
+            .to_list(),
+            vec![1.0, 2.0]
+        );
+        assert_eq!(
+            broadcast(
+                &NumOperator::Subtract,
+                &number,
+                &list,
+                ListAlignment::Shortest,
+                NumericPolicy::Ieee
+            )
+            .to_list(),
+            vec![0.0, -2.0]
+        );
+        assert_eq!(
+            broadcast(
+                &NumOperator::Add,
+                &number,
+                &number,
+                ListAlignment::Shortest,
+                NumericPolicy::Ieee
+            ),
+            TerminalType::Number(4.0)
+        );
+    }
+
+    #[test]
+    fn test_exact_arithmetic() {
+        let apply = |operator: NumOperator, left: TerminalType, right: TerminalType| {
+            let expected = operator.exact_type(left.value_type(), right.value_type());
+            let value = broadcast(
+                &operator,
+                &left,
+                &right,
+                ListAlignment::Shortest,
+                NumericPolicy::Protected,
+            );
+            assert_eq!(expected.unwrap_or(ValueType::Number), value.value_type());
+            value
+        };
+        //past f32's 2^24 exact integers
+        let now = 1_650_000_000_123;
+        assert_eq!(
+            apply(
+                NumOperator::Subtract,
+                TerminalType::Timestamp(now),
+                TerminalType::Int(60_001)
+            ),
+            TerminalType::Timestamp(now - 60_001)
+        );
+        assert_eq!(
+            apply(
+                NumOperator::Add,
+                TerminalType::Int(1),
+                TerminalType::Timestamp(now)
+            ),
+            TerminalType::Timestamp(now + 1)
+        );
+        assert_eq!(
+            apply(
+                NumOperator::Subtract,
+                TerminalType::Timestamp(now),
+                TerminalType::Timestamp(now - 7)
+            ),
+            TerminalType::Int(7)
+        );
+        assert_eq!(
+            apply(
+                NumOperator::Add,
+                TerminalType::Int(16_777_217),
+                TerminalType::Int(2)
+            ),
+            TerminalType::Int(16_777_219)
+        );
+        assert_eq!(
+            apply(
+                NumOperator::Multiply,
+                TerminalType::Int(i64::MAX),
+                TerminalType::Int(2)
+            ),
+            TerminalType::Int(i64::MAX)
+        );
+        assert_eq!(
+            apply(
+                NumOperator::Max,
+                TerminalType::Int(3),
+                TerminalType::Int(-3)
+            ),
+            TerminalType::Int(3)
+        );
+        //everything else still goes through f32
+        assert_eq!(
+            apply(
+                NumOperator::Divide,
+                TerminalType::Int(3),
+                TerminalType::Int(2)
+            ),
+            TerminalType::Number(1.5)
+        );
+        assert_eq!(
+            apply(
+                NumOperator::Add,
+                TerminalType::Int(1),
+                TerminalType::Number(0.5)
+            ),
+            TerminalType::Number(1.5)
+        );
+    }
+
+    #[test]
+    fn test_numeric_policy() {
+        let apply = |operator: NumOperator, a: f32, b: f32, policy: NumericPolicy| {
+            operator.func(policy)(a, b)
+        };
+        assert!(apply(NumOperator::Divide, 1.0, 0.0, NumericPolicy::Ieee).is_infinite());
+        assert_eq!(
+            apply(NumOperator::Divide, 3.0, 0.0, NumericPolicy::Protected),
+            1.0
+        );
+        assert_eq!(
+            apply(NumOperator::Divide, 3.0, 2.0, NumericPolicy::Protected),
+            1.5
+        );
+        assert_eq!(
+            apply(NumOperator::Modulo, 3.0, 0.0, NumericPolicy::Protected),
+            3.0
+        );
+        assert_eq!(
+            apply(NumOperator::Log, 0.0, 10.0, NumericPolicy::Protected),
+            0.0
+        );
+        assert_eq!(
+            apply(NumOperator::Log, -8.0, 2.0, NumericPolicy::Protected),
+            3.0
+        );
+        assert_eq!(
+            apply(NumOperator::Log, 8.0, 1.0, NumericPolicy::Protected),
+            0.0
+        );
+        assert_eq!(
+            apply(NumOperator::Sqrt, -4.0, 0.0, NumericPolicy::Protected),
+            2.0
+        );
+        assert_eq!(
+            apply(NumOperator::Exp, 1000.0, 0.0, NumericPolicy::Protected),
+            0.0
+        );
+        assert_eq!(
+            apply(NumOperator::Sqrt, -4.0, 0.0, NumericPolicy::NanToZero),
+            0.0
+        );
+        assert!(apply(NumOperator::Exp, 1000.0, 0.0, NumericPolicy::NanToZero).is_infinite());
+        assert_eq!(
+            apply(
+                NumOperator::Exp,
+                1000.0,
+                0.0,
+                NumericPolicy::Saturating(1e6)
+            ),
+            1e6
+        );
+        assert_eq!(
+            apply(
+                NumOperator::Divide,
+                -1.0,
+                0.0,
+                NumericPolicy::Saturating(1e6)
+            ),
+            -1e6
+        );
+        assert_eq!(
+            apply(NumOperator::Pow, -8.0, 0.5, NumericPolicy::Saturating(1e6)),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_unary_operators() {
+        let apply = |operator: NumOperator, a: f32| operator.func(NumericPolicy::Ieee)(a, 0.0);
+        assert_eq!(apply(NumOperator::Abs, -2.5), 2.5);
+        assert_eq!(apply(NumOperator::Sqrt, 9.0), 3.0);
+        assert_eq!(apply(NumOperator::Exp, 0.0), 1.0);
+        assert_eq!(apply(NumOperator::Tanh, 0.0), 0.0);
+        assert_eq!(apply(NumOperator::Sign, -2.5), -1.0);
+        assert_eq!(apply(NumOperator::Sign, 0.0), 0.0);
+        assert_eq!(apply(NumOperator::Neg, 2.5), -2.5);
+        assert_eq!(apply(NumOperator::Floor, -2.5), -3.0);
+        assert_eq!(apply(NumOperator::Round, 2.5), 3.0);
+    }
+}
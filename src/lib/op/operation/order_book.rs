@@ -0,0 +1,21 @@
+use crate::lib::op::operand::*;
+
+#[derive(Clone, Debug)]
+pub enum OrderBookOperator {
+    BidPrice,
+    BidVolume,
+    AskPrice,
+    AskVolume,
+}
+
+type MarketIndex = Operand;
+type OrderBookTimestamp = Operand;
+///number of price levels to return on each side
+type OrderBookDepth = Operand;
+
+pub type OrderBookOperation = (
+    OrderBookOperator,
+    MarketIndex,
+    OrderBookTimestamp,
+    OrderBookDepth,
+);
@@ -0,0 +1,462 @@
+use crate::lib::op::operand::*;
+use std::collections::VecDeque;
+
+///Every output element summarizes the window ending at the same position of the input,
+/// so the result is as long as the input. The first window - 1 elements use the shorter
+/// window available so far.
+#[derive(Clone, Debug)]
+pub enum RollingOperator {
+    Mean,
+    ///population standard deviation
+    Std,
+    Min,
+    Max,
+    ///(x - mean) / std of the window, 0 where the window has no spread
+    ZScore,
+    ///linearly interpolated quantile, the argument is clamped to 0..1
+    Quantile,
+    Skew,
+    ///excess kurtosis, 0 for a normal distribution
+    Kurtosis,
+    ///Pearson correlation with the argument list, both lists are tail-aligned
+    /// and the result is as long as the shorter one
+    Correlation,
+}
+
+type RollingList = Operand;
+type RollingWindow = Operand;
+///the quantile for Quantile, the other list for Correlation, Operand::None for the others
+type RollingArgument = Operand;
+
+pub type RollingOperation = (RollingOperator, RollingList, RollingWindow, RollingArgument);
+
+///negative and NaN windows become 1
+pub fn clamp_window(window: f32) -> usize {
+    if window > 1.0 {
+        window as usize
+    } else {
+        1
+    }
+}
+
+///first finite value, the shift that keeps running sums precise
+fn first_finite(values: &[f32]) -> f64 {
+    values
+        .iter()
+        .copied()
+        .find(|value| value.is_finite())
+        .unwrap_or(0.0) as f64
+}
+
+///Running power sums of a window. Values are shifted by the first finite value
+/// so prices far from 0 don't lose their precision in the higher powers.
+/// NaN and infinities are counted instead of summed, they would poison the sums
+/// for good, and the window's statistics are NaN while it holds any.
+struct Moments {
+    shift: f64,
+    count: f64,
+    sum: [f64; 4],
+    non_finite: usize,
+}
+
+impl Moments {
+    fn new(shift: f64) -> Moments {
+        Moments {
+            shift,
+            count: 0.0,
+            sum: [0.0; 4],
+            non_finite: 0,
+        }
+    }
+
+    fn add(&mut self, value: f32, sign: f64) {
+        if !value.is_finite() {
+            if sign > 0.0 {
+                self.non_finite += 1;
+            } else {
+                self.non_finite -= 1;
+            }
+            return;
+        }
+        let x = value as f64 - self.shift;
+        self.count += sign;
+        let mut power = sign;
+        for sum in self.sum.iter_mut() {
+            power *= x;
+            *sum += power;
+        }
+    }
+
+    fn push(&mut self, value: f32) {
+        self.add(value, 1.0);
+    }
+
+    fn pop(&mut self, value: f32) {
+        self.add(value, -1.0);
+    }
+
+    ///(mean, m2, m3, m4) with the central moments of the shifted values
+    fn central(&self) -> (f64, f64, f64, f64) {
+        let n = self.count;
+        let mean = self.sum[0] / n;
+        let [s1, s2, s3, s4] = self.sum.map(|sum| sum / n);
+        let m2 = (s2 - mean * mean).max(0.0);
+        let m3 = s3 - 3.0 * mean * s2 + 2.0 * mean.powi(3);
+        let m4 = s4 - 4.0 * mean * s3 + 6.0 * mean.powi(2) * s2 - 3.0 * mean.powi(4);
+        (s1 + self.shift, m2, m3, m4)
+    }
+}
+
+///variances below this are rounding noise of a flat window
+const FLAT_VARIANCE: f64 = 1e-12;
+
+fn rolling_moments(values: &[f32], window: usize, statistic: fn(f32, &Moments) -> f64) -> Vec<f32> {
+    let mut moments = Moments::new(first_finite(values));
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            moments.push(*value);
+            if i >= window {
+                moments.pop(values[i - window]);
+            }
+            if moments.non_finite > 0 {
+                f32::NAN
+            } else {
+                statistic(*value, &moments) as f32
+            }
+        })
+        .collect()
+}
+
+pub fn rolling_mean(values: &[f32], window: usize) -> Vec<f32> {
+    rolling_moments(values, window, |_, moments| moments.central().0)
+}
+
+pub fn rolling_std(values: &[f32], window: usize) -> Vec<f32> {
+    rolling_moments(values, window, |_, moments| moments.central().1.sqrt())
+}
+
+pub fn rolling_z_score(values: &[f32], window: usize) -> Vec<f32> {
+    rolling_moments(values, window, |value, moments| {
+        let (mean, m2, _, _) = moments.central();
+        if m2 <= FLAT_VARIANCE {
+            0.0
+        } else {
+            (value as f64 - mean) / m2.sqrt()
+        }
+    })
+}
+
+pub fn rolling_skew(values: &[f32], window: usize) -> Vec<f32> {
+    rolling_moments(values, window, |_, moments| {
+        let (_, m2, m3, _) = moments.central();
+        if m2 <= FLAT_VARIANCE {
+            0.0
+        } else {
+            m3 / m2.powf(1.5)
+        }
+    })
+}
+
+pub fn rolling_kurtosis(values: &[f32], window: usize) -> Vec<f32> {
+    rolling_moments(values, window, |_, moments| {
+        let (_, m2, _, m4) = moments.central();
+        if m2 <= FLAT_VARIANCE {
+            0.0
+        } else {
+            m4 / (m2 * m2) - 3.0
+        }
+    })
+}
+
+///Monotonic deque of indices, the front is always the extreme of the window,
+/// keep(a, b) is true when a can stay in front of b
+fn rolling_extreme(values: &[f32], window: usize, keep: fn(f32, f32) -> bool) -> Vec<f32> {
+    let mut candidates: VecDeque<usize> = VecDeque::new();
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            while let Some(back) = candidates.back() {
+                if keep(values[*back], *value) {
+                    break;
+                }
+                candidates.pop_back();
+            }
+            candidates.push_back(i);
+            if i - candidates[0] >= window {
+                candidates.pop_front();
+            }
+            values[candidates[0]]
+        })
+        .collect()
+}
+
+pub fn rolling_min(values: &[f32], window: usize) -> Vec<f32> {
+    rolling_extreme(values, window, |kept, value| kept < value)
+}
+
+pub fn rolling_max(values: &[f32], window: usize) -> Vec<f32> {
+    rolling_extreme(values, window, |kept, value| kept > value)
+}
+
+///Counts of the values in the window by their rank among all values, a Fenwick tree
+/// so adding, removing and finding the k-th smallest value are O(log n)
+struct RankCounts {
+    ///1-based, tree[i] counts the ranks i - lowest_bit(i) up to but excluding i
+    tree: Vec<i32>,
+}
+
+impl RankCounts {
+    fn new(len: usize) -> RankCounts {
+        RankCounts {
+            tree: vec![0; len + 1],
+        }
+    }
+
+    fn add(&mut self, rank: usize, delta: i32) {
+        let mut i = rank + 1;
+        while i < self.tree.len() {
+            self.tree[i] += delta;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    ///rank of the k-th smallest value in the window, counting from 0
+    fn find(&self, k: usize) -> usize {
+        let mut position = 0;
+        let mut remaining = k as i32;
+        let mut step = (self.tree.len() - 1)
+            .checked_next_power_of_two()
+            .unwrap_or(0);
+        while step > 0 {
+            if position + step < self.tree.len() && self.tree[position + step] <= remaining {
+                position += step;
+                remaining -= self.tree[position];
+            }
+            step /= 2;
+        }
+        position
+    }
+}
+
+///Ranks every value once up front, then each step updates the window's rank counts
+/// and looks up the two values around the quantile, O(n log n) overall.
+/// NaN sorts above everything, a NaN quantile gives NaN everywhere like the pick.
+pub fn rolling_quantile(values: &[f32], window: usize, quantile: f32) -> Vec<f32> {
+    if quantile.is_nan() {
+        return vec![f32::NAN; values.len()];
+    }
+    let quantile = quantile.clamp(0.0, 1.0);
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    //equal values share the rank of the first of them
+    let ranks: Vec<usize> = values
+        .iter()
+        .map(|value| sorted.partition_point(|v| v.total_cmp(value).is_lt()))
+        .collect();
+    let mut counts = RankCounts::new(values.len());
+    (0..values.len())
+        .map(|i| {
+            if i >= window {
+                counts.add(ranks[i - window], -1);
+            }
+            counts.add(ranks[i], 1);
+
+            let rank = quantile * ((i + 1).min(window) - 1) as f32;
+            let lower = sorted[counts.find(rank.floor() as usize)];
+            let upper = sorted[counts.find(rank.ceil() as usize)];
+            lower + (upper - lower) * (rank - rank.floor())
+        })
+        .collect()
+}
+
+pub fn rolling_correlation(a: &[f32], b: &[f32], window: usize) -> Vec<f32> {
+    let length = a.len().min(b.len());
+    let a = &a[a.len() - length..];
+    let b = &b[b.len() - length..];
+    let (shift_a, shift_b) = (first_finite(a), first_finite(b));
+    let pair = |i: usize| (a[i] as f64 - shift_a, b[i] as f64 - shift_b);
+
+    //sums of x, y, xx, yy, xy, pairs with a non-finite value are only counted like in Moments
+    let mut sums = [0.0f64; 5];
+    let mut non_finite = 0;
+    let mut update = |(x, y): (f64, f64), sign: f64| {
+        if !x.is_finite() || !y.is_finite() {
+            if sign > 0.0 {
+                non_finite += 1;
+            } else {
+                non_finite -= 1;
+            }
+        } else {
+            for (sum, term) in sums.iter_mut().zip([x, y, x * x, y * y, x * y]) {
+                *sum += sign * term;
+            }
+        }
+        (sums, non_finite)
+    };
+    (0..length)
+        .map(|i| {
+            if i >= window {
+                update(pair(i - window), -1.0);
+            }
+            let ([x, y, xx, yy, xy], non_finite) = update(pair(i), 1.0);
+            if non_finite > 0 {
+                return f32::NAN;
+            }
+            let n = (i + 1).min(window) as f64;
+            let variance_x = xx / n - (x / n).powi(2);
+            let variance_y = yy / n - (y / n).powi(2);
+            if variance_x <= FLAT_VARIANCE || variance_y <= FLAT_VARIANCE {
+                0.0
+            } else {
+                ((xy / n - x * y / (n * n)) / (variance_x * variance_y).sqrt()).clamp(-1.0, 1.0)
+                    as f32
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::op::operation::num_pick::quantile;
+
+    fn assert_close(actual: Vec<f32>, expected: Vec<f32>) {
+        assert_eq!(
+            actual.len(),
+            expected.len(),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+        for (a, e) in actual.iter().zip(&expected) {
+            assert!((a - e).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    ///recomputes every window from scratch
+    fn naive(values: &[f32], window: usize, statistic: fn(&[f32]) -> f32) -> Vec<f32> {
+        (0..values.len())
+            .map(|i| statistic(&values[(i + 1).saturating_sub(window)..=i]))
+            .collect()
+    }
+
+    #[test]
+    fn test_rolling_mean_std() {
+        let values = [1.0, 3.0, 5.0, 7.0];
+        assert_close(rolling_mean(&values, 2), vec![1.0, 2.0, 4.0, 6.0]);
+        assert_close(rolling_std(&values, 2), vec![0.0, 1.0, 1.0, 1.0]);
+        assert_close(rolling_z_score(&values, 2), vec![0.0, 1.0, 1.0, 1.0]);
+        assert_close(rolling_mean(&[], 3), vec![]);
+
+        //precision holds for prices far from 0
+        let prices = [30000.0, 30001.0, 30000.0, 30001.0];
+        assert_close(rolling_std(&prices, 2), vec![0.0, 0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_rolling_extremes() {
+        let values = [3.0, 1.0, 4.0, 1.0, 5.0, 9.0, 2.0, 6.0];
+        let min = |window: &[f32]| window.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = |window: &[f32]| window.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        for window in 1..5 {
+            assert_close(rolling_min(&values, window), naive(&values, window, min));
+            assert_close(rolling_max(&values, window), naive(&values, window, max));
+        }
+    }
+
+    #[test]
+    fn test_rolling_quantile() {
+        let values = [3.0, 1.0, 4.0, 1.0, 5.0];
+        assert_close(
+            rolling_quantile(&values, 3, 0.5),
+            vec![3.0, 2.0, 3.0, 1.0, 4.0],
+        );
+        assert_close(rolling_quantile(&values, 3, 1.0), rolling_max(&values, 3));
+        assert_close(rolling_quantile(&values, 3, -1.0), rolling_min(&values, 3));
+        assert!(rolling_quantile(&values, 3, f32::NAN)
+            .iter()
+            .all(|value| value.is_nan()));
+
+        //duplicates and a list longer than a power of two against sorting every window
+        let values: Vec<f32> = (0..37).map(|i| ((i * 7) % 11) as f32).collect();
+        for window in [1, 4, 16, 40] {
+            for q in [0.0, 0.3, 0.5, 0.9] {
+                let expected = (0..values.len())
+                    .map(|i| quantile(&values[(i + 1).saturating_sub(window)..=i], q))
+                    .collect();
+                assert_close(rolling_quantile(&values, window, q), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rolling_skew_kurtosis() {
+        let values = [1.0, 2.0, 3.0, 10.0];
+        assert_close(rolling_skew(&[2.0, 2.0, 2.0], 3), vec![0.0, 0.0, 0.0]);
+        assert_close(rolling_skew(&values, 3)[2..].to_vec(), vec![0.0, 0.6655]);
+        assert_close(rolling_kurtosis(&values, 4)[3..].to_vec(), vec![-0.7696]);
+    }
+
+    #[test]
+    fn test_rolling_correlation() {
+        let a = [1.0, 2.0, 3.0, 4.0];
+        assert_close(
+            rolling_correlation(&a, &[2.0, 4.0, 6.0, 8.0], 3),
+            vec![0.0, 1.0, 1.0, 1.0],
+        );
+        assert_close(
+            rolling_correlation(&a, &[9.0, 8.0, 6.0, 7.0], 2),
+            vec![0.0, -1.0, -1.0, 1.0],
+        );
+        //tail-aligned to the shorter list
+        assert_close(rolling_correlation(&a, &[1.0, 1.0], 2), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_non_finite_values_leave_the_window() {
+        let nan = f32::NAN;
+        let values = [1.0, 2.0, nan, 3.0, 4.0, 5.0, 6.0, 7.0];
+        let mean = rolling_mean(&values, 2);
+        assert!(mean[2].is_nan() && mean[3].is_nan());
+        assert_close(mean[4..].to_vec(), vec![3.5, 4.5, 5.5, 6.5]);
+        assert_close(mean[..2].to_vec(), vec![1.0, 1.5]);
+        assert_close(rolling_std(&values, 3)[5..].to_vec(), vec![0.8165; 3]);
+
+        //a leading NaN doesn't become the shift
+        let values = [f32::INFINITY, 1.0, 3.0];
+        assert_close(rolling_mean(&values, 2)[2..].to_vec(), vec![2.0]);
+        assert_close(rolling_skew(&values, 2)[2..].to_vec(), vec![0.0]);
+
+        let a = [1.0, nan, 3.0, 4.0, 5.0];
+        let b = [2.0, 4.0, 6.0, 8.0, 10.0];
+        let correlation = rolling_correlation(&a, &b, 2);
+        assert!(correlation[1].is_nan() && correlation[2].is_nan());
+        assert_close(correlation[3..].to_vec(), vec![1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_huge_window() {
+        let values = [3.0, 1.0, 4.0, 1.0, 5.0];
+        let window = clamp_window(1e30);
+        assert_eq!(window, usize::MAX);
+        //the whole list seen so far, like a window as long as the list
+        let longest = values.len();
+        assert_eq!(rolling_min(&values, window), rolling_min(&values, longest));
+        assert_eq!(rolling_max(&values, window), vec![3.0, 3.0, 4.0, 4.0, 5.0]);
+        assert_close(
+            rolling_mean(&values, window),
+            rolling_mean(&values, longest),
+        );
+        assert_close(
+            rolling_quantile(&values, window, 0.5),
+            rolling_quantile(&values, longest, 0.5),
+        );
+        assert_close(
+            rolling_correlation(&values, &values, window),
+            rolling_correlation(&values, &values, longest),
+        );
+    }
+}
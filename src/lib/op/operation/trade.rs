@@ -4,6 +4,8 @@ use std::fmt::Display;
 type MarketIndex = Operand;
 type MarketPrice = Operand;
 type MarketAmount = Operand;
+///trigger price of stop and OCO orders, ignored by the other order types
+type StopPrice = Operand;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum TradeLeverage {
@@ -12,6 +14,16 @@ pub enum TradeLeverage {
     X5,
 }
 
+impl TradeLeverage {
+    pub fn factor(self) -> f32 {
+        match self {
+            TradeLeverage::X1 => 1.0,
+            TradeLeverage::X2 => 2.0,
+            TradeLeverage::X5 => 5.0,
+        }
+    }
+}
+
 impl Display for TradeLeverage {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -27,9 +39,67 @@ pub type TradeOperation = (
     MarketIndex,
     MarketPrice,
     MarketAmount,
-    // TradeLeverage,
+    OrderType,
+    TimeInForce,
+    StopPrice,
+    TradeLeverage,
 );
 
+///How the price of a trade is used, buys trigger on the candle high and sells on the low
+/// for stops, limits the other way round
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OrderType {
+    ///fills at the open of the next candle, the price is ignored
+    Market,
+    ///fills at the price or better
+    Limit,
+    ///becomes a market order once the price trades through the stop price
+    StopMarket,
+    ///becomes a limit order at the price once the price trades through the stop price
+    StopLimit,
+    ///a limit order that only closes an open position
+    TakeProfit,
+    ///one-cancels-other exit, a take-profit at the price and a stop-market at the stop price,
+    /// whichever triggers first cancels the other
+    Oco,
+}
+
+impl Display for OrderType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            OrderType::Market => write!(f, "Market"),
+            OrderType::Limit => write!(f, "Limit"),
+            OrderType::StopMarket => write!(f, "StopMarket"),
+            OrderType::StopLimit => write!(f, "StopLimit"),
+            OrderType::TakeProfit => write!(f, "TakeProfit"),
+            OrderType::Oco => write!(f, "Oco"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TimeInForce {
+    ///rests until it fills or is cancelled
+    GoodTilCancelled,
+    ///fills what it can on the first candle it is live for, the rest is cancelled
+    ImmediateOrCancel,
+    ///fills completely on the first candle it is live for or not at all
+    FillOrKill,
+    ///rests for at most this many candles
+    GoodForCandles(u32),
+}
+
+impl Display for TimeInForce {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TimeInForce::GoodTilCancelled => write!(f, "GTC"),
+            TimeInForce::ImmediateOrCancel => write!(f, "IOC"),
+            TimeInForce::FillOrKill => write!(f, "FOK"),
+            TimeInForce::GoodForCandles(candles) => write!(f, "GF{}", candles),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum TradeOperator {
     Buy,
@@ -61,13 +131,33 @@ impl Display for TradeOperator {
 
 pub type TradeList = Vec<Trade>;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Trade {
     pub operator: TradeOperator,
     pub index: usize,
     pub price: f32,
     pub amount: f32,
-    // pub leverage: TradeLeverage,
+    pub order_type: OrderType,
+    pub time_in_force: TimeInForce,
+    pub stop_price: f32,
+    ///only used when trading perpetual futures, spot trades are always X1
+    pub leverage: TradeLeverage,
+}
+
+impl Trade {
+    ///a good til cancelled market order
+    pub fn market(operator: TradeOperator, index: usize, price: f32, amount: f32) -> Trade {
+        Trade {
+            operator,
+            index,
+            price,
+            amount,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::GoodTilCancelled,
+            stop_price: 0.0,
+            leverage: TradeLeverage::X1,
+        }
+    }
 }
 
 //implment Display for Trade struct
@@ -75,38 +165,33 @@ impl Display for Trade {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
-            "Trade: {}, {}, {}, {}",
-            self.operator, self.index, self.price, self.amount,
+            "Trade: {}, {}, {}, {}, {} {}, stop {}, {}",
+            self.operator,
+            self.index,
+            self.price,
+            self.amount,
+            self.order_type,
+            self.time_in_force,
+            self.stop_price,
+            self.leverage,
         )
     }
 }
 
 #[test]
 fn test_partial_eq() {
-    let trade1 = Trade {
-        operator: TradeOperator::Buy,
-        index: 1,
-        price: 1.0,
-        amount: 1.0,
-        // leverage: TradeLeverage::X1,
-    };
-    let trade2 = Trade {
-        operator: TradeOperator::Buy,
-        index: 1,
-        price: 1.0,
-        amount: 1.0,
-        // leverage: TradeLeverage::X1,
-    };
+    let trade1 = Trade::market(TradeOperator::Buy, 1, 1.0, 1.0);
+    let trade2 = Trade::market(TradeOperator::Buy, 1, 1.0, 1.0);
     assert_eq!(trade1, trade2);
 
-    let trade3 = Trade {
-        operator: TradeOperator::Sell,
-        index: 1,
-        price: 1.0,
-        amount: 1.0,
-        // leverage: TradeLeverage::X1,
-    };
+    let trade3 = Trade::market(TradeOperator::Sell, 1, 1.0, 1.0);
 
     assert_ne!(trade1, trade3);
     assert_eq!(trade1, trade1);
+
+    let trade4 = Trade {
+        order_type: OrderType::Limit,
+        ..Trade::market(TradeOperator::Buy, 1, 1.0, 1.0)
+    };
+    assert_ne!(trade1, trade4);
 }
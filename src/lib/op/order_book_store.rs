@@ -0,0 +1,197 @@
+use crate::lib::op::operation::market_data::OrderBook;
+use std::fmt::Display;
+
+///Historical level 2 snapshots of one market, ordered by timestamp
+pub struct OrderBookStore {
+    snapshots: Vec<(u64, OrderBook)>,
+    ///levels kept per side, deeper levels are dropped on insert
+    max_depth: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OrderBookStoreError {
+    ///the snapshot is at or before the last stored one
+    OutOfOrder { timestamp: u64, last_timestamp: u64 },
+    ///a side's price and volume lists have different lengths
+    MismatchedLevels { timestamp: u64 },
+}
+
+impl Display for OrderBookStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            OrderBookStoreError::OutOfOrder {
+                timestamp,
+                last_timestamp,
+            } => write!(
+                f,
+                "snapshot at {} is not after the last snapshot at {}",
+                timestamp, last_timestamp
+            ),
+            OrderBookStoreError::MismatchedLevels { timestamp } => write!(
+                f,
+                "snapshot at {} has a different number of prices and volumes",
+                timestamp
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OrderBookStoreError {}
+
+impl OrderBookStore {
+    pub fn new(max_depth: usize) -> OrderBookStore {
+        OrderBookStore {
+            snapshots: Vec::new(),
+            max_depth,
+        }
+    }
+
+    ///stores a snapshot, sorting each side best price first
+    pub fn insert(
+        &mut self,
+        timestamp: u64,
+        mut order_book: OrderBook,
+    ) -> Result<(), OrderBookStoreError> {
+        if let Some((last_timestamp, _)) = self.snapshots.last() {
+            if timestamp <= *last_timestamp {
+                return Err(OrderBookStoreError::OutOfOrder {
+                    timestamp,
+                    last_timestamp: *last_timestamp,
+                });
+            }
+        }
+        if order_book.bid_price.len() != order_book.bid_volume.len()
+            || order_book.ask_price.len() != order_book.ask_volume.len()
+        {
+            return Err(OrderBookStoreError::MismatchedLevels { timestamp });
+        }
+
+        let (bid_price, bid_volume) =
+            sort_levels(&order_book.bid_price, &order_book.bid_volume, |a, b| {
+                b.total_cmp(a)
+            });
+        let (ask_price, ask_volume) =
+            sort_levels(&order_book.ask_price, &order_book.ask_volume, |a, b| {
+                a.total_cmp(b)
+            });
+        order_book = OrderBook {
+            bid_price,
+            bid_volume,
+            ask_price,
+            ask_volume,
+        };
+        order_book.truncate(self.max_depth);
+        self.snapshots.push((timestamp, order_book));
+        Ok(())
+    }
+
+    ///The latest snapshot at or before timestamp, so a backtest never sees a book from the future,
+    /// None if timestamp is before the first snapshot
+    pub fn get_snapshot(&self, timestamp: u64, depth: usize) -> Option<OrderBook> {
+        let count = self
+            .snapshots
+            .partition_point(|(snapshot_timestamp, _)| *snapshot_timestamp <= timestamp);
+        let (_, order_book) = self.snapshots.get(count.checked_sub(1)?)?;
+        let mut order_book = order_book.clone();
+        order_book.truncate(depth);
+        Some(order_book)
+    }
+
+    pub fn get_snapshot_count(&self) -> usize {
+        self.snapshots.len()
+    }
+}
+
+fn sort_levels(
+    prices: &[f32],
+    volumes: &[f32],
+    compare: fn(&f32, &f32) -> std::cmp::Ordering,
+) -> (Vec<f32>, Vec<f32>) {
+    let mut levels: Vec<(f32, f32)> = prices
+        .iter()
+        .copied()
+        .zip(volumes.iter().copied())
+        .collect();
+    levels.sort_by(|(price_a, _), (price_b, _)| compare(price_a, price_b));
+    levels.into_iter().unzip()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order_book(best_bid: f32, best_ask: f32) -> OrderBook {
+        OrderBook {
+            bid_price: vec![best_bid - 2.0, best_bid, best_bid - 1.0],
+            bid_volume: vec![3.0, 1.0, 2.0],
+            ask_price: vec![best_ask + 1.0, best_ask, best_ask + 2.0],
+            ask_volume: vec![2.0, 1.0, 3.0],
+        }
+    }
+
+    #[test]
+    fn test_insert_sorts_and_truncates() {
+        let mut order_book_store = OrderBookStore::new(2);
+        order_book_store
+            .insert(100, order_book(10.0, 11.0))
+            .unwrap();
+        let snapshot = order_book_store.get_snapshot(100, 10).unwrap();
+        assert_eq!(snapshot.bid_price, vec![10.0, 9.0]);
+        assert_eq!(snapshot.bid_volume, vec![1.0, 2.0]);
+        assert_eq!(snapshot.ask_price, vec![11.0, 12.0]);
+        assert_eq!(snapshot.ask_volume, vec![1.0, 2.0]);
+        assert_eq!(
+            order_book_store.get_snapshot(100, 1).unwrap().ask_price,
+            vec![11.0]
+        );
+    }
+
+    #[test]
+    fn test_get_snapshot_never_looks_ahead() {
+        let mut order_book_store = OrderBookStore::new(5);
+        order_book_store
+            .insert(100, order_book(10.0, 11.0))
+            .unwrap();
+        order_book_store
+            .insert(200, order_book(20.0, 21.0))
+            .unwrap();
+        assert_eq!(order_book_store.get_snapshot(99, 5), None);
+        assert_eq!(
+            order_book_store.get_snapshot(150, 5).unwrap().bid_price[0],
+            10.0
+        );
+        assert_eq!(
+            order_book_store.get_snapshot(200, 5).unwrap().bid_price[0],
+            20.0
+        );
+        assert_eq!(
+            order_book_store
+                .get_snapshot(u64::MAX, 5)
+                .unwrap()
+                .bid_price[0],
+            20.0
+        );
+    }
+
+    #[test]
+    fn test_insert_errors() {
+        let mut order_book_store = OrderBookStore::new(5);
+        order_book_store
+            .insert(100, order_book(10.0, 11.0))
+            .unwrap();
+        assert_eq!(
+            order_book_store.insert(100, order_book(10.0, 11.0)),
+            Err(OrderBookStoreError::OutOfOrder {
+                timestamp: 100,
+                last_timestamp: 100
+            })
+        );
+        let mut mismatched = order_book(10.0, 11.0);
+        mismatched.ask_volume.pop();
+        assert_eq!(
+            order_book_store.insert(200, mismatched),
+            Err(OrderBookStoreError::MismatchedLevels { timestamp: 200 })
+        );
+        assert_eq!(order_book_store.get_snapshot_count(), 1);
+    }
+}
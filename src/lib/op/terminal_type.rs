@@ -7,9 +7,20 @@ use super::environment::Env;
 
 #[derive(Clone, Debug)]
 
+///Every terminal type can be read as any other, the coercions are:
+/// - to_f32: Bool is 1 or 0, lists are their length, timestamps lose precision past 2^24 ms
+/// - to_i64 and to_usize: Numbers are truncated towards 0, NaN is 0, out of range values
+///   saturate and to_usize turns negative values into 0
+/// - to_bool: Bool is itself, lists are true when not empty, everything else when > 0
+/// - to_list: lists are themselves, everything else is a one element list of its to_f32
 pub enum TerminalType {
     Number(f32),
     NumberList(Vec<f32>),
+    Bool(bool),
+    Int(i64),
+    ///milliseconds since the unix epoch
+    Timestamp(i64),
+    MarketIndex(usize),
 }
 
 impl TerminalType {
@@ -19,6 +30,19 @@ impl TerminalType {
         match self {
             TerminalType::Number(n) => *n as f32,
             TerminalType::NumberList(n) => n.len() as f32,
+            TerminalType::Bool(b) => *b as i32 as f32,
+            TerminalType::Int(n) | TerminalType::Timestamp(n) => *n as f32,
+            TerminalType::MarketIndex(index) => *index as f32,
+        }
+    }
+
+    pub fn to_i64(&self) -> i64 {
+        match self {
+            TerminalType::Number(n) => *n as i64,
+            TerminalType::NumberList(n) => n.len() as i64,
+            TerminalType::Bool(b) => *b as i64,
+            TerminalType::Int(n) | TerminalType::Timestamp(n) => *n,
+            TerminalType::MarketIndex(index) => *index as i64,
         }
     }
 
@@ -26,6 +50,9 @@ impl TerminalType {
         match self {
             TerminalType::Number(n) => *n as usize,
             TerminalType::NumberList(n) => n.len(),
+            TerminalType::Bool(b) => *b as usize,
+            TerminalType::Int(n) | TerminalType::Timestamp(n) => (*n).max(0) as usize,
+            TerminalType::MarketIndex(index) => *index,
         }
     }
 
@@ -33,15 +60,26 @@ impl TerminalType {
         match self {
             TerminalType::Number(n) => *n > 0.0,
             TerminalType::NumberList(n) => n.len() > 0,
+            TerminalType::Bool(b) => *b,
+            TerminalType::Int(n) | TerminalType::Timestamp(n) => *n > 0,
+            TerminalType::MarketIndex(index) => *index > 0,
         }
     }
     pub fn to_list(&self) -> Vec<f32> {
         match self {
-            TerminalType::Number(n) => vec![*n as f32],
             TerminalType::NumberList(n) => n.clone(),
+            _ => vec![self.to_f32()],
         }
     }
 
+    ///Int, Timestamp and MarketIndex compare exactly with each other
+    fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            TerminalType::Int(_) | TerminalType::Timestamp(_) | TerminalType::MarketIndex(_)
+        )
+    }
+
     pub fn evaluate_branch_terminal(
         &self,
         operand_left: &Operand,
@@ -61,23 +99,63 @@ impl TerminalType {
     }
 }
 
+///Equality agrees with the ordering, so two lists are equal when their elements are,
+/// not just their lengths as when it went through to_f32
 impl PartialEq for TerminalType {
     fn eq(&self, other: &Self) -> bool {
-        self.to_f32() == other.to_f32()
+        self.partial_cmp(other) == Some(Ordering::Equal)
     }
 }
 
 impl PartialOrd for TerminalType {
     fn partial_cmp(&self, other: &TerminalType) -> Option<Ordering> {
         match (self, other) {
-            (TerminalType::Number(n), TerminalType::Number(m)) => n.partial_cmp(m),
             (TerminalType::NumberList(n), TerminalType::NumberList(m)) => n.partial_cmp(m),
-            (TerminalType::NumberList(n), TerminalType::Number(m)) => {
-                (n.len() as f32).partial_cmp(m)
-            }
-            (TerminalType::Number(n), TerminalType::NumberList(m)) => {
-                n.partial_cmp(&(m.len() as f32))
+            _ if self.is_integer() && other.is_integer() => {
+                self.to_i64().partial_cmp(&other.to_i64())
             }
+            _ => self.to_f32().partial_cmp(&other.to_f32()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coercions() {
+        assert_eq!(TerminalType::Number(-2.7).to_usize(), 0);
+        assert_eq!(TerminalType::Number(f32::NAN).to_i64(), 0);
+        assert_eq!(TerminalType::Number(2.7).to_i64(), 2);
+        assert_eq!(TerminalType::Int(-3).to_usize(), 0);
+        assert_eq!(TerminalType::Bool(true).to_f32(), 1.0);
+        assert!(!TerminalType::MarketIndex(0).to_bool());
+        assert!(TerminalType::NumberList(vec![0.0]).to_bool());
+        assert_eq!(TerminalType::Timestamp(5).to_list(), vec![5.0]);
+    }
+
+    #[test]
+    fn test_timestamps_compare_exactly() {
+        let timestamp = TerminalType::Timestamp(1_700_000_000_001);
+        let next = TerminalType::Timestamp(1_700_000_000_002);
+        //both round to the same f32
+        assert_eq!(timestamp.to_f32(), next.to_f32());
+        assert!(timestamp < next);
+        assert!(timestamp != next);
+        assert_eq!(timestamp.to_i64(), 1_700_000_000_001);
+        assert_eq!(TerminalType::Bool(true), TerminalType::Number(1.0));
+        assert_eq!(TerminalType::MarketIndex(2), TerminalType::Int(2));
+    }
+
+    #[test]
+    fn test_lists_compare_by_elements() {
+        let list = TerminalType::NumberList(vec![1.0, 2.0]);
+        assert_eq!(list, TerminalType::NumberList(vec![1.0, 2.0]));
+        //same length, different elements
+        assert_ne!(list, TerminalType::NumberList(vec![1.0, 3.0]));
+        assert!(list < TerminalType::NumberList(vec![1.0, 3.0]));
+        //a list against a single value still goes through its length
+        assert_eq!(list, TerminalType::Number(2.0));
+    }
+}
@@ -1,100 +1,380 @@
 use lerp::Lerp;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
 
-struct TickerStore {
+pub struct TickerStore {
     tickers: Vec<Ticker>,
-    ticker_size: u64, //in minutes
+    ticker_size: u64, //in milliseconds
     start_timestamp: u64,
+    gap_policy: GapPolicy,
+    ///false for every ticker that was synthesized to fill a gap rather than ingested
+    valid: Vec<bool>,
+    gaps: Vec<Gap>,
 }
 
-#[derive(Copy, Clone, Lerp)]
-struct Ticker {
-    high: f32,
-    low: f32,
+#[derive(Copy, Clone, Debug, Default, PartialEq, Lerp)]
+pub struct Ticker {
+    pub open: f32,
+    pub high: f32,
+    pub low: f32,
+    pub close: f32,
+    pub volume: f32,
+    pub trade_count: f32,
 }
-///ticker_size is in minutes
+
+impl Ticker {
+    ///placeholder stored for missing intervals under `GapPolicy::MarkMissing`
+    pub fn missing() -> Ticker {
+        Ticker {
+            open: f32::NAN,
+            high: f32::NAN,
+            low: f32::NAN,
+            close: f32::NAN,
+            volume: f32::NAN,
+            trade_count: f32::NAN,
+        }
+    }
+}
+
+///How `TickerStore::sample` resolves a timestamp that falls between two tickers
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InterpolationMode {
+    ///the closer of the two tickers, the later one on a tie
+    Nearest,
+    ///the ticker whose interval contains the timestamp
+    Floor,
+    ///the first ticker at or after the timestamp
+    Ceil,
+    ///linear interpolation between the two tickers
+    Linear,
+}
+
+///What to do with the intervals missing between the last stored ticker and an inserted one,
+/// e.g. because the exchange was down
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GapPolicy {
+    ///repeat the last ticker before the gap
+    ForwardFill,
+    ///interpolate linearly between the tickers on both sides of the gap
+    LerpFill,
+    ///store NaN placeholders, the validity mask tells them apart from real tickers
+    MarkMissing,
+    ///refuse the insert
+    Reject,
+}
+
+impl Display for GapPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GapPolicy::ForwardFill => write!(f, "ForwardFill"),
+            GapPolicy::LerpFill => write!(f, "LerpFill"),
+            GapPolicy::MarkMissing => write!(f, "MarkMissing"),
+            GapPolicy::Reject => write!(f, "Reject"),
+        }
+    }
+}
+
+///A run of consecutive missing intervals, `start_index` is the index of the first missing ticker
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Gap {
+    pub start_index: usize,
+    pub length: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GapReport {
+    pub gaps: Vec<Gap>,
+    ///total number of missing intervals over all gaps
+    pub missing_count: usize,
+    pub longest_gap: usize,
+    ///number of intervals covered, missing ones included
+    pub interval_count: usize,
+}
+
+impl GapReport {
+    fn from_gaps(gaps: Vec<Gap>, interval_count: usize) -> GapReport {
+        GapReport {
+            missing_count: gaps.iter().map(|gap| gap.length).sum(),
+            longest_gap: gaps.iter().map(|gap| gap.length).max().unwrap_or(0),
+            gaps,
+            interval_count,
+        }
+    }
+
+    pub fn gap_count(&self) -> usize {
+        self.gaps.len()
+    }
+
+    ///fraction of intervals that hold real data, 1.0 for an empty dataset
+    pub fn coverage(&self) -> f64 {
+        if self.interval_count == 0 {
+            1.0
+        } else {
+            1.0 - self.missing_count as f64 / self.interval_count as f64
+        }
+    }
+}
+
+impl Display for GapReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} gaps, {} of {} intervals missing, longest gap {} intervals, coverage {:.2}%",
+            self.gap_count(),
+            self.missing_count,
+            self.interval_count,
+            self.longest_gap,
+            self.coverage() * 100.0
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TickerStoreError {
+    ///the timestamp doesn't fall on a multiple of ticker_size from start_timestamp
+    MisalignedTimestamp { timestamp: u64 },
+    ///the timestamp is at or before the last stored ticker
+    OutOfOrder { timestamp: u64, last_timestamp: u64 },
+    ///the insert would leave a gap and the policy is `GapPolicy::Reject`
+    Gap { timestamp: u64, missing: usize },
+}
+
+impl Display for TickerStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TickerStoreError::MisalignedTimestamp { timestamp } => {
+                write!(
+                    f,
+                    "timestamp {} is not aligned to the ticker size",
+                    timestamp
+                )
+            }
+            TickerStoreError::OutOfOrder {
+                timestamp,
+                last_timestamp,
+            } => write!(
+                f,
+                "timestamp {} is not after the last ticker at {}",
+                timestamp, last_timestamp
+            ),
+            TickerStoreError::Gap { timestamp, missing } => write!(
+                f,
+                "ticker at {} would leave a gap of {} intervals",
+                timestamp, missing
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TickerStoreError {}
+
+///Finds the gaps in a sorted list of candle timestamps without building a store,
+/// timestamps that don't land on the grid are counted in the interval they fall into
+pub fn detect_gaps(timestamps: &[u64], ticker_size: u64) -> GapReport {
+    let start_timestamp = match timestamps.first() {
+        Some(start_timestamp) => *start_timestamp,
+        None => return GapReport::default(),
+    };
+    let mut gaps = Vec::new();
+    let mut expected_index = 0;
+    for timestamp in timestamps {
+        let index = ((timestamp.saturating_sub(start_timestamp)) / ticker_size) as usize;
+        if index > expected_index {
+            gaps.push(Gap {
+                start_index: expected_index,
+                length: index - expected_index,
+            });
+        }
+        expected_index = expected_index.max(index + 1);
+    }
+    GapReport::from_gaps(gaps, expected_index)
+}
+///ticker_size is in milliseconds
 /// 
 impl TickerStore {
-    fn new(ticker_size: u64, start_timestamp: u64) -> TickerStore {
+    pub fn new(ticker_size: u64, start_timestamp: u64) -> TickerStore {
+        TickerStore::with_gap_policy(ticker_size, start_timestamp, GapPolicy::ForwardFill)
+    }
+
+    pub fn with_gap_policy(
+        ticker_size: u64,
+        start_timestamp: u64,
+        gap_policy: GapPolicy,
+    ) -> TickerStore {
         TickerStore {
             tickers: Vec::new(),
             ticker_size,
             start_timestamp,
+            gap_policy,
+            valid: Vec::new(),
+            gaps: Vec::new(),
         }
     }
 
-    fn get_ticker(&self, timestamp: u64) -> Ticker {
-        let index = self.timestamp_to_index(timestamp);
-        self.tickers[index]
+    ///the ticker whose interval contains timestamp, clamped to the first and last ticker
+    ///
+    /// panics on an empty store, use `sample` to handle that case
+    pub fn get_ticker(&self, timestamp: u64) -> Ticker {
+        self.sample(timestamp, InterpolationMode::Floor)
+            .expect("get_ticker called on an empty TickerStore")
     }
 
-    fn get_ticker_lerp(&self, timestamp: u64) -> Ticker {
-        let index = self.timestamp_to_float_index(timestamp);
-        if index < 0.0 {
-            Ticker {
-                high: 0.0,
-                low: 0.0,
-            }
-        } else if index > self.tickers.len() as f64 {
-            self.tickers[self.tickers.len() - 1]
-        } else {
-            let prev_index = (index - 1 as f64) as usize;
-            let next_index = (index + 1 as f64) as usize;
-            let next_index = if next_index >= self.tickers.len() {
-                self.tickers.len()
-            } else {
-                next_index
-            };
-
-            let prev_ticker = &self.tickers[prev_index];
-            let next_ticker = &self.tickers[next_index];
+    pub fn get_ticker_lerp(&self, timestamp: u64) -> Option<Ticker> {
+        self.sample(timestamp, InterpolationMode::Linear)
+    }
 
-            //lerp between prev and next
-            let lerp_value = (index - prev_index as f64) / (next_index as f64 - prev_index as f64);
-            let lerp_ticker = prev_ticker.lerp(*next_ticker, lerp_value);
+    ///Retrieves a ticker for a timestamp that doesn't have to fall on an interval cutoff.
+    ///
+    /// Returns None for an empty store. Timestamps before the first ticker resolve to the first ticker
+    /// and timestamps after the last ticker resolve to the last one, whatever the mode,
+    /// so a single-ticker store always returns its only ticker.
+    pub fn sample(&self, timestamp: u64, mode: InterpolationMode) -> Option<Ticker> {
+        let last_index = self.tickers.len().checked_sub(1)?;
+        if timestamp <= self.start_timestamp {
+            return Some(self.tickers[0]);
+        }
 
-            lerp_ticker
+        let offset = timestamp - self.start_timestamp;
+        let floor_index = (offset / self.ticker_size) as usize;
+        if floor_index >= last_index {
+            return Some(self.tickers[last_index]);
         }
+        let remainder = offset % self.ticker_size;
+
+        let ticker = match mode {
+            InterpolationMode::Floor => self.tickers[floor_index],
+            InterpolationMode::Ceil if remainder == 0 => self.tickers[floor_index],
+            InterpolationMode::Ceil => self.tickers[floor_index + 1],
+            //ties go to the later ticker
+            InterpolationMode::Nearest if remainder * 2 >= self.ticker_size => {
+                self.tickers[floor_index + 1]
+            }
+            InterpolationMode::Nearest => self.tickers[floor_index],
+            InterpolationMode::Linear => self.tickers[floor_index].lerp(
+                self.tickers[floor_index + 1],
+                remainder as f64 / self.ticker_size as f64,
+            ),
+        };
+        Some(ticker)
     }
 
-    fn timestamp_to_float_index(&self, timestamp: u64) -> f64 {
+    pub fn timestamp_to_float_index(&self, timestamp: u64) -> f64 {
+        let last_index = self.tickers.len().saturating_sub(1);
         if timestamp < self.start_timestamp {
-            return 0.0;
-        } else if timestamp
-            >= self.start_timestamp + self.ticker_size * (self.tickers.len() - 1) as u64
-        {
-            return self.tickers.len() as f64 - 1.0;
+            0.0
         } else {
             let index =
                 (timestamp as f64 - (self.start_timestamp as f64)) / self.ticker_size as f64;
 
-            index
+            index.min(last_index as f64)
         }
     }
 
-    fn timestamp_to_index(&self, timestamp: u64) -> usize {
-        // check if timestamp less than start_timestamp
-        // if so, return 0
-        // else check if timestamp is greater than last timestamp using the length of the tickers
-        // else calculate index from timestamp
+    pub fn timestamp_to_index(&self, timestamp: u64) -> usize {
+        // if timestamp is less than start_timestamp, return 0
+        // else calculate index from timestamp, clamped to the last ticker
+        // an empty store also returns 0
 
+        let last_index = self.tickers.len().saturating_sub(1);
         if timestamp < self.start_timestamp {
-            0 as usize
-        } else if timestamp
-            >= self.start_timestamp + self.ticker_size * (self.tickers.len() - 1) as u64
-        {
-            self.tickers.len() - 1
+            0
         } else {
             let index = (timestamp - self.start_timestamp) / self.ticker_size;
-            index as usize
+            (index as usize).min(last_index)
         }
     }
 
-    fn get_ticker_count(&self) -> usize {
+    pub fn ticker_size(&self) -> u64 {
+        self.ticker_size
+    }
+
+    pub fn start_timestamp(&self) -> u64 {
+        self.start_timestamp
+    }
+
+    pub fn gap_policy(&self) -> GapPolicy {
+        self.gap_policy
+    }
+
+    pub fn tickers(&self) -> &[Ticker] {
+        &self.tickers
+    }
+
+    pub fn get_ticker_count(&self) -> usize {
         self.tickers.len()
     }
 
-    fn add_ticker(&mut self, ticker: Ticker) {
+    ///appends a ticker directly after the last one, without any gap checks
+    pub fn add_ticker(&mut self, ticker: Ticker) {
         self.tickers.push(ticker);
+        self.valid.push(true);
+    }
+
+    ///timestamp the next contiguous ticker would have
+    pub fn next_timestamp(&self) -> u64 {
+        self.start_timestamp + self.ticker_size * self.tickers.len() as u64
+    }
+
+    ///inserts a ticker at its timestamp, filling any intervals missing since the last ticker
+    /// according to the store's gap policy
+    pub fn insert_ticker(&mut self, timestamp: u64, ticker: Ticker) -> Result<(), TickerStoreError> {
+        if timestamp < self.start_timestamp
+            || !(timestamp - self.start_timestamp).is_multiple_of(self.ticker_size)
+        {
+            return Err(TickerStoreError::MisalignedTimestamp { timestamp });
+        }
+        let next_timestamp = self.next_timestamp();
+        if timestamp < next_timestamp {
+            return Err(TickerStoreError::OutOfOrder {
+                timestamp,
+                last_timestamp: next_timestamp - self.ticker_size,
+            });
+        }
+
+        let missing = ((timestamp - next_timestamp) / self.ticker_size) as usize;
+        if missing > 0 {
+            if self.gap_policy == GapPolicy::Reject {
+                return Err(TickerStoreError::Gap { timestamp, missing });
+            }
+            self.fill_gap(missing, ticker);
+        }
+
+        self.add_ticker(ticker);
+        Ok(())
+    }
+
+    fn fill_gap(&mut self, missing: usize, next_ticker: Ticker) {
+        //a gap at the very start has nothing before it, so it is filled backwards from the new ticker
+        let prev_ticker = self.tickers.last().copied().unwrap_or(next_ticker);
+        self.gaps.push(Gap {
+            start_index: self.tickers.len(),
+            length: missing,
+        });
+        for i in 0..missing {
+            let ticker = match self.gap_policy {
+                GapPolicy::ForwardFill => prev_ticker,
+                GapPolicy::LerpFill => {
+                    prev_ticker.lerp(next_ticker, (i + 1) as f64 / (missing + 1) as f64)
+                }
+                GapPolicy::MarkMissing => Ticker::missing(),
+                GapPolicy::Reject => unreachable!("rejected gaps are never filled"),
+            };
+            self.tickers.push(ticker);
+            self.valid.push(false);
+        }
+    }
+
+    ///whether the ticker at index was ingested rather than synthesized for a gap
+    pub fn is_valid(&self, index: usize) -> bool {
+        self.valid.get(index).copied().unwrap_or(false)
+    }
+
+    pub fn validity_mask(&self) -> &[bool] {
+        &self.valid
+    }
+
+    pub fn gap_report(&self) -> GapReport {
+        GapReport::from_gaps(self.gaps.clone(), self.tickers.len())
     }
 }
 
@@ -102,6 +382,7 @@ impl TickerStore {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
     const START_TIMESTAMP: u64 = 1546300800;
     #[test]
     fn test_ticker_store_new() {
@@ -115,6 +396,7 @@ mod tests {
         let ticker = Ticker {
             high: 1.0,
             low: 0.0,
+            ..Ticker::default()
         };
         ticker_store.add_ticker(ticker);
         assert_eq!(ticker_store.get_ticker_count(), 1);
@@ -126,10 +408,12 @@ mod tests {
         ticker_store.add_ticker(Ticker {
             high: 1.0,
             low: 0.0,
+            ..Ticker::default()
         });
         ticker_store.add_ticker(Ticker {
             high: 2.0,
             low: 0.0,
+            ..Ticker::default()
         });
         let ticker = ticker_store.get_ticker(START_TIMESTAMP);
         assert_eq!(ticker.high, 1.0);
@@ -151,10 +435,12 @@ mod tests {
         ticker_store.add_ticker(Ticker {
             high: 1.0,
             low: 0.0,
+            ..Ticker::default()
         });
         ticker_store.add_ticker(Ticker {
             high: 2.0,
             low: 0.0,
+            ..Ticker::default()
         });
         let index = ticker_store.timestamp_to_float_index(START_TIMESTAMP);
         assert_eq!(index, 0.0);
@@ -174,15 +460,17 @@ mod tests {
         ticker_store.add_ticker(Ticker {
             high: 1.0,
             low: 0.0,
+            ..Ticker::default()
         });
         ticker_store.add_ticker(Ticker {
             high: 2.0,
             low: 0.0,
+            ..Ticker::default()
         });
-        let ticker = ticker_store.get_ticker_lerp(START_TIMESTAMP);
+        let ticker = ticker_store.get_ticker_lerp(START_TIMESTAMP).unwrap();
         assert_eq!(ticker.high, 1.0);
         assert_eq!(ticker.low, 0.0);
-        let ticker = ticker_store.get_ticker_lerp(START_TIMESTAMP + 14);
+        let ticker = ticker_store.get_ticker_lerp(START_TIMESTAMP + 14).unwrap();
         assert_eq!(ticker.high > 1.7, true);
         assert_eq!(ticker.high < 2.0, true);
         assert_eq!(ticker.low, 0.0);
@@ -193,6 +481,7 @@ mod tests {
         let ticker = Ticker {
             high: 1.0,
             low: 0.0,
+            ..Ticker::default()
         };
         ticker_store.add_ticker(ticker);
         ticker_store.add_ticker(ticker);
@@ -230,4 +519,277 @@ mod tests {
         timestamp_index_test_generic(15, START_TIMESTAMP);
         timestamp_index_test_generic(5, START_TIMESTAMP);
     }
+
+    fn ticker(high: f32) -> Ticker {
+        Ticker {
+            high,
+            ..Ticker::default()
+        }
+    }
+
+    #[test]
+    fn test_default_gap_policy() {
+        let ticker_store = TickerStore::new(15, START_TIMESTAMP);
+        assert_eq!(ticker_store.gap_policy(), GapPolicy::ForwardFill);
+    }
+
+    #[test]
+    fn test_insert_ticker_contiguous() {
+        let mut ticker_store = TickerStore::with_gap_policy(15, START_TIMESTAMP, GapPolicy::Reject);
+        ticker_store
+            .insert_ticker(START_TIMESTAMP, ticker(1.0))
+            .unwrap();
+        ticker_store
+            .insert_ticker(START_TIMESTAMP + 15, ticker(2.0))
+            .unwrap();
+        assert_eq!(ticker_store.get_ticker_count(), 2);
+        assert_eq!(ticker_store.gap_report().gap_count(), 0);
+        assert_eq!(ticker_store.validity_mask(), &[true, true]);
+    }
+
+    #[test]
+    fn test_insert_ticker_forward_fill() {
+        let mut ticker_store =
+            TickerStore::with_gap_policy(15, START_TIMESTAMP, GapPolicy::ForwardFill);
+        ticker_store
+            .insert_ticker(START_TIMESTAMP, ticker(1.0))
+            .unwrap();
+        ticker_store
+            .insert_ticker(START_TIMESTAMP + 45, ticker(4.0))
+            .unwrap();
+        assert_eq!(ticker_store.get_ticker_count(), 4);
+        //later timestamps still land on the right ticker
+        assert_eq!(ticker_store.get_ticker(START_TIMESTAMP + 45).high, 4.0);
+        assert_eq!(ticker_store.get_ticker(START_TIMESTAMP + 15).high, 1.0);
+        assert_eq!(ticker_store.get_ticker(START_TIMESTAMP + 30).high, 1.0);
+        assert_eq!(ticker_store.validity_mask(), &[true, false, false, true]);
+    }
+
+    #[test]
+    fn test_insert_ticker_lerp_fill() {
+        let mut ticker_store =
+            TickerStore::with_gap_policy(15, START_TIMESTAMP, GapPolicy::LerpFill);
+        ticker_store
+            .insert_ticker(START_TIMESTAMP, ticker(1.0))
+            .unwrap();
+        ticker_store
+            .insert_ticker(START_TIMESTAMP + 45, ticker(4.0))
+            .unwrap();
+        assert_eq!(ticker_store.get_ticker(START_TIMESTAMP + 15).high, 2.0);
+        assert_eq!(ticker_store.get_ticker(START_TIMESTAMP + 30).high, 3.0);
+    }
+
+    #[test]
+    fn test_insert_ticker_mark_missing() {
+        let mut ticker_store =
+            TickerStore::with_gap_policy(15, START_TIMESTAMP, GapPolicy::MarkMissing);
+        ticker_store
+            .insert_ticker(START_TIMESTAMP + 15, ticker(2.0))
+            .unwrap();
+        assert_eq!(ticker_store.get_ticker_count(), 2);
+        assert!(ticker_store.get_ticker(START_TIMESTAMP).high.is_nan());
+        assert!(!ticker_store.is_valid(0));
+        assert!(ticker_store.is_valid(1));
+    }
+
+    #[test]
+    fn test_insert_ticker_errors() {
+        let mut ticker_store = TickerStore::with_gap_policy(15, START_TIMESTAMP, GapPolicy::Reject);
+        ticker_store
+            .insert_ticker(START_TIMESTAMP, ticker(1.0))
+            .unwrap();
+        assert_eq!(
+            ticker_store.insert_ticker(START_TIMESTAMP + 45, ticker(4.0)),
+            Err(TickerStoreError::Gap {
+                timestamp: START_TIMESTAMP + 45,
+                missing: 2
+            })
+        );
+        assert_eq!(
+            ticker_store.insert_ticker(START_TIMESTAMP + 20, ticker(4.0)),
+            Err(TickerStoreError::MisalignedTimestamp {
+                timestamp: START_TIMESTAMP + 20
+            })
+        );
+        assert_eq!(
+            ticker_store.insert_ticker(START_TIMESTAMP, ticker(4.0)),
+            Err(TickerStoreError::OutOfOrder {
+                timestamp: START_TIMESTAMP,
+                last_timestamp: START_TIMESTAMP
+            })
+        );
+        assert_eq!(ticker_store.get_ticker_count(), 1);
+    }
+
+    #[test]
+    fn test_gap_report() {
+        let mut ticker_store = TickerStore::new(15, START_TIMESTAMP);
+        for i in [0, 1, 4, 5, 9] {
+            ticker_store
+                .insert_ticker(START_TIMESTAMP + 15 * i, ticker(1.0))
+                .unwrap();
+        }
+        let report = ticker_store.gap_report();
+        assert_eq!(
+            report.gaps,
+            vec![
+                Gap {
+                    start_index: 2,
+                    length: 2
+                },
+                Gap {
+                    start_index: 6,
+                    length: 3
+                }
+            ]
+        );
+        assert_eq!(report.missing_count, 5);
+        assert_eq!(report.longest_gap, 3);
+        assert_eq!(report.interval_count, 10);
+        assert_eq!(report.coverage(), 0.5);
+    }
+
+    #[test]
+    fn test_detect_gaps() {
+        let timestamps: Vec<u64> = [0, 1, 4, 5, 9]
+            .iter()
+            .map(|i| START_TIMESTAMP + 15 * i)
+            .collect();
+        let report = detect_gaps(&timestamps, 15);
+        assert_eq!(report, {
+            let mut ticker_store = TickerStore::new(15, START_TIMESTAMP);
+            for timestamp in &timestamps {
+                ticker_store.insert_ticker(*timestamp, ticker(1.0)).unwrap();
+            }
+            ticker_store.gap_report()
+        });
+        assert_eq!(detect_gaps(&[], 15), GapReport::default());
+    }
+
+    #[test]
+    fn test_sample_empty_store() {
+        let ticker_store = TickerStore::new(15, START_TIMESTAMP);
+        for mode in MODES {
+            assert_eq!(ticker_store.sample(START_TIMESTAMP, mode), None);
+            assert_eq!(ticker_store.sample(0, mode), None);
+        }
+        assert_eq!(ticker_store.timestamp_to_index(START_TIMESTAMP + 100), 0);
+        assert_eq!(
+            ticker_store.timestamp_to_float_index(START_TIMESTAMP + 100),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_sample_single_ticker() {
+        let mut ticker_store = TickerStore::new(15, START_TIMESTAMP);
+        ticker_store.add_ticker(ticker(1.0));
+        for mode in MODES {
+            for timestamp in [0, START_TIMESTAMP, START_TIMESTAMP + 7, u64::MAX] {
+                assert_eq!(ticker_store.sample(timestamp, mode), Some(ticker(1.0)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_modes() {
+        let mut ticker_store = TickerStore::new(10, START_TIMESTAMP);
+        ticker_store.add_ticker(ticker(1.0));
+        ticker_store.add_ticker(ticker(2.0));
+        ticker_store.add_ticker(ticker(4.0));
+        let high = |timestamp, mode| ticker_store.sample(timestamp, mode).unwrap().high;
+
+        assert_eq!(high(START_TIMESTAMP + 14, InterpolationMode::Floor), 2.0);
+        assert_eq!(high(START_TIMESTAMP + 14, InterpolationMode::Ceil), 4.0);
+        assert_eq!(high(START_TIMESTAMP + 14, InterpolationMode::Nearest), 2.0);
+        assert_eq!(high(START_TIMESTAMP + 15, InterpolationMode::Nearest), 4.0);
+        assert_eq!(high(START_TIMESTAMP + 15, InterpolationMode::Linear), 3.0);
+        assert_eq!(high(START_TIMESTAMP + 10, InterpolationMode::Ceil), 2.0);
+
+        //out of range clamps to the ends
+        assert_eq!(high(START_TIMESTAMP - 5, InterpolationMode::Ceil), 1.0);
+        assert_eq!(high(START_TIMESTAMP + 25, InterpolationMode::Floor), 4.0);
+        assert_eq!(high(START_TIMESTAMP + 1000, InterpolationMode::Linear), 4.0);
+    }
+
+    const MODES: [InterpolationMode; 4] = [
+        InterpolationMode::Nearest,
+        InterpolationMode::Floor,
+        InterpolationMode::Ceil,
+        InterpolationMode::Linear,
+    ];
+
+    fn store_from(ticker_size: u64, highs: &[f32]) -> TickerStore {
+        let mut ticker_store = TickerStore::new(ticker_size, START_TIMESTAMP);
+        for high in highs {
+            ticker_store.add_ticker(ticker(*high));
+        }
+        ticker_store
+    }
+
+    proptest! {
+        #[test]
+        fn prop_sample_never_panics(
+            ticker_size in 1u64..1000,
+            highs in prop::collection::vec(-1000.0f32..1000.0, 0..20),
+            timestamp in any::<u64>(),
+            mode_index in 0usize..4,
+        ) {
+            let ticker_store = store_from(ticker_size, &highs);
+            let sample = ticker_store.sample(timestamp, MODES[mode_index]);
+            prop_assert_eq!(sample.is_none(), highs.is_empty());
+        }
+
+        #[test]
+        fn prop_sample_on_grid_is_exact(
+            ticker_size in 1u64..1000,
+            highs in prop::collection::vec(-1000.0f32..1000.0, 1..20),
+            index in 0usize..20,
+            mode_index in 0usize..4,
+        ) {
+            let ticker_store = store_from(ticker_size, &highs);
+            let index = index % highs.len();
+            let timestamp = START_TIMESTAMP + ticker_size * index as u64;
+            prop_assert_eq!(ticker_store.sample(timestamp, MODES[mode_index]), Some(ticker(highs[index])));
+        }
+
+        #[test]
+        fn prop_sample_between_neighbours(
+            ticker_size in 2u64..1000,
+            highs in prop::collection::vec(-1000.0f32..1000.0, 2..20),
+            offset in any::<u64>(),
+        ) {
+            let ticker_store = store_from(ticker_size, &highs);
+            let offset = offset % (ticker_size * (highs.len() as u64 - 1));
+            let timestamp = START_TIMESTAMP + offset;
+            let floor = ticker_store.sample(timestamp, InterpolationMode::Floor).unwrap().high;
+            let ceil = ticker_store.sample(timestamp, InterpolationMode::Ceil).unwrap().high;
+            let nearest = ticker_store.sample(timestamp, InterpolationMode::Nearest).unwrap().high;
+            let linear = ticker_store.sample(timestamp, InterpolationMode::Linear).unwrap().high;
+
+            prop_assert_eq!(floor, highs[(offset / ticker_size) as usize]);
+            prop_assert!(nearest == floor || nearest == ceil);
+            let tolerance = 1e-3;
+            prop_assert!(linear >= floor.min(ceil) - tolerance);
+            prop_assert!(linear <= floor.max(ceil) + tolerance);
+        }
+
+        #[test]
+        fn prop_sample_out_of_range_clamps(
+            ticker_size in 1u64..1000,
+            highs in prop::collection::vec(-1000.0f32..1000.0, 1..20),
+            before in 1u64..START_TIMESTAMP,
+            after in any::<u32>(),
+            mode_index in 0usize..4,
+        ) {
+            let ticker_store = store_from(ticker_size, &highs);
+            let mode = MODES[mode_index];
+            let last_timestamp = START_TIMESTAMP + ticker_size * (highs.len() as u64 - 1);
+            prop_assert_eq!(ticker_store.sample(START_TIMESTAMP - before, mode), Some(ticker(highs[0])));
+            prop_assert_eq!(
+                ticker_store.sample(last_timestamp + after as u64, mode),
+                Some(ticker(highs[highs.len() - 1]))
+            );
+        }
+    }
 }
@@ -0,0 +1,614 @@
+use crate::lib::op::environment::Env;
+use crate::lib::op::operand::*;
+use crate::lib::op::operation::boolean::BoolOperator;
+use crate::lib::op::operation::constant::ConstantOperator;
+use crate::lib::op::operation::index::IndexOperator;
+use crate::lib::op::operation::indicator::IndicatorOutput;
+use crate::lib::op::operation::list_window::ListWindowOperator;
+use crate::lib::op::operation::num_pick::NumPickOperator;
+use crate::lib::op::operation::number::NumOperator;
+use crate::lib::op::operation::rolling::RollingOperator;
+use crate::lib::op::operation::trade::TradeList;
+use crate::lib::op::operation::*;
+use crate::lib::op::terminal_type::*;
+use std::fmt::Display;
+
+///The static type of a value, one per TerminalType variant
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ValueType {
+    Number,
+    NumberList,
+    Bool,
+    Int,
+    Timestamp,
+    MarketIndex,
+}
+
+impl Display for ValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ValueType::Number => write!(f, "Number"),
+            ValueType::NumberList => write!(f, "NumberList"),
+            ValueType::Bool => write!(f, "Bool"),
+            ValueType::Int => write!(f, "Int"),
+            ValueType::Timestamp => write!(f, "Timestamp"),
+            ValueType::MarketIndex => write!(f, "MarketIndex"),
+        }
+    }
+}
+
+impl TerminalType {
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            TerminalType::Number(_) => ValueType::Number,
+            TerminalType::NumberList(_) => ValueType::NumberList,
+            TerminalType::Bool(_) => ValueType::Bool,
+            TerminalType::Int(_) => ValueType::Int,
+            TerminalType::Timestamp(_) => ValueType::Timestamp,
+            TerminalType::MarketIndex(_) => ValueType::MarketIndex,
+        }
+    }
+}
+
+///prices, amounts and other plain quantities
+pub const NUMBER: &[ValueType] = &[ValueType::Number, ValueType::Int];
+///NumOperators broadcast over lists
+pub const NUMERIC: &[ValueType] = &[ValueType::Number, ValueType::Int, ValueType::NumberList];
+///Add and Subtract also move a Timestamp
+pub const TIME_NUMERIC: &[ValueType] = &[
+    ValueType::Number,
+    ValueType::Int,
+    ValueType::NumberList,
+    ValueType::Timestamp,
+];
+///what can be subtracted from a Timestamp
+pub const DURATION_OR_TIMESTAMP: &[ValueType] = &[ValueType::Int, ValueType::Timestamp];
+pub const LIST: &[ValueType] = &[ValueType::NumberList];
+pub const BOOL: &[ValueType] = &[ValueType::Bool];
+///periods, windows, depths, positions and durations in milliseconds,
+/// negative durations look back
+pub const COUNT: &[ValueType] = &[ValueType::Int];
+pub const TIMESTAMP: &[ValueType] = &[ValueType::Timestamp];
+pub const MARKET_INDEX: &[ValueType] = &[ValueType::MarketIndex];
+///values the comparison operators accept
+pub const SCALAR: &[ValueType] = &[
+    ValueType::Number,
+    ValueType::Int,
+    ValueType::Bool,
+    ValueType::Timestamp,
+    ValueType::MarketIndex,
+];
+///operands that are ignored or passed through untouched
+pub const ANY: &[ValueType] = &[
+    ValueType::Number,
+    ValueType::NumberList,
+    ValueType::Bool,
+    ValueType::Int,
+    ValueType::Timestamp,
+    ValueType::MarketIndex,
+];
+
+///the types a value of value_type can be compared with or swapped for
+pub fn same_family(value_type: ValueType) -> &'static [ValueType] {
+    match value_type {
+        ValueType::Number | ValueType::Int => NUMBER,
+        ValueType::NumberList => LIST,
+        ValueType::Bool => BOOL,
+        ValueType::Timestamp => TIMESTAMP,
+        ValueType::MarketIndex => MARKET_INDEX,
+    }
+}
+
+///An operand position of an operation and the types it accepts
+pub struct Slot<'a> {
+    pub operand: &'a Operand,
+    pub accepts: &'static [ValueType],
+    ///Operand::None is evaluated to the context here, e.g. the list element a Map is called for
+    pub context: bool,
+}
+
+impl<'a> Slot<'a> {
+    fn new(operand: &'a Operand, accepts: &'static [ValueType]) -> Slot<'a> {
+        Slot {
+            operand,
+            accepts,
+            context: false,
+        }
+    }
+
+    fn context(operand: &'a Operand, accepts: &'static [ValueType]) -> Slot<'a> {
+        Slot {
+            operand,
+            accepts,
+            context: true,
+        }
+    }
+}
+
+///Every operand of an operation in evaluation order, operand_type gives the static type
+/// of an operand and is needed where one operand's type decides what the next accepts
+pub fn slots<'a>(
+    operation: &'a Operation,
+    operand_type: &impl Fn(&Operand) -> ValueType,
+) -> Vec<Slot<'a>> {
+    match operation {
+        Operation::Branch((condition, left, right)) => vec![
+            Slot::new(condition, BOOL),
+            Slot::new(left, ANY),
+            Slot::new(right, same_family(operand_type(left))),
+        ],
+        Operation::Bool((operator, left, right)) => match operator {
+            BoolOperator::And | BoolOperator::Or | BoolOperator::Xor => {
+                vec![Slot::new(left, BOOL), Slot::new(right, BOOL)]
+            }
+            BoolOperator::Not => vec![Slot::new(left, BOOL), Slot::new(right, ANY)],
+            _ => vec![
+                Slot::new(left, SCALAR),
+                Slot::new(right, same_family(operand_type(left))),
+            ],
+        },
+        Operation::Trade((_, market_index, price, amount, _, _, stop_price, _)) => vec![
+            Slot::new(market_index, MARKET_INDEX),
+            Slot::new(price, NUMBER),
+            Slot::new(amount, NUMBER),
+            Slot::new(stop_price, NUMBER),
+        ],
+        Operation::MarketData((_, market_index, timestamp_start, duration)) => vec![
+            Slot::context(market_index, MARKET_INDEX),
+            Slot::new(timestamp_start, TIMESTAMP),
+            Slot::new(duration, COUNT),
+        ],
+        Operation::NumPick((operator, list, quantile)) => {
+            let mut slots = vec![Slot::new(list, LIST)];
+            if let NumPickOperator::Quantile = operator {
+                slots.push(Slot::new(quantile, NUMBER));
+            }
+            slots
+        }
+        Operation::Number((operator, left, right)) => {
+            let moves_time = matches!(operator, NumOperator::Add | NumOperator::Subtract);
+            let right_accepts = match (operator, operand_type(left)) {
+                (NumOperator::Add, ValueType::Timestamp) => COUNT,
+                (NumOperator::Subtract, ValueType::Timestamp) => DURATION_OR_TIMESTAMP,
+                _ if operator.is_unary() => NUMBER,
+                _ => NUMERIC,
+            };
+            vec![
+                Slot::new(left, if moves_time { TIME_NUMERIC } else { NUMERIC }),
+                Slot::new(right, right_accepts),
+            ]
+        }
+        Operation::Constant((operator, operand)) => match operator {
+            ConstantOperator::MarketPrice
+            | ConstantOperator::SelectedMarketPortfolioValue
+            | ConstantOperator::SelectedMarketListingTimestampMs => {
+                vec![Slot::new(operand, MARKET_INDEX)]
+            }
+            _ => vec![Slot::new(operand, ANY)],
+        },
+        Operation::Index((operator, list)) => {
+            let mut slots = vec![Slot::new(list, LIST)];
+            if let IndexOperator::Operand(index) = operator {
+                slots.push(Slot::new(index, COUNT));
+            }
+            slots
+        }
+        Operation::Identity(operand) => vec![Slot::new(operand, ANY)],
+        Operation::MarketSort((key,)) => vec![Slot::context(key, NUMBER)],
+        Operation::OrderBook((_, market_index, timestamp, depth)) => vec![
+            Slot::context(market_index, MARKET_INDEX),
+            Slot::new(timestamp, TIMESTAMP),
+            Slot::new(depth, COUNT),
+        ],
+        Operation::ListSort((_, _, list, key)) => {
+            vec![Slot::new(list, LIST), Slot::context(key, NUMBER)]
+        }
+        Operation::Map((list, expression)) => {
+            vec![Slot::new(list, LIST), Slot::context(expression, NUMBER)]
+        }
+        Operation::Filter((list, expression)) => {
+            vec![Slot::new(list, LIST), Slot::context(expression, BOOL)]
+        }
+        Operation::Zip((_, left, right)) => vec![Slot::new(left, LIST), Slot::new(right, LIST)],
+        Operation::ListWindow((operator, list, count, end)) => {
+            let mut slots = vec![Slot::new(list, LIST), Slot::new(count, COUNT)];
+            if let ListWindowOperator::Slice = operator {
+                slots.push(Slot::new(end, COUNT));
+            }
+            slots
+        }
+        Operation::Indicator((_, _, market_index, timestamp_start, duration, period)) => vec![
+            Slot::context(market_index, MARKET_INDEX),
+            Slot::new(timestamp_start, TIMESTAMP),
+            Slot::new(duration, COUNT),
+            Slot::new(period, COUNT),
+        ],
+        Operation::Rolling((operator, list, window, argument)) => {
+            let mut slots = vec![Slot::new(list, LIST), Slot::new(window, COUNT)];
+            match operator {
+                RollingOperator::Quantile => slots.push(Slot::new(argument, NUMBER)),
+                RollingOperator::Correlation => slots.push(Slot::new(argument, LIST)),
+                _ => {}
+            }
+            slots
+        }
+    }
+}
+
+///the type evaluate returns for operation
+pub fn output_type(
+    operation: &Operation,
+    operand_type: &impl Fn(&Operand) -> ValueType,
+) -> ValueType {
+    match operation {
+        Operation::Branch((_, left, right)) => {
+            match (operand_type(left), operand_type(right)) {
+                //an Int arm mixed with a Number arm is read as a Number
+                (ValueType::Int, ValueType::Number) => ValueType::Number,
+                (left, _) => left,
+            }
+        }
+        Operation::Bool(_) => ValueType::Bool,
+        Operation::Trade(_) | Operation::NumPick(_) | Operation::Index(_) => ValueType::Number,
+        Operation::Number((operator, left, right)) => {
+            let (left, right) = (operand_type(left), operand_type(right));
+            let list_operand = left == ValueType::NumberList
+                || (!operator.is_unary() && right == ValueType::NumberList);
+            if list_operand {
+                ValueType::NumberList
+            } else {
+                operator
+                    .exact_type(left, right)
+                    .unwrap_or(ValueType::Number)
+            }
+        }
+        Operation::Constant((operator, _)) => match operator {
+            ConstantOperator::BtcMarketIndex
+            | ConstantOperator::EthMarketIndex
+            | ConstantOperator::USDTMarketIndex => ValueType::MarketIndex,
+            ConstantOperator::CurrentTimestampMs
+            | ConstantOperator::SelectedMarketListingTimestampMs => ValueType::Timestamp,
+            _ => ValueType::Number,
+        },
+        Operation::Identity(operand) => operand_type(operand),
+        Operation::Indicator((_, IndicatorOutput::Latest, ..)) => ValueType::Number,
+        Operation::Indicator((_, IndicatorOutput::Series, ..))
+        | Operation::MarketData(_)
+        | Operation::MarketSort(_)
+        | Operation::OrderBook(_)
+        | Operation::ListSort(_)
+        | Operation::Map(_)
+        | Operation::Filter(_)
+        | Operation::Zip(_)
+        | Operation::ListWindow(_)
+        | Operation::Rolling(_) => ValueType::NumberList,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeErrorKind {
+    Mismatch {
+        expected: Vec<ValueType>,
+        found: ValueType,
+    },
+    ///pointers must point to an earlier instruction, anything else can recurse forever
+    ForwardPointer { pointer: usize },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeError {
+    pub instruction: usize,
+    ///position of the operand in `slots`
+    pub operand: usize,
+    pub kind: TypeErrorKind,
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "instruction {} operand {}: ",
+            self.instruction, self.operand
+        )?;
+        match &self.kind {
+            TypeErrorKind::Mismatch { expected, found } => {
+                let expected: Vec<String> = expected.iter().map(ValueType::to_string).collect();
+                write!(f, "expected {}, found {}", expected.join(" or "), found)
+            }
+            TypeErrorKind::ForwardPointer { pointer } => {
+                write!(f, "points forward to instruction {}", pointer)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TypeReport {
+    ///the inferred output type of every instruction
+    pub output_types: Vec<ValueType>,
+    pub errors: Vec<TypeError>,
+}
+
+impl TypeReport {
+    pub fn is_well_typed(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+///Infers the output type of every instruction and flags every operand whose type
+/// its slot doesn't accept. Instructions with errors still get a best effort type
+/// so one mistake isn't reported again by everything that uses it.
+pub fn check_program(operation_list: &OperationList) -> TypeReport {
+    let mut report = TypeReport::default();
+    for (instruction, operation) in operation_list.iter().enumerate() {
+        let known_types = &report.output_types;
+        let operand_type = |operand: &Operand| match operand {
+            Operand::Pointer(pointer) => known_types
+                .get(*pointer)
+                .copied()
+                .unwrap_or(ValueType::Number),
+            Operand::Terminal(terminal) => terminal.value_type(),
+            Operand::None => ValueType::Number,
+        };
+
+        for (position, slot) in slots(operation, &operand_type).iter().enumerate() {
+            let kind = match slot.operand {
+                Operand::Pointer(pointer) if *pointer >= instruction => {
+                    Some(TypeErrorKind::ForwardPointer { pointer: *pointer })
+                }
+                Operand::None if slot.context => None,
+                operand if !slot.accepts.contains(&operand_type(operand)) => {
+                    Some(TypeErrorKind::Mismatch {
+                        expected: slot.accepts.to_vec(),
+                        found: operand_type(operand),
+                    })
+                }
+                _ => None,
+            };
+            if let Some(kind) = kind {
+                report.errors.push(TypeError {
+                    instruction,
+                    operand: position,
+                    kind,
+                });
+            }
+        }
+        let output = output_type(operation, &operand_type);
+        report.output_types.push(output);
+    }
+    report
+}
+
+///which instructions evaluating instruction output can reach through pointers
+pub fn reachable(operation_list: &OperationList, output: usize) -> Vec<bool> {
+    let mut reached = vec![false; operation_list.len()];
+    let mut pending = vec![output];
+    while let Some(instruction) = pending.pop() {
+        if instruction >= operation_list.len() || reached[instruction] {
+            continue;
+        }
+        reached[instruction] = true;
+        for slot in slots(&operation_list[instruction], &|_| ValueType::Number) {
+            if let Operand::Pointer(pointer) = slot.operand {
+                pending.push(*pointer);
+            }
+        }
+    }
+    reached
+}
+
+///Strict evaluation of the last instruction, the instructions it depends on are type
+/// checked first and any mismatch is returned instead of being silently coerced.
+/// An empty program evaluates to 0 like Operand::None.
+pub fn evaluate_strict(
+    operation_list: &OperationList,
+    trade_list: &mut TradeList,
+    context: &Context,
+    env: &impl Env,
+) -> Result<TerminalType, Vec<TypeError>> {
+    let output = match operation_list.len().checked_sub(1) {
+        Some(output) => output,
+        None => return Ok(TerminalType::Number(0.0)),
+    };
+    let reached = reachable(operation_list, output);
+    let errors: Vec<TypeError> = check_program(operation_list)
+        .errors
+        .into_iter()
+        .filter(|error| reached[error.instruction])
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(operation_list[output].evaluate(operation_list, trade_list, context, env))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::op::operation::market_data::MarketDataOperator;
+    use crate::lib::op::operation::num_pick::NumPickOperator;
+
+    struct DefaultEnv {}
+    impl Env for DefaultEnv {}
+
+    fn number(value: f32) -> Operand {
+        Operand::Terminal(TerminalType::Number(value))
+    }
+
+    ///average close of the BTC market over the last minute
+    fn average_close() -> OperationList {
+        vec![
+            Operation::Constant((ConstantOperator::BtcMarketIndex, Operand::None)),
+            Operation::Constant((ConstantOperator::CurrentTimestampMs, Operand::None)),
+            Operation::MarketData((
+                MarketDataOperator::Close,
+                Operand::Pointer(0),
+                Operand::Pointer(1),
+                Operand::Terminal(TerminalType::Int(-60_000)),
+            )),
+            Operation::NumPick((NumPickOperator::Average, Operand::Pointer(2), Operand::None)),
+        ]
+    }
+
+    #[test]
+    fn test_infers_output_types() {
+        let mut operation_list = average_close();
+        operation_list.push(Operation::Number((
+            NumOperator::Multiply,
+            Operand::Pointer(2),
+            number(2.0),
+        )));
+        operation_list.push(Operation::Bool((
+            BoolOperator::GreaterThan,
+            Operand::Pointer(3),
+            number(2.0),
+        )));
+        let report = check_program(&operation_list);
+        assert!(report.is_well_typed(), "{:?}", report.errors);
+        assert_eq!(
+            report.output_types,
+            vec![
+                ValueType::MarketIndex,
+                ValueType::Timestamp,
+                ValueType::NumberList,
+                ValueType::Number,
+                ValueType::NumberList,
+                ValueType::Bool,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_timestamp_arithmetic() {
+        let operation_list = vec![
+            Operation::Constant((ConstantOperator::CurrentTimestampMs, Operand::None)),
+            //an hour ago
+            Operation::Number((
+                NumOperator::Subtract,
+                Operand::Pointer(0),
+                Operand::Terminal(TerminalType::Int(3_600_000)),
+            )),
+            Operation::Number((
+                NumOperator::Subtract,
+                Operand::Pointer(0),
+                Operand::Pointer(1),
+            )),
+            Operation::Number((NumOperator::Multiply, Operand::Pointer(0), number(2.0))),
+        ];
+        let report = check_program(&operation_list);
+        assert_eq!(
+            report.output_types[..3],
+            [ValueType::Timestamp, ValueType::Timestamp, ValueType::Int]
+        );
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].instruction, 3);
+    }
+
+    #[test]
+    fn test_flags_ill_typed_operands() {
+        let operation_list = vec![
+            Operation::Constant((ConstantOperator::One, Operand::None)),
+            //a number used as a list
+            Operation::NumPick((NumPickOperator::Max, Operand::Pointer(0), Operand::None)),
+            //a list length used as a price
+            Operation::Constant((
+                ConstantOperator::MarketPrice,
+                Operand::Terminal(TerminalType::NumberList(vec![1.0])),
+            )),
+            Operation::Branch((Operand::Pointer(0), Operand::Pointer(3), number(1.0))),
+        ];
+        let report = check_program(&operation_list);
+        assert_eq!(
+            report.errors,
+            vec![
+                TypeError {
+                    instruction: 1,
+                    operand: 0,
+                    kind: TypeErrorKind::Mismatch {
+                        expected: vec![ValueType::NumberList],
+                        found: ValueType::Number,
+                    },
+                },
+                TypeError {
+                    instruction: 2,
+                    operand: 0,
+                    kind: TypeErrorKind::Mismatch {
+                        expected: vec![ValueType::MarketIndex],
+                        found: ValueType::NumberList,
+                    },
+                },
+                TypeError {
+                    instruction: 3,
+                    operand: 0,
+                    kind: TypeErrorKind::Mismatch {
+                        expected: vec![ValueType::Bool],
+                        found: ValueType::Number,
+                    },
+                },
+                TypeError {
+                    instruction: 3,
+                    operand: 1,
+                    kind: TypeErrorKind::ForwardPointer { pointer: 3 },
+                },
+            ]
+        );
+        assert_eq!(
+            report.errors[1].to_string(),
+            "instruction 2 operand 0: expected MarketIndex, found NumberList"
+        );
+    }
+
+    #[test]
+    fn test_context_slots_accept_none() {
+        let operation_list = vec![
+            Operation::Identity(Operand::Terminal(TerminalType::NumberList(vec![1.0, 2.0]))),
+            Operation::Map((Operand::Pointer(0), Operand::None)),
+            Operation::MarketData((
+                MarketDataOperator::Close,
+                Operand::None,
+                Operand::Terminal(TerminalType::Timestamp(0)),
+                Operand::Terminal(TerminalType::Int(0)),
+            )),
+        ];
+        assert!(check_program(&operation_list).is_well_typed());
+    }
+
+    #[test]
+    fn test_evaluate_strict() {
+        let env = DefaultEnv {};
+        let mut trade_list = TradeList::new();
+        assert_eq!(
+            evaluate_strict(&average_close(), &mut trade_list, &None, &env).unwrap(),
+            TerminalType::Number(3.0)
+        );
+
+        //the lenient evaluation reads the timestamp as a number of milliseconds
+        let mut operation_list = average_close();
+        operation_list[2] = Operation::MarketData((
+            MarketDataOperator::Close,
+            Operand::Pointer(0),
+            number(0.0),
+            Operand::Terminal(TerminalType::Int(-60_000)),
+        ));
+        assert_eq!(
+            operation_list[3].evaluate(&operation_list, &mut trade_list, &None, &env),
+            TerminalType::Number(3.0)
+        );
+        let errors = evaluate_strict(&operation_list, &mut trade_list, &None, &env).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].instruction, 2);
+
+        //errors in instructions the output doesn't use are ignored
+        operation_list.push(Operation::Constant((ConstantOperator::Two, Operand::None)));
+        assert_eq!(
+            evaluate_strict(&operation_list, &mut trade_list, &None, &env).unwrap(),
+            TerminalType::Number(2.0)
+        );
+        assert_eq!(
+            evaluate_strict(&OperationList::new(), &mut trade_list, &None, &env).unwrap(),
+            TerminalType::Number(0.0)
+        );
+    }
+}
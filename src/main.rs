@@ -1,46 +1,35 @@
 mod lib;
-use barter::data::handler::{
-    historical::{HistoricalCandleHandler, HistoricalDataLego},
-    Continuation, Continuer, MarketGenerator,
-};
-use chrono::{TimeZone, Utc};
-use std::fs::File;
-use csv_candle_iterator::CSVCandleData;
-use barter_data::model::{Candle, MarketData};
+use barter::data::handler::historical::{HistoricalCandleHandler, HistoricalDataLego};
+use lib::backtest::runner::{Backtest, BacktestConfig};
+use lib::data::importer::{CandleImporter, CandleProfile, ImportedCandle, RowErrorPolicy};
+use lib::data::ingest::{ingest_directory, IngestOptions};
+use lib::op::generator::ProgramGenerator;
+use lib::op::type_check::ValueType;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("ingest") {
+        match (args.get(2), args.get(3)) {
+            (Some(source_dir), Some(dataset_dir)) => ingest(source_dir, dataset_dir),
+            _ => eprintln!("usage: {} ingest <source_dir> <dataset_dir>", args[0]),
+        }
+        return;
+    }
 
-    let mut rdr = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .from_reader(File::open("src/data/1inch.csv").expect("file not found"));
-
-    let candle_iterator = rdr.deserialize().map(|result| {
-        let (
-                open_time,
-                open,
-                high,
-                low,
-                close,
-                volume,
-                close_time,
-                _,
-                number_of_trades,
-                _,
-                _,
-                _,
-            ): CSVCandleData = result.unwrap_or_default();
+    let import = CandleImporter::new(CandleProfile::binance_klines(), RowErrorPolicy::Lenient)
+        .read_path("src/data/1inch.csv")
+        .expect("could not import candles");
+    for error in &import.errors {
+        eprintln!("skipped row, {}", error);
+    }
 
-        Candle {
-            close,
-            high,
-            low,
-            open,
-            start_timestamp: Utc.timestamp_millis(open_time as i64),
-            end_timestamp: Utc.timestamp_millis(close_time as i64),
-            volume: volume as f64,
-            trade_count: number_of_trades,
-        }
-    });
+    let config = BacktestConfig {
+        ticker_size_ms: import.infer_interval_ms().unwrap_or(60_000),
+        ..BacktestConfig::default()
+    };
+    let candle_iterator = import.candles.into_iter().map(ImportedCandle::to_candle);
 
     let lego = HistoricalDataLego {
         exchange: "Binance",
@@ -49,60 +38,60 @@ fn main() {
     };
 
     let mut data = HistoricalCandleHandler::new(lego);
-    
-    loop {
-        let market_event = match data.can_continue() {
-            Continuation::Continue => match data.generate_market() {
-                Some(market_event) => market_event,
-                None => continue,
-            },
-            Continuation::Stop => {
-                break;
-            }
-        };
 
-        match market_event.data {
-            MarketData::Candle(candle) => {
-                println!("{}", candle.close);
-            }
-            MarketData::Trade(trade) => {
-                println!("{}", trade.price);
-            }
-        }
+    //a fixed seed keeps the program and so the whole run reproducible
+    let mut rng = StdRng::seed_from_u64(0);
+    let program = ProgramGenerator::default().generate(&mut rng, ValueType::Number);
+    let backtest = Backtest::new(config, vec!["1inchbtc".to_string()]);
+    let report = backtest.run(&program, &mut data);
+
+    for point in &report.equity_curve {
+        println!("{} {}", point.timestamp_ms, point.equity);
+    }
+    for position in report.journal.positions() {
+        println!(
+            "{} {} entry {} exit {} net {}",
+            position.id,
+            position.side,
+            position.entry_price,
+            position.exit_price,
+            position.net_pnl()
+        );
     }
+    for event in &report.risk_events {
+        println!("{}", event);
+    }
+    println!(
+        "{} fills, {} liquidations, return {:.4}",
+        report.fills.len(),
+        report.liquidations.len(),
+        report.total_return()
+    );
 }
 
-mod csv_candle_iterator {
-    
-    type Timestamp = u64;
-    type Opentime = Timestamp;
-    type Closetime = Timestamp;
-    type Open = f64;
-    type High = f64;
-    type Low = f64;
-    type Close = f64;
-    type Volume = f64;
-    type TradeCount = u64;
-    type TakerBuyBaseAssetVolume = f64;
-    type QuoteAssetVolume = f64;
-    type TakerBuyQuoteAssetVolume = f64;
-    type Ignore = u32;
-
-    pub type CSVCandleData = (
-        Opentime,
-        Open,
-        High,
-        Low,
-        Close,
-        Volume,
-        Closetime,
-        QuoteAssetVolume,
-        TradeCount,
-        TakerBuyBaseAssetVolume,
-        TakerBuyQuoteAssetVolume,
-        Ignore,
-    );
-    // type CSVCandleIterator = std::vec::IntoIter<CSVCandleData>;
+fn ingest(source_dir: &str, dataset_dir: &str) {
+    let report = ingest_directory(source_dir, dataset_dir, &IngestOptions::default())
+        .expect("could not ingest kline archives");
+    for (path, error) in &report.skipped_rows {
+        eprintln!("skipped row in {}, {}", path.display(), error);
+    }
+    for path in &report.ignored_files {
+        eprintln!("ignored {}", path.display());
+    }
+    for (market, error) in &report.failed_markets {
+        eprintln!("could not ingest {}, {}", market, error);
+    }
+    for entry in &report.manifest.markets {
+        println!(
+            "{} {}: {} candles, {} duplicates, {} gaps ({} missing intervals)",
+            entry.symbol,
+            entry.interval,
+            entry.candle_count,
+            entry.duplicate_count,
+            entry.gaps.gap_count,
+            entry.gaps.missing_count
+        );
+    }
 }
 
 // pub struct TestHistoricDataLego<T: Iterator<Item = Candle>> {